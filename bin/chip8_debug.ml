@@ -0,0 +1,139 @@
+open! Core
+open Hardcaml_risc_v_test
+module Request = Chip8_debug_protocol.Request
+module Reply = Chip8_debug_protocol.Reply
+
+(** Read the payload of the next DMA packet from the device, skipping anything
+    before the header. *)
+let rec read_packet reader =
+  match In_channel.input_char reader with
+  | None -> raise_s [%message "Device closed"]
+  | Some 'D' ->
+    let length_msb = In_channel.input_byte reader |> Option.value_exn in
+    let length_lsb = In_channel.input_byte reader |> Option.value_exn in
+    let length = (length_msb lsl 8) lor length_lsb in
+    let buffer = Bytes.create length in
+    In_channel.really_input_exn reader ~buf:buffer ~pos:0 ~len:length;
+    Bytes.to_string buffer
+  | Some _ -> read_packet reader
+;;
+
+(** Wait for a debugger reply matching [f], printing any log messages the
+    device sends in the meantime. *)
+let rec wait_for_reply reader ~f =
+  let packet = read_packet reader in
+  match Reply.of_packet packet with
+  | Some reply when f reply -> reply
+  | Some reply ->
+    print_s [%message "Unexpected reply" (reply : Reply.t)];
+    wait_for_reply reader ~f
+  | None ->
+    print_s [%message "Device" ~_:(packet : string)];
+    wait_for_reply reader ~f
+;;
+
+let parse_register = function
+  | "i" -> Chip8_debug_protocol.register_i
+  | "pc" -> Chip8_debug_protocol.register_pc
+  | "delay" -> Chip8_debug_protocol.register_delay
+  | "sound" -> Chip8_debug_protocol.register_sound
+  | register -> Int.of_string register
+;;
+
+let parse_condition register comparison value =
+  match
+    List.find Chip8_debug_protocol.Comparison.all ~f:(fun candidate ->
+      String.equal comparison (Chip8_debug_protocol.Comparison.to_string candidate))
+  with
+  | Some comparison ->
+    { Chip8_debug_protocol.Condition.register = parse_register register
+    ; comparison
+    ; value = Int.of_string value
+    }
+  | None -> raise_s [%message "Unknown comparison" (comparison : string)]
+;;
+
+let parse_request words =
+  let int = Int.of_string in
+  match words with
+  | [ "break"; address ] -> `Request (Request.Set_breakpoint (int address))
+  | [ "delete"; address ] -> `Request (Request.Clear_breakpoint (int address))
+  | [ "step" ] -> `Request Request.Step
+  | [ "continue" ] -> `Request Request.Continue
+  | [ "halt" ] -> `Request Request.Halt
+  | [ "regs" ] -> `Request Request.Read_registers
+  | [ "set"; register; value ] ->
+    `Request
+      (Request.Write_register { register = parse_register register; value = int value })
+  | [ "read"; address; length ] ->
+    `Request (Request.Read_memory { address = int address; length = int length })
+  | [ "write"; address; hex ] ->
+    let data =
+      String.to_list hex
+      |> List.chunks_of ~length:2
+      |> List.map ~f:(fun digits ->
+        Int.of_string ("0x" ^ String.of_char_list digits) |> Char.of_int_exn)
+      |> String.of_char_list
+    in
+    `Request (Request.Write_memory { address = int address; data })
+  | [ "watch"; address; length ] ->
+    `Request (Request.Set_watchpoint { address = int address; length = int length })
+  | [ "unwatch"; address ] -> `Request (Request.Clear_watchpoint (int address))
+  | [ "cond"; register; comparison; value ] ->
+    `Request (Request.Set_condition (parse_condition register comparison value))
+  | [ "uncond"; register; comparison; value ] ->
+    `Request (Request.Clear_condition (parse_condition register comparison value))
+  | [ "display"; mode ] ->
+    (match
+       List.find Chip8_debug_protocol.Display_mode.all ~f:(fun display_mode ->
+         String.equal mode (Chip8_debug_protocol.Display_mode.to_string display_mode))
+     with
+     | Some mode -> `Request (Request.Set_display_mode mode)
+     | None -> raise_s [%message "Unknown display mode" (mode : string)])
+  | [ "seed"; seed ] -> `Request (Request.Set_seed (int seed))
+  | [ "jit"; "on" ] -> `Request (Request.Set_jit true)
+  | [ "jit"; "off" ] -> `Request (Request.Set_jit false)
+  | [ "back"; frames ] -> `Request (Request.Step_back (int frames))
+  | [ "wait" ] -> `Wait_for_stop
+  | _ -> raise_s [%message "Unknown debugger command" (words : string list)]
+;;
+
+let command =
+  Command.basic
+    ~summary:
+      "send debugger commands to a CHIP-8 emulator running on the device. Commands are \
+       break ADDR, delete ADDR, step, continue, halt, regs, set REG VALUE, read ADDR \
+       LEN, write ADDR HEX, watch ADDR LEN, unwatch ADDR, cond REG ==|!=|<|> VALUE, \
+       uncond REG ==|!=|<|> VALUE, display direct|buffered|persist, seed N (for the \
+       next ROM load, 0 to seed from the clock), jit on|off, back FRAMES (step back \
+       through the rewind history) and wait (for the machine to stop). REG is a V \
+       register number, i, pc, delay or sound"
+    (let open Command.Let_syntax in
+     let open Command.Param in
+     let%map device_filename = anon ("device-filename" %: string)
+     and commands = anon (sequence ("command" %: string)) in
+     fun () ->
+       let writer, reader = Serial.open_device device_filename in
+       (* The device ignores a request with the sequence number it last served,
+          so start from something unlikely to already be in the mailbox. *)
+       let sequence = ref (Int.of_float (Core_unix.gettimeofday ()) land 0xFFFFFFFF) in
+       List.iter commands ~f:(fun command ->
+         match String.split command ~on:' ' |> parse_request with
+         | `Wait_for_stop ->
+           let reply =
+             wait_for_reply reader ~f:(function
+               | Reply.Stopped _ -> true
+               | _ -> false)
+           in
+           print_s [%message "" (reply : Reply.t)]
+         | `Request request ->
+           incr sequence;
+           let sequence = !sequence land 0xFFFFFFFF in
+           Serial.do_write ~ch:writer (Request.to_dma_packet request ~sequence);
+           let reply =
+             wait_for_reply reader ~f:(fun reply -> Reply.sequence reply = sequence)
+           in
+           print_s [%message "" (request : Request.t) (reply : Reply.t)]))
+;;
+
+let () = Command_unix.run command
@@ -0,0 +1,114 @@
+open! Core
+open Hardcaml_risc_v_test
+
+(* Terminals report key presses (and autorepeats) but never releases, so a key
+   is treated as held until no press has arrived for the hold time. The hold
+   time needs to cover the gap before autorepeat starts for keys to stay held. *)
+let held_keys ~last_pressed ~hold ~now =
+  Map.filter last_pressed ~f:(fun pressed ->
+    Time_ns.Span.( < ) (Time_ns.diff now pressed) hold)
+  |> Map.keys
+  |> Chip8_keypad.State.of_keys
+;;
+
+(** Put the terminal into non-canonical mode without echo so key presses can be
+    read as they happen. Returns a function restoring the previous settings. *)
+let raw_terminal () =
+  let original = Core_unix.Terminal_io.tcgetattr Core_unix.stdin in
+  let raw = { original with c_icanon = false; c_echo = false; c_vmin = 1; c_vtime = 0 } in
+  Core_unix.Terminal_io.tcsetattr raw ~mode:TCSANOW Core_unix.stdin;
+  fun () -> Core_unix.Terminal_io.tcsetattr original ~mode:TCSANOW Core_unix.stdin
+;;
+
+let command =
+  Command.basic
+    ~summary:"send key presses to the chip-8-rust emulator keypad"
+    (let open Command.Let_syntax in
+     let open Command.Param in
+     let%map device_filename = anon ("device-filename" %: string)
+     and fixed_keymap =
+       flag
+         "-keymap"
+         (optional
+            (Arg_type.create (fun keymap ->
+               Chip8_keypad.Keymap.of_string keymap |> Or_error.ok_exn)))
+         ~doc:
+           "KEYS the host key for each keypad key 0 - F, instead of the keymap of \
+            each ROM loaded"
+     and hold_ms =
+       flag
+         "-hold-ms"
+         (optional_with_default 250. float)
+         ~doc:"MS how long a key stays held after the last press or autorepeat"
+     in
+     fun () ->
+       let writer, reader = Serial.open_device device_filename in
+       let device = Core_unix.descr_of_in_channel reader in
+       let hold = Time_ns.Span.of_ms hold_ms in
+       let send state =
+         Serial.write ~ch:writer (Chip8_keypad.State.to_dma_packet state)
+       in
+       let keymap = Option.value fixed_keymap ~default:Chip8_keypad.Keymap.default in
+       print_s [%message "Press escape to quit" (keymap : Chip8_keypad.Keymap.t)];
+       (* Switch to the keymap of each ROM the device loads, unless one was given *)
+       let next_keymap keymap packets =
+         if Option.is_some fixed_keymap
+         then keymap
+         else
+           List.fold packets ~init:keymap ~f:(fun keymap packet ->
+             match Chip8_keypad.Keymap.of_packet packet with
+             | Some keymap ->
+               print_s [%message "ROM loaded" (keymap : Chip8_keypad.Keymap.t)];
+               keymap
+             | None -> keymap)
+       in
+       let restore_terminal = raw_terminal () in
+       let buffer = Bytes.create 256 in
+       let read descr =
+         let length = Core_unix.read descr ~buf:buffer in
+         Bytes.To_string.sub buffer ~pos:0 ~len:length
+       in
+       let rec loop ~keymap ~pending ~last_pressed ~sent =
+         let readable =
+           Core_unix.select
+             ~read:[ Core_unix.stdin; device ]
+             ~write:[]
+             ~except:[]
+             ~timeout:(`After (Time_ns.Span.of_ms 5.))
+             ()
+         in
+         let is_readable descr =
+           List.mem readable.read descr ~equal:Core_unix.File_descr.equal
+         in
+         let input = if is_readable Core_unix.stdin then read Core_unix.stdin else "" in
+         (* Packets split across reads are kept until the rest arrives *)
+         let packets, pending =
+           Chip8_debug_protocol.split_packets
+             (if is_readable device then pending ^ read device else pending)
+         in
+         let keymap = next_keymap keymap packets in
+         if String.mem input '\027'
+         then send Chip8_keypad.State.empty
+         else (
+           let now = Time_ns.now () in
+           let last_pressed =
+             String.fold input ~init:last_pressed ~f:(fun last_pressed host_key ->
+               match Chip8_keypad.Keymap.keypad_key keymap host_key with
+               | Some key -> Map.set last_pressed ~key ~data:now
+               | None -> last_pressed)
+           in
+           let state = held_keys ~last_pressed ~hold ~now in
+           if not (Chip8_keypad.State.equal state sent) then send state;
+           loop ~keymap ~pending ~last_pressed ~sent:state)
+       in
+       Exn.protect
+         ~f:(fun () ->
+           loop
+             ~keymap
+             ~pending:""
+             ~last_pressed:Int.Map.empty
+             ~sent:Chip8_keypad.State.empty)
+         ~finally:restore_terminal)
+;;
+
+let () = Command_unix.run command
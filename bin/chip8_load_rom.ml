@@ -0,0 +1,66 @@
+open! Core
+open Hardcaml_risc_v_test
+
+let command =
+  Command.basic
+    ~summary:"load a CHIP-8 ROM into a running chip-8-rust emulator"
+    (let open Command.Let_syntax in
+     let open Command.Param in
+     let%map device_filename = anon ("device-filename" %: string)
+     and rom_filename = anon ("rom-filename" %: string)
+     and title = flag "-title" (optional string) ~doc:"TITLE shown for the ROM"
+     and platform =
+       flag
+         "-platform"
+         (optional_with_default
+            Chip8_rom.Platform.Super_chip
+            (Arg_type.of_alist_exn
+               [ "chip-8", Chip8_rom.Platform.Chip8
+               ; "super-chip", Super_chip
+               ; "xo-chip", Xo_chip
+               ]))
+         ~doc:"PLATFORM chip-8, super-chip or xo-chip"
+     and instructions_per_frame =
+       flag
+         "-instructions-per-frame"
+         (optional_with_default 10 int)
+         ~doc:"N CPU speed in instructions per 60Hz frame"
+     and vip_timing =
+       flag
+         "-vip-timing"
+         no_arg
+         ~doc:" run at the speed of the COSMAC VIP interpreter instead"
+     and keymap =
+       flag
+         "-keymap"
+         (optional string)
+         ~doc:"KEYS the host key for each keypad key 0 - F"
+     in
+     fun () ->
+       let writer, _reader = Serial.open_device device_filename in
+       let title =
+         Option.value_or_thunk title ~default:(fun () ->
+           Filename.basename rom_filename
+           |> Filename.chop_extension
+           |> String.uppercase
+           |> String.prefix ~len:Chip8_rom.title_size)
+       in
+       let container =
+         Chip8_rom.container
+           ~platform
+           ~instructions_per_frame
+           ~vip_timing
+           ?keymap
+           ~title
+           (In_channel.read_all rom_filename)
+       in
+       (* The emulator only loads a ROM when the generation changes, so use one
+          that is unlikely to match the last load. *)
+       let generation = Int.of_float (Core_unix.gettimeofday ()) land 0xFFFFFFFF in
+       print_s [%message "Loading" (title : string) (String.length container : int)];
+       List.iter
+         (Chip8_rom.load_packets ~generation container)
+         ~f:(Serial.do_write ~ch:writer))
+;;
+
+let () = Command_unix.run command
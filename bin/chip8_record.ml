@@ -0,0 +1,72 @@
+open! Core
+open Hardcaml_risc_v_test
+module Event = Chip8_input.Event
+module Recording = Chip8_input.Recording
+
+(** Read the payload of the next DMA packet from the device, skipping anything
+    before the header. *)
+let rec read_packet reader =
+  match In_channel.input_char reader with
+  | None -> raise_s [%message "Device closed"]
+  | Some 'D' ->
+    let length_msb = In_channel.input_byte reader |> Option.value_exn in
+    let length_lsb = In_channel.input_byte reader |> Option.value_exn in
+    let length = (length_msb lsl 8) lor length_lsb in
+    let buffer = Bytes.create length in
+    In_channel.really_input_exn reader ~buf:buffer ~pos:0 ~len:length;
+    Bytes.to_string buffer
+  | Some _ -> read_packet reader
+;;
+
+(* Rewrite the file after every packet so that it always holds the recording of
+   the game running when the tool is stopped. *)
+let record reader ~filename ~verbose =
+  let rec loop recording =
+    let packet = read_packet reader in
+    let next = Recording.add recording packet in
+    if not (String.equal next recording)
+    then (
+      Out_channel.write_all filename ~data:next;
+      if verbose
+      then (
+        if String.length next = Chip8_input.header_size
+        then eprint_s [%message "Recording" ~seed:(Recording.seed next : int)];
+        List.drop (Recording.events next) (List.length (Recording.events recording))
+        |> List.iter ~f:(fun event -> eprint_s [%message "" (event : Event.t)])));
+    loop next
+  in
+  loop ""
+;;
+
+let command =
+  Command.basic
+    ~summary:
+      "record the keypad input of the game running on a chip-8-rust emulator to a \
+       file, or replay a recording on it"
+    (let open Command.Let_syntax in
+     let open Command.Param in
+     let%map device_filename = anon ("device-filename" %: string)
+     and filename = anon ("recording-filename" %: string)
+     and replay =
+       flag "-replay" no_arg ~doc:" restart the running game and replay the recording"
+     and verbose = flag "-verbose" no_arg ~doc:" print each recorded event to stderr" in
+     fun () ->
+       let writer, reader = Serial.open_device device_filename in
+       if replay
+       then (
+         let recording = In_channel.read_all filename in
+         (* The emulator only replays when the generation changes, so use one
+            that is unlikely to match the last replay. *)
+         let generation = Int.of_float (Core_unix.gettimeofday ()) land 0xFFFFFFFF in
+         print_s
+           [%message
+             "Replaying"
+               ~seed:(Recording.seed recording : int)
+               ~events:(List.length (Recording.events recording) : int)];
+         List.iter
+           (Chip8_input.replay_packets ~generation recording)
+           ~f:(Serial.do_write ~ch:writer))
+       else record reader ~filename ~verbose)
+;;
+
+let () = Command_unix.run command
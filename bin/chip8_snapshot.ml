@@ -0,0 +1,63 @@
+open! Core
+open Hardcaml_risc_v_test
+module Capture = Chip8_snapshot.Capture
+
+(** Read the payload of the next DMA packet from the device, skipping anything
+    before the header. *)
+let rec read_packet reader =
+  match In_channel.input_char reader with
+  | None -> raise_s [%message "Device closed"]
+  | Some 'D' ->
+    let length_msb = In_channel.input_byte reader |> Option.value_exn in
+    let length_lsb = In_channel.input_byte reader |> Option.value_exn in
+    let length = (length_msb lsl 8) lor length_lsb in
+    let buffer = Bytes.create length in
+    In_channel.really_input_exn reader ~buf:buffer ~pos:0 ~len:length;
+    Bytes.to_string buffer
+  | Some _ -> read_packet reader
+;;
+
+(* A fault sends the faulting machine and then the oldest machine in the
+   rewind history, so every snapshot gets its own numbered file. *)
+let capture reader ~prefix =
+  let rec loop capture count =
+    match Capture.add capture (read_packet reader) with
+    | Complete image ->
+      let filename = sprintf "%s-%d.c8ss" prefix count in
+      Out_channel.write_all filename ~data:image;
+      print_s [%message "Captured snapshot" (filename : string)];
+      loop Capture.Waiting (count + 1)
+    | capture -> loop capture count
+  in
+  loop Capture.Waiting 0
+;;
+
+let command =
+  Command.basic
+    ~summary:
+      "save the snapshots a chip-8-rust emulator sends when a machine faults to \
+       PREFIX-N.c8ss, or restore a snapshot by writing it to the device and \
+       resetting it"
+    (let open Command.Let_syntax in
+     let open Command.Param in
+     let%map device_filename = anon ("device-filename" %: string)
+     and prefix =
+       flag
+         "-capture"
+         (optional string)
+         ~doc:"PREFIX save snapshots to PREFIX-0.c8ss, PREFIX-1.c8ss and so on"
+     and restore =
+       flag "-restore" (optional string) ~doc:"FILENAME restore the snapshot in FILENAME"
+     in
+     fun () ->
+       let writer, reader = Serial.open_device device_filename in
+       match prefix, restore with
+       | Some prefix, None -> capture reader ~prefix
+       | None, Some filename ->
+         let image = In_channel.read_all filename in
+         print_s [%message "Restoring" (filename : string) (String.length image : int)];
+         List.iter (Chip8_snapshot.restore_packets image) ~f:(Serial.do_write ~ch:writer)
+       | _ -> raise_s [%message "Pass exactly one of -capture and -restore"])
+;;
+
+let () = Command_unix.run command
@@ -0,0 +1,72 @@
+open! Core
+open Hardcaml_risc_v_test
+
+let sample_rate = 8000
+
+(* The buzzer tone at the default pitch. XO-CHIP pitch changes scale it. *)
+let buzzer_frequency = 440.
+
+(** Read whatever the device has sent within [timeout] without blocking *)
+let read_available descr ~buf ~timeout =
+  let readable =
+    Core_unix.select ~read:[ descr ] ~write:[] ~except:[] ~timeout:(`After timeout) ()
+  in
+  if List.is_empty readable.read
+  then ""
+  else (
+    let length = Core_unix.read descr ~buf in
+    Bytes.To_string.sub buf ~pos:0 ~len:length)
+;;
+
+let command =
+  Command.basic
+    ~summary:
+      "play the chip-8-rust buzzer as unsigned 8-bit mono PCM on stdout, e.g. \
+       chip8_sound.exe /dev/ttyUSB1 | aplay -f U8 -r 8000"
+    (let open Command.Let_syntax in
+     let open Command.Param in
+     let%map device_filename = anon ("device-filename" %: string)
+     and verbose = flag "-verbose" no_arg ~doc:" print each sound event to stderr" in
+     fun () ->
+       let _writer, reader = Serial.open_device device_filename in
+       let descr = Core_unix.descr_of_in_channel reader in
+       let buf = Bytes.create 256 in
+       let start = Time_ns.now () in
+       (* The device clock is not synchronised with ours, so events are played
+          as they arrive rather than at their timestamps. *)
+       let rec loop ~pending ~tone ~samples_written =
+         let received = read_available descr ~buf ~timeout:(Time_ns.Span.of_ms 5.) in
+         let packets, pending = Chip8_debug_protocol.split_packets (pending ^ received) in
+         let tone =
+           List.fold packets ~init:tone ~f:(fun tone packet ->
+             match Chip8_sound.Event.of_packet packet with
+             | Some event ->
+               if verbose then eprint_s [%message "" (event : Chip8_sound.Event.t)];
+               if event.on
+               then
+                 Some
+                   (buzzer_frequency
+                    *. Chip8_sound.playback_rate event.pitch
+                    /. Chip8_sound.playback_rate Chip8_sound.default_pitch)
+               else None
+             | None -> tone)
+         in
+         let elapsed = Time_ns.Span.to_sec (Time_ns.diff (Time_ns.now ()) start) in
+         let samples_due = Float.iround_down_exn (elapsed *. Float.of_int sample_rate) in
+         for sample = samples_written to samples_due - 1 do
+           let level =
+             match tone with
+             | None -> 0x80
+             | Some frequency ->
+               let phase = Float.of_int sample *. frequency /. Float.of_int sample_rate in
+               if Float.(mod_float phase 1. < 0.5) then 0xC0 else 0x40
+           in
+           Out_channel.output_byte stdout level
+         done;
+         Out_channel.flush stdout;
+         loop ~pending ~tone ~samples_written:(Int.max samples_written samples_due)
+       in
+       loop ~pending:"" ~tone:None ~samples_written:0)
+;;
+
+let () = Command_unix.run command
@@ -20,32 +20,11 @@ let read_packet t =
   header, length, bytes_
 ;;
 
-let do_write ~ch data =
-  List.iter ~f:(fun byte -> Out_channel.output_byte ch byte) data;
-  Out_channel.flush ch;
-  let _ = Core_unix.nanosleep 1. in
-  ()
-;;
-
-(** Open a serial device, configure the baud rate, stop bits and parity bit,
-    then convert the file descriptor into input and output channels and return
-    them. *)
-let open_with_stty_settings ~baud_rate ~stop_bits ~parity_bit ~device_filename =
-  let file_descr = Core_unix.openfile ~mode:[ O_RDWR ] device_filename in
-  let tio_attr = Core_unix.Terminal_io.tcgetattr file_descr in
-  tio_attr.c_obaud <- baud_rate;
-  tio_attr.c_ibaud <- baud_rate;
-  tio_attr.c_csize <- 8;
-  tio_attr.c_cstopb <- stop_bits;
-  tio_attr.c_parenb <- parity_bit;
-  Core_unix.out_channel_of_descr file_descr, Core_unix.in_channel_of_descr file_descr
-;;
-
 (** Program a device with a chunk of memory at a specific address. The address
     must be register width aligned. *)
 let send_chunk ~writer ~address ~chunk =
   let formatted_packet = dma_packet ~address chunk in
-  do_write ~ch:writer formatted_packet
+  Serial.do_write ~ch:writer formatted_packet
 ;;
 
 (** Read packets from the serial device and print them out. *)
@@ -64,15 +43,7 @@ let command =
      and program_filename = anon ("program-filename" %: string) in
      fun () ->
        printf "Opening device\n";
-       (* Pick an arbitrary clock frequency, it doesn't matter for stty settings. *)
-       let settings = Uart_settings.default ~clock_frequency:0 in
-       let writer, reader =
-         open_with_stty_settings
-           ~baud_rate:settings.baud_rate
-           ~stop_bits:settings.stop_bits
-           ~parity_bit:settings.include_parity_bit
-           ~device_filename
-       in
+       let writer, reader = Serial.open_device device_filename in
        print_s [%message "Loading program" ~_:(program_filename : string)];
        let program = In_channel.read_all program_filename in
        print_s [%message "Progam length: " ~_:(String.length program : int)];
@@ -85,7 +56,7 @@ let command =
          send_chunk ~writer ~address:(index * chunk_sz) ~chunk);
        (* Send a clear signal to the device. *)
        print_s [%message "Sending clear signal via DMA"];
-       do_write ~ch:writer clear_packet;
+       Serial.do_write ~ch:writer clear_packet;
        print_s [%message "Printing any received packets"];
        print_any_incoming_packets ~reader)
 ;;
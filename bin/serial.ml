@@ -0,0 +1,33 @@
+open! Core
+
+let write ~ch data =
+  List.iter ~f:(fun byte -> Out_channel.output_byte ch byte) data;
+  Out_channel.flush ch
+;;
+
+let do_write ~ch data =
+  write ~ch data;
+  let _ = Core_unix.nanosleep 1. in
+  ()
+;;
+
+let open_with_stty_settings ~baud_rate ~stop_bits ~parity_bit ~device_filename =
+  let file_descr = Core_unix.openfile ~mode:[ O_RDWR ] device_filename in
+  let tio_attr = Core_unix.Terminal_io.tcgetattr file_descr in
+  tio_attr.c_obaud <- baud_rate;
+  tio_attr.c_ibaud <- baud_rate;
+  tio_attr.c_csize <- 8;
+  tio_attr.c_cstopb <- stop_bits;
+  tio_attr.c_parenb <- parity_bit;
+  Core_unix.out_channel_of_descr file_descr, Core_unix.in_channel_of_descr file_descr
+;;
+
+let open_device device_filename =
+  (* Pick an arbitrary clock frequency, it doesn't matter for stty settings. *)
+  let settings = Uart_settings.default ~clock_frequency:0 in
+  open_with_stty_settings
+    ~baud_rate:settings.baud_rate
+    ~stop_bits:settings.stop_bits
+    ~parity_bit:settings.include_parity_bit
+    ~device_filename
+;;
@@ -0,0 +1,20 @@
+open! Core
+
+(** Write bytes to the device without waiting for them to drain. *)
+val write : ch:Out_channel.t -> int list -> unit
+
+(** Write bytes to the device and wait for them to drain. *)
+val do_write : ch:Out_channel.t -> int list -> unit
+
+(** Open a serial device, configure the baud rate, stop bits and parity bit,
+    then convert the file descriptor into input and output channels and return
+    them. *)
+val open_with_stty_settings
+  :  baud_rate:int
+  -> stop_bits:int
+  -> parity_bit:bool
+  -> device_filename:string
+  -> Out_channel.t * In_channel.t
+
+(** [open_with_stty_settings] using the default UART settings. *)
+val open_device : string -> Out_channel.t * In_channel.t
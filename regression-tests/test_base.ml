@@ -176,8 +176,9 @@ let send_dma_message ~address ~packet sim =
     whole_packet
 ;;
 
-let test ~print_frames ~cycles ~data sim =
-  let sim, _, _ = sim in
+(** Zero main memory, reset the design and DMA [data] to address zero, then
+    reset again so the CPU starts executing it. *)
+let load_program ~data sim =
   let inputs : _ With_transmitter.I.t = Cyclesim.inputs sim in
   (* Initialize the main memory to some known values for testing. *)
   let initial_ram =
@@ -188,16 +189,69 @@ let test ~print_frames ~cycles ~data sim =
   (* Send a clear signal to initialize any CPU IO controller state back to
      default so we're ready to receive. *)
   clear_registers ~inputs sim;
-  let video_emulator = Video_emulator.create ~width:output_width ~height:output_height in
   send_dma_message ~address:0 ~packet:data sim;
-  let _outputs_before : _ With_transmitter.O.t =
-    Cyclesim.outputs ~clock_edge:Side.Before sim
-  in
-  let outputs : _ With_transmitter.O.t = Cyclesim.outputs sim in
   (* Wait some arbitrary number of cycles for the actual DMA to proceed. This is hard to guess, since the memory controller can push back. *)
   Sequence.range 0 100 |> Sequence.iter ~f:(fun _ -> Cyclesim.cycle sim);
   (* Send a clear signal and then start the vsync logic *)
-  clear_registers ~inputs sim;
+  clear_registers ~inputs sim
+;;
+
+(** Run the simulation collecting bytes the CPU sends over the UART into
+    [buffer] until [until buffer] holds or [max_cycles] have elapsed. Returns
+    whether [until] was satisfied. *)
+let run_until ~max_cycles ~buffer ~until sim =
+  let outputs : _ With_transmitter.O.t = Cyclesim.outputs sim in
+  let rec loop cycles =
+    if until buffer
+    then true
+    else if cycles = 0
+    then false
+    else (
+      Cyclesim.cycle sim;
+      if to_bool !(outputs.data_out_valid)
+      then Buffer.add_char buffer (to_char !(outputs.data_out));
+      loop (cycles - 1))
+  in
+  loop max_cycles
+;;
+
+(* Enough for the CHIP-8 emulator to boot and poll the debugger mailbox at
+   least once per request. *)
+let max_cycles_per_debug_request = 2_000_000
+
+(** Send a CHIP-8 debugger request and run the simulation until the emulator
+    replies to it. Bytes received before the reply are dropped. *)
+let send_debug_request ~sequence ~buffer sim request =
+  let open Chip8_debug_protocol in
+  send_dma_message
+    ~address:mailbox_address
+    ~packet:(Request.to_mailbox request ~sequence)
+    sim;
+  let find_reply buffer =
+    let packets, _ = split_packets (Buffer.contents buffer) in
+    List.find_map packets ~f:(fun packet ->
+      match Reply.of_packet packet with
+      | Some reply when Reply.sequence reply = sequence -> Some reply
+      | _ -> None)
+  in
+  let replied =
+    run_until
+      ~max_cycles:max_cycles_per_debug_request
+      ~buffer
+      ~until:(fun buffer -> Option.is_some (find_reply buffer))
+      sim
+  in
+  if not replied then raise_s [%message "No reply" (request : Request.t)];
+  let reply = Option.value_exn (find_reply buffer) in
+  Buffer.clear buffer;
+  reply
+;;
+
+let test ~print_frames ~cycles ~data sim =
+  let sim, _, _ = sim in
+  let video_emulator = Video_emulator.create ~width:output_width ~height:output_height in
+  load_program ~data sim;
+  let outputs : _ With_transmitter.O.t = Cyclesim.outputs sim in
   print_s [%message "Printing RAM before registers"];
   let rec loop_for cycles =
     if cycles = 0
@@ -0,0 +1,145 @@
+open! Core
+open Hardcaml_risc_v_test
+open Test_base
+module Request = Chip8_debug_protocol.Request
+module Reply = Chip8_debug_protocol.Reply
+
+let%expect_test "Chip-8 remote debugger" =
+  let program =
+    In_channel.read_all "../test-programs/test-rust-programs/chip-8-rust/chip-8-rust.bin"
+  in
+  let sim = create_sim "test_chip8_debugger" in
+  let cyclesim, _, _ = sim in
+  load_program ~data:program cyclesim;
+  (* The emulator boots into the ROM menu, key 1 picks the first ROM. *)
+  send_dma_message
+    ~address:Chip8_keypad.address
+    ~packet:(Chip8_keypad.State.to_message (Chip8_keypad.State.of_keys [ 1 ]))
+    cyclesim;
+  let buffer = Buffer.create 256 in
+  let sequence = ref 0 in
+  let send request =
+    incr sequence;
+    send_debug_request ~sequence:!sequence ~buffer cyclesim request
+  in
+  let request request =
+    let reply = send request in
+    print_s [%message "" (request : Request.t) (reply : Reply.t)]
+  in
+  (* Run until the machine stops and print why *)
+  let wait_for_stop () =
+    let stopped =
+      run_until
+        ~max_cycles:max_cycles_per_debug_request
+        ~buffer
+        ~until:(fun buffer ->
+          Chip8_debug_protocol.split_packets (Buffer.contents buffer)
+          |> fst
+          |> List.exists ~f:(fun packet ->
+            match Reply.of_packet packet with
+            | Some (Reply.Stopped _) -> true
+            | _ -> false))
+        cyclesim
+    in
+    print_s [%message (stopped : bool)];
+    Chip8_debug_protocol.split_packets (Buffer.contents buffer)
+    |> fst
+    |> List.filter_map ~f:Reply.of_packet
+    |> List.iter ~f:(function
+      | Reply.Stopped { reason; pc; _ } ->
+        print_s
+          [%message "Stopped" (reason : Chip8_debug_protocol.Stop_reason.t) (pc : int)]
+      | _ -> ())
+  in
+  (* Where the ROM has got to when the halt arrives depends on timing, so only
+     print what the test controls. *)
+  (match send Request.Halt with
+   | Reply.Stopped { reason; _ } ->
+     print_s [%message "Halted" (reason : Chip8_debug_protocol.Stop_reason.t)]
+   | reply -> raise_s [%message "Unexpected reply to halt" (reply : Reply.t)]);
+  request (Request.Write_register { register = 0; value = 0x42 });
+  request
+    (Request.Write_register { register = Chip8_debug_protocol.register_pc; value = 0xE00 });
+  (match send Request.Read_registers with
+   | Reply.Registers { v; pc; _ } ->
+     print_s [%message "Registers" ~v0:(List.hd_exn v : int) (pc : int)]
+   | reply -> raise_s [%message "Unexpected reply to read" (reply : Reply.t)]);
+  request (Request.Write_memory { address = 0xE00; data = "hello" });
+  request (Request.Read_memory { address = 0xE00; length = 5 });
+  (* Two CLS instructions, stopping on the second. *)
+  request (Request.Write_memory { address = 0xE00; data = "\x00\xE0\x00\xE0" });
+  request (Request.Set_breakpoint 0xE02);
+  request Request.Continue;
+  wait_for_stop ();
+  request (Request.Clear_breakpoint 0xE02);
+  request Request.Step;
+  request (Request.Set_display_mode Chip8_debug_protocol.Display_mode.Persist);
+  (* LD V0, 5; LD I, 0xE10; LD [I], V0; ADD V0, 1; JP 0xE08. The store stops on
+     the watchpoint and the add on the condition. *)
+  request
+    (Request.Write_register { register = Chip8_debug_protocol.register_pc; value = 0xE00 });
+  request
+    (Request.Write_memory
+       { address = 0xE00; data = "\x60\x05\xAE\x10\xF0\x55\x70\x01\x1E\x08" });
+  request (Request.Set_watchpoint { address = 0xE10; length = 1 });
+  let condition =
+    { Chip8_debug_protocol.Condition.register = 0; comparison = Equal; value = 6 }
+  in
+  request (Request.Set_condition condition);
+  request Request.Continue;
+  wait_for_stop ();
+  request Request.Continue;
+  wait_for_stop ();
+  request (Request.Clear_watchpoint 0xE10);
+  request (Request.Clear_condition condition);
+  request
+    (Request.Write_register { register = Chip8_debug_protocol.register_delay; value = 30 });
+  (* How far back the history goes depends on how many frames the simulation
+     ran, so only check that the machine stepped back. *)
+  (match send (Request.Step_back 1) with
+   | Reply.Stepped_back _ -> print_s [%message "Stepped back"]
+   | reply -> raise_s [%message "Unexpected reply to step back" (reply : Reply.t)]);
+  finalize_sim sim;
+  [%expect
+    {|
+    (Halted (reason Halted))
+    ((request (Write_register (register 0) (value 66)))
+     (reply (Ok (command W) (sequence 2))))
+    ((request (Write_register (register 17) (value 3584)))
+     (reply (Ok (command W) (sequence 3))))
+    (Registers (v0 66) (pc 3584))
+    ((request (Write_memory (address 3584) (data hello)))
+     (reply (Ok (command w) (sequence 5))))
+    ((request (Read_memory (address 3584) (length 5)))
+     (reply (Memory (sequence 6) (data hello))))
+    ((request (Write_memory (address 3584) (data "\000\224\000\224")))
+     (reply (Ok (command w) (sequence 7))))
+    ((request (Set_breakpoint 3586)) (reply (Ok (command B) (sequence 8))))
+    ((request Continue) (reply (Ok (command C) (sequence 9))))
+    (stopped true)
+    (Stopped (reason Breakpoint) (pc 3586))
+    ((request (Clear_breakpoint 3586)) (reply (Ok (command b) (sequence 10))))
+    ((request Step) (reply (Stopped (sequence 11) (reason Step) (pc 3588))))
+    ((request (Set_display_mode Persist)) (reply (Ok (command D) (sequence 12))))
+    ((request (Write_register (register 17) (value 3584)))
+     (reply (Ok (command W) (sequence 13))))
+    ((request (Write_memory (address 3584) (data "`\005\174\016\240U`\001\030\b")))
+     (reply (Ok (command w) (sequence 14))))
+    ((request (Set_watchpoint (address 3600) (length 1)))
+     (reply (Ok (command A) (sequence 15))))
+    ((request (Set_condition ((register 0) (comparison Equal) (value 6))))
+     (reply (Ok (command X) (sequence 16))))
+    ((request Continue) (reply (Ok (command C) (sequence 17))))
+    (stopped true)
+    (Stopped (reason Watchpoint) (pc 3590))
+    ((request Continue) (reply (Ok (command C) (sequence 18))))
+    (stopped true)
+    (Stopped (reason Condition) (pc 3592))
+    ((request (Clear_watchpoint 3600)) (reply (Ok (command a) (sequence 19))))
+    ((request (Clear_condition ((register 0) (comparison Equal) (value 6))))
+     (reply (Ok (command x) (sequence 20))))
+    ((request (Write_register (register 18) (value 30)))
+     (reply (Ok (command W) (sequence 21))))
+    "Stepped back"
+    |}]
+;;
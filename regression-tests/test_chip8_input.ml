@@ -0,0 +1,85 @@
+open! Core
+open Hardcaml_risc_v_test
+open Test_base
+module Request = Chip8_debug_protocol.Request
+module Reply = Chip8_debug_protocol.Reply
+module Recording = Chip8_input.Recording
+
+let%expect_test "Chip-8 input recording and replay" =
+  let program =
+    In_channel.read_all "../test-programs/test-rust-programs/chip-8-rust/chip-8-rust.bin"
+  in
+  let sim = create_sim "test_chip8_input" in
+  let cyclesim, _, _ = sim in
+  load_program ~data:program cyclesim;
+  (* LD V0, K then loop forever on JP 0x202. *)
+  let container = Chip8_rom.container ~title:"KEYS" "\xF0\x0A\x12\x02" in
+  List.iter
+    (Chip8_rom.load_messages ~generation:1 container)
+    ~f:(fun (address, packet) -> send_dma_message ~address ~packet cyclesim);
+  let buffer = Buffer.create 256 in
+  let packets () = Chip8_debug_protocol.split_packets (Buffer.contents buffer) |> fst in
+  let recording () = List.fold (packets ()) ~init:"" ~f:Recording.add in
+  let run_until_recording ~f =
+    run_until
+      ~max_cycles:max_cycles_per_debug_request
+      ~buffer
+      ~until:(fun _ -> f (recording ()))
+      cyclesim
+  in
+  let run_until_events count =
+    run_until_recording ~f:(fun recording ->
+      List.length (Recording.events recording) >= count)
+  in
+  let press keys =
+    send_dma_message
+      ~address:Chip8_keypad.address
+      ~packet:(Chip8_keypad.State.to_message (Chip8_keypad.State.of_keys keys))
+      cyclesim
+  in
+  (* The recording starts with the header sent when the ROM loads *)
+  let loaded = run_until_recording ~f:(Fn.non String.is_empty) in
+  print_s [%message (loaded : bool)];
+  press [ 5 ];
+  let pressed = run_until_events 1 in
+  press [];
+  let released = run_until_events 2 in
+  print_s [%message (pressed : bool) (released : bool)];
+  (* Frame numbers depend on how long the simulation takes to deliver the
+     keypad writes, so only print the keys. *)
+  let recording = recording () in
+  List.iter (Recording.events recording) ~f:(fun event ->
+    print_s [%message "" ~key:(event.key : int) ~pressed:(event.pressed : bool)]);
+  (* Replaying restarts the game and presses the key again without the keypad *)
+  Buffer.clear buffer;
+  List.iter
+    (Chip8_input.replay_messages ~generation:1 recording)
+    ~f:(fun (address, packet) -> send_dma_message ~address ~packet cyclesim);
+  let finished =
+    run_until
+      ~max_cycles:(4 * max_cycles_per_debug_request)
+      ~buffer
+      ~until:(fun _ -> List.mem (packets ()) "Replay finished" ~equal:String.equal)
+      cyclesim
+  in
+  let replayed = List.fold (packets ()) ~init:"" ~f:Recording.add in
+  print_s
+    [%message
+      (finished : bool)
+        ~same_seed:(Recording.seed replayed = Recording.seed recording : bool)];
+  Buffer.clear buffer;
+  (match send_debug_request ~sequence:1 ~buffer cyclesim Request.Read_registers with
+   | Reply.Registers { v; pc; _ } ->
+     print_s [%message "Registers" ~v0:(List.hd_exn v : int) (pc : int)]
+   | reply -> raise_s [%message "Unexpected reply to read" (reply : Reply.t)]);
+  finalize_sim sim;
+  [%expect
+    {|
+    (loaded true)
+    ((pressed true) (released true))
+    ((key 5) (pressed true))
+    ((key 5) (pressed false))
+    ((finished true) (same_seed true))
+    (Registers (v0 5) (pc 514))
+    |}]
+;;
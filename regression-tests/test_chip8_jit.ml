@@ -0,0 +1,74 @@
+open! Core
+open Hardcaml_risc_v_test
+open Test_base
+module Request = Chip8_debug_protocol.Request
+module Reply = Chip8_debug_protocol.Reply
+
+let%expect_test "Chip-8 JIT matches the interpreter" =
+  let program =
+    In_channel.read_all
+      "../test-programs/test-rust-programs/chip-8-rust/chip-8-rust-jit.bin"
+  in
+  let sim = create_sim "test_chip8_jit" in
+  let cyclesim, _, _ = sim in
+  load_program ~data:program cyclesim;
+  (* Sum V0 into V1 as V0 counts from 0xF1 round to 0, then loop forever on JP
+     0x20C. The loop body runs often enough to be translated. *)
+  let container =
+    Chip8_rom.container
+      ~title:"LOOP"
+      ~instructions_per_frame:100
+      "\x60\xF0\x61\x00\x70\x01\x81\x04\x30\x00\x12\x04\x12\x0C"
+  in
+  let buffer = Buffer.create 256 in
+  let sequence = ref 0 in
+  let send request =
+    incr sequence;
+    send_debug_request ~sequence:!sequence ~buffer cyclesim request
+  in
+  let run ~generation =
+    Buffer.clear buffer;
+    List.iter
+      (Chip8_rom.load_messages ~generation container)
+      ~f:(fun (address, packet) -> send_dma_message ~address ~packet cyclesim);
+    let loaded =
+      run_until
+        ~max_cycles:max_cycles_per_debug_request
+        ~buffer
+        ~until:(fun buffer ->
+          Chip8_debug_protocol.split_packets (Buffer.contents buffer)
+          |> fst
+          |> List.mem ~equal:String.equal "LOOP")
+        cyclesim
+    in
+    (* Read the registers until the program has reached the final loop *)
+    let rec finish attempts =
+      match send Request.Read_registers with
+      | Reply.Registers { v; pc = 0x20C; _ } -> Some v
+      | Reply.Registers _ when attempts > 1 -> finish (attempts - 1)
+      | _ -> None
+    in
+    match finish 10 with
+    | Some v ->
+      print_s
+        [%message
+          (loaded : bool)
+            ~v0:(List.nth_exn v 0 : int)
+            ~v1:(List.nth_exn v 1 : int)
+            ~vf:(List.nth_exn v 15 : int)]
+    | None -> print_s [%message "Program did not finish" (loaded : bool)]
+  in
+  run ~generation:1;
+  (* The sequence number depends on how many reads the first run took *)
+  (match send (Request.Set_jit false) with
+   | Reply.Ok { command; _ } -> print_s [%message "Set_jit" (command : char)]
+   | reply -> raise_s [%message "Unexpected reply to Set_jit" (reply : Reply.t)]);
+  run ~generation:2;
+  finalize_sim sim;
+  [%expect
+    {|
+    ((loaded true) (v0 0) (v1 136) (vf 0))
+    (Set_jit (command J))
+    ((loaded true) (v0 0) (v1 136) (vf 0))
+    |}]
+;;
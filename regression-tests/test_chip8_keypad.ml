@@ -0,0 +1,58 @@
+open! Core
+open Hardcaml_risc_v_test
+open Test_base
+module Request = Chip8_debug_protocol.Request
+module Reply = Chip8_debug_protocol.Reply
+
+let%expect_test "Chip-8 keypad input" =
+  let program =
+    In_channel.read_all "../test-programs/test-rust-programs/chip-8-rust/chip-8-rust.bin"
+  in
+  let sim = create_sim "test_chip8_keypad" in
+  let cyclesim, _, _ = sim in
+  load_program ~data:program cyclesim;
+  (* LD V0, K then loop forever on JP 0x202. *)
+  let container = Chip8_rom.container ~title:"KEYS" "\xF0\x0A\x12\x02" in
+  List.iter
+    (Chip8_rom.load_messages ~generation:1 container)
+    ~f:(fun (address, packet) -> send_dma_message ~address ~packet cyclesim);
+  let buffer = Buffer.create 256 in
+  let loaded =
+    run_until
+      ~max_cycles:max_cycles_per_debug_request
+      ~buffer
+      ~until:(fun buffer ->
+        Chip8_debug_protocol.split_packets (Buffer.contents buffer)
+        |> fst
+        |> List.mem ~equal:String.equal "KEYS")
+      cyclesim
+  in
+  print_s [%message (loaded : bool)];
+  Buffer.clear buffer;
+  (* Halt and step so the machine is waiting for a key however far it got
+     before the halt arrived. Keys are still read while halted. *)
+  ignore (send_debug_request ~sequence:1 ~buffer cyclesim Request.Halt : Reply.t);
+  ignore (send_debug_request ~sequence:2 ~buffer cyclesim Request.Step : Reply.t);
+  send_dma_message
+    ~address:Chip8_keypad.address
+    ~packet:(Chip8_keypad.State.to_message (Chip8_keypad.State.of_keys [ 5 ]))
+    cyclesim;
+  (* Give the emulator a frame to read the keypad *)
+  ignore
+    (run_until
+       ~max_cycles:max_cycles_per_debug_request
+       ~buffer
+       ~until:(Fn.const false)
+       cyclesim
+     : bool);
+  (match send_debug_request ~sequence:3 ~buffer cyclesim Request.Read_registers with
+   | Reply.Registers { v; pc; _ } ->
+     print_s [%message "Registers" ~v0:(List.hd_exn v : int) (pc : int)]
+   | reply -> raise_s [%message "Unexpected reply to read" (reply : Reply.t)]);
+  finalize_sim sim;
+  [%expect
+    {|
+    (loaded true)
+    (Registers (v0 5) (pc 514))
+    |}]
+;;
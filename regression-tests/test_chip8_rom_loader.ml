@@ -0,0 +1,47 @@
+open! Core
+open Hardcaml_risc_v_test
+open Test_base
+module Request = Chip8_debug_protocol.Request
+module Reply = Chip8_debug_protocol.Reply
+
+let%expect_test "Chip-8 runtime ROM loading" =
+  let program =
+    In_channel.read_all "../test-programs/test-rust-programs/chip-8-rust/chip-8-rust.bin"
+  in
+  let sim = create_sim "test_chip8_rom_loader" in
+  let cyclesim, _, _ = sim in
+  load_program ~data:program cyclesim;
+  (* LD V0, 0x42 then loop forever on JP 0x202. *)
+  let container = Chip8_rom.container ~title:"TEST" "\x60\x42\x12\x02" in
+  List.iter
+    (Chip8_rom.load_messages ~generation:1 container)
+    ~f:(fun (address, packet) -> send_dma_message ~address ~packet cyclesim);
+  let buffer = Buffer.create 256 in
+  let loaded =
+    run_until
+      ~max_cycles:max_cycles_per_debug_request
+      ~buffer
+      ~until:(fun buffer ->
+        Chip8_debug_protocol.split_packets (Buffer.contents buffer)
+        |> fst
+        |> List.mem ~equal:String.equal "TEST")
+      cyclesim
+  in
+  print_s [%message (loaded : bool)];
+  Buffer.clear buffer;
+  (match send_debug_request ~sequence:1 ~buffer cyclesim Request.Halt with
+   | Reply.Stopped { reason; pc; _ } ->
+     print_s
+       [%message "Halted" (reason : Chip8_debug_protocol.Stop_reason.t) (pc : int)]
+   | reply -> raise_s [%message "Unexpected reply to halt" (reply : Reply.t)]);
+  (match send_debug_request ~sequence:2 ~buffer cyclesim Request.Read_registers with
+   | Reply.Registers { v; _ } -> print_s [%message "Registers" ~v0:(List.hd_exn v : int)]
+   | reply -> raise_s [%message "Unexpected reply to read" (reply : Reply.t)]);
+  finalize_sim sim;
+  [%expect
+    {|
+    (loaded true)
+    (Halted (reason Halted) (pc 514))
+    (Registers (v0 66))
+    |}]
+;;
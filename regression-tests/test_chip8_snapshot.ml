@@ -0,0 +1,87 @@
+open! Core
+open Hardcaml_risc_v_test
+open Test_base
+module Request = Chip8_debug_protocol.Request
+module Reply = Chip8_debug_protocol.Reply
+module Capture = Chip8_snapshot.Capture
+
+let%expect_test "Chip-8 snapshot capture and restore" =
+  let program =
+    In_channel.read_all "../test-programs/test-rust-programs/chip-8-rust/chip-8-rust.bin"
+  in
+  let sim = create_sim "test_chip8_snapshot" in
+  let cyclesim, _, _ = sim in
+  load_program ~data:program cyclesim;
+  (* LD V0, 0x42; LD V1, 0x17; RET, which faults as the stack is empty *)
+  let container = Chip8_rom.container ~title:"FAULT" "\x60\x42\x61\x17\x00\xEE" in
+  List.iter
+    (Chip8_rom.load_messages ~generation:1 container)
+    ~f:(fun (address, packet) -> send_dma_message ~address ~packet cyclesim);
+  let buffer = Buffer.create 256 in
+  let capture () =
+    Chip8_debug_protocol.split_packets (Buffer.contents buffer)
+    |> fst
+    |> List.fold ~init:Capture.Waiting ~f:Capture.add
+  in
+  (* The first snapshot is the faulting machine, the second the oldest machine
+     in the rewind history. Only the first is needed. *)
+  let captured =
+    run_until
+      ~max_cycles:(4 * max_cycles_per_debug_request)
+      ~buffer
+      ~until:(fun _ ->
+        match capture () with
+        | Complete _ -> true
+        | _ -> false)
+      cyclesim
+  in
+  let image =
+    match capture () with
+    | Complete image -> image
+    | capture -> raise_s [%message "No snapshot" (capture : Capture.t)]
+  in
+  print_s
+    [%message
+      (captured : bool)
+        ~v0:(Char.to_int image.[10] : int)
+        ~pc:(Chip8_snapshot.u16 image ~pos:26 : int)];
+  (* Write the snapshot back and reset, the emulator restores it in place of
+     the menu *)
+  List.iter (Chip8_snapshot.restore_messages image) ~f:(fun (address, packet) ->
+    send_dma_message ~address ~packet cyclesim);
+  clear_registers ~inputs:(Cyclesim.inputs cyclesim) cyclesim;
+  Buffer.clear buffer;
+  let restored =
+    run_until
+      ~max_cycles:max_cycles_per_debug_request
+      ~buffer
+      ~until:(fun buffer ->
+        Chip8_debug_protocol.split_packets (Buffer.contents buffer)
+        |> fst
+        |> List.mem ~equal:String.equal "Initialized, stepping")
+      cyclesim
+  in
+  print_s [%message (restored : bool)];
+  Buffer.clear buffer;
+  (* The restored machine is where the fault left it, and faults again *)
+  (match send_debug_request ~sequence:1 ~buffer cyclesim Request.Halt with
+   | Reply.Stopped _ -> ()
+   | reply -> raise_s [%message "Unexpected reply to halt" (reply : Reply.t)]);
+  (match send_debug_request ~sequence:2 ~buffer cyclesim Request.Read_registers with
+   | Reply.Registers { v; pc; _ } ->
+     print_s [%message "Registers" ~v0:(List.hd_exn v : int) (pc : int)]
+   | reply -> raise_s [%message "Unexpected reply to read" (reply : Reply.t)]);
+  (match send_debug_request ~sequence:3 ~buffer cyclesim Request.Step with
+   | Reply.Stopped { reason; pc; _ } ->
+     print_s
+       [%message "Stepped" (reason : Chip8_debug_protocol.Stop_reason.t) (pc : int)]
+   | reply -> raise_s [%message "Unexpected reply to step" (reply : Reply.t)]);
+  finalize_sim sim;
+  [%expect
+    {|
+    ((captured true) (v0 66) (pc 516))
+    (restored true)
+    (Registers (v0 66) (pc 516))
+    (Stepped (reason Fault) (pc 516))
+    |}]
+;;
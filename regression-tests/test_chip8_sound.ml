@@ -0,0 +1,51 @@
+open! Core
+open Hardcaml_risc_v_test
+open Test_base
+module Event = Chip8_sound.Event
+
+let%expect_test "Chip-8 sound events" =
+  let program =
+    In_channel.read_all "../test-programs/test-rust-programs/chip-8-rust/chip-8-rust.bin"
+  in
+  let sim = create_sim "test_chip8_sound" in
+  let cyclesim, _, _ = sim in
+  load_program ~data:program cyclesim;
+  (* LD V0, 2; LD ST, V0 then loop forever on JP 0x204. The buzzer sounds for
+     two frames. *)
+  let container = Chip8_rom.container ~title:"BEEP" "\x60\x02\xF0\x18\x12\x04" in
+  List.iter
+    (Chip8_rom.load_messages ~generation:1 container)
+    ~f:(fun (address, packet) -> send_dma_message ~address ~packet cyclesim);
+  let buffer = Buffer.create 256 in
+  let events () =
+    Chip8_debug_protocol.split_packets (Buffer.contents buffer)
+    |> fst
+    |> List.filter_map ~f:Event.of_packet
+  in
+  let finished =
+    run_until
+      ~max_cycles:(4 * max_cycles_per_debug_request)
+      ~buffer
+      ~until:(fun _ -> List.length (events ()) >= 2)
+      cyclesim
+  in
+  print_s [%message (finished : bool)];
+  (* Timestamps depend on how long the emulator takes to boot, so only print
+     their order. *)
+  (match events () with
+   | [ on; off ] ->
+     print_s
+       [%message
+         ""
+           ~on:(on.Event.on : bool)
+           ~off:(off.Event.on : bool)
+           ~pitch:(on.pitch : int)
+           ~off_after_on:(off.time_ns > on.time_ns : bool)]
+   | events -> print_s [%message "Unexpected events" (events : Event.t list)]);
+  finalize_sim sim;
+  [%expect
+    {|
+    (finished true)
+    ((on true) (off false) (pitch 64) (off_after_on true))
+    |}]
+;;
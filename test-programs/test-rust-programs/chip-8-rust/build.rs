@@ -0,0 +1,199 @@
+//! Wrap every ROM listed in roms/roms.txt in a ROM container and generate the table of bundled
+//! ROMs that src/roms.rs includes.
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use chip8_core::container::{
+    Container, FLAG_VIP_TIMING, KEYMAP_SIZE, MAX_PROGRAM_SIZE, TITLE_SIZE,
+};
+use chip8_core::cpu::Platform;
+use chip8_core::font::Font;
+use chip8_core::quirks::Quirks;
+
+const MANIFEST: &str = "roms/roms.txt";
+const DEFAULT_INSTRUCTIONS_PER_FRAME: u16 = 10;
+const DEFAULT_KEYMAP: &[u8; KEYMAP_SIZE] = b"x123qweasdzc4rfv";
+
+/// A ROM from the manifest, before it is wrapped
+struct Entry {
+    name: String,
+    file: Option<String>,
+    title: Option<String>,
+    platform: Platform,
+    quirks: Quirks,
+    instructions_per_frame: u16,
+    vip_timing: bool,
+    keymap: [u8; KEYMAP_SIZE],
+}
+
+impl Entry {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            file: None,
+            title: None,
+            platform: Platform::SuperChip,
+            quirks: Quirks::SUPER_CHIP,
+            instructions_per_frame: DEFAULT_INSTRUCTIONS_PER_FRAME,
+            vip_timing: false,
+            keymap: *DEFAULT_KEYMAP,
+        }
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let flag = || match value {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            _ => Err(format!("{key} must be true or false")),
+        };
+
+        match key {
+            "file" => self.file = Some(value.to_string()),
+            "title" => {
+                if value.len() > TITLE_SIZE || !value.is_ascii() {
+                    return Err(format!(
+                        "title must be at most {TITLE_SIZE} ASCII characters"
+                    ));
+                }
+                self.title = Some(value.to_string());
+            }
+            "platform" => {
+                self.platform = match value {
+                    "chip-8" => Platform::Chip8,
+                    "super-chip" => Platform::SuperChip,
+                    "xo-chip" => Platform::XoChip,
+                    _ => return Err(format!("unknown platform {value}")),
+                }
+            }
+            "quirks" => {
+                self.quirks = match value {
+                    "cosmac-vip" => Quirks::COSMAC_VIP,
+                    "chip-48" => Quirks::CHIP_48,
+                    "super-chip" => Quirks::SUPER_CHIP,
+                    "xo-chip" => Quirks::XO_CHIP,
+                    _ => return Err(format!("unknown quirks preset {value}")),
+                }
+            }
+            "shift_uses_vy" => self.quirks.shift_uses_vy = flag()?,
+            "load_store_increments_i" => self.quirks.load_store_increments_i = flag()?,
+            "jump_uses_vx" => self.quirks.jump_uses_vx = flag()?,
+            "clip_sprites" => self.quirks.clip_sprites = flag()?,
+            "logic_resets_vf" => self.quirks.logic_resets_vf = flag()?,
+            "lores_dxy0_draws_8x16" => self.quirks.lores_dxy0_draws_8x16 = flag()?,
+            "font" => {
+                self.quirks.font = match value {
+                    "vip" => Font::Vip,
+                    "dream-6800" => Font::Dream6800,
+                    "eti-660" => Font::Eti660,
+                    "super-chip" => Font::SuperChip,
+                    _ => return Err(format!("unknown font {value}")),
+                }
+            }
+            "instructions_per_frame" => {
+                self.instructions_per_frame = value
+                    .parse()
+                    .ok()
+                    .filter(|instructions| *instructions > 0)
+                    .ok_or_else(|| format!("bad instructions_per_frame {value}"))?
+            }
+            "vip_timing" => self.vip_timing = flag()?,
+            "keymap" => {
+                self.keymap = value
+                    .as_bytes()
+                    .try_into()
+                    .map_err(|_| format!("keymap must have {KEYMAP_SIZE} keys"))?
+            }
+            _ => return Err(format!("unknown key {key}")),
+        }
+        Ok(())
+    }
+}
+
+fn parse_manifest(manifest: &str) -> Result<Vec<Entry>, String> {
+    let mut entries: Vec<Entry> = Vec::new();
+
+    for (index, line) in manifest.lines().enumerate() {
+        let line = line.trim();
+        let error = |message: String| format!("{MANIFEST}:{}: {message}", index + 1);
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(name) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            entries.push(Entry::new(name.trim()));
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| error("expected key = value".to_string()))?;
+        let entry = entries
+            .last_mut()
+            .ok_or_else(|| error("key before the first [section]".to_string()))?;
+        entry.set(key.trim(), value.trim()).map_err(error)?;
+    }
+
+    Ok(entries)
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=roms");
+
+    let out_dir = env::var("OUT_DIR").unwrap();
+    let manifest = fs::read_to_string(MANIFEST).unwrap();
+    let entries = parse_manifest(&manifest).unwrap_or_else(|error| panic!("{error}"));
+
+    let mut table = String::new();
+    writeln!(table, "pub static ROMS: [&[u8]; {}] = [", entries.len()).unwrap();
+
+    for entry in entries {
+        let file = entry
+            .file
+            .unwrap_or_else(|| panic!("{MANIFEST}: [{}] has no file", entry.name));
+        let program = fs::read(Path::new("roms").join(&file)).unwrap();
+
+        // Raw ROMs are often padded to fill memory. Memory is cleared before a ROM is loaded, so
+        // the padding does not need to be stored.
+        let len = program
+            .iter()
+            .rposition(|byte| *byte != 0)
+            .map_or(0, |last| last + 1);
+        if len > MAX_PROGRAM_SIZE {
+            panic!(
+                "{MANIFEST}: [{}] is {len} bytes, ROMs can be at most {MAX_PROGRAM_SIZE}",
+                entry.name
+            );
+        }
+
+        let container = Container {
+            title: entry.title.as_deref().unwrap_or(&entry.name),
+            platform: entry.platform.to_u8(),
+            quirks: entry.quirks.to_bits(),
+            flags: if entry.vip_timing { FLAG_VIP_TIMING } else { 0 },
+            instructions_per_frame: entry.instructions_per_frame,
+            keymap: entry.keymap,
+            program: &program[..len],
+        };
+
+        let mut image = container.header().to_vec();
+        image.extend_from_slice(container.program);
+
+        let path = Path::new(&out_dir).join(format!("{}.c8rom", entry.name));
+        fs::write(&path, image).unwrap();
+        writeln!(
+            table,
+            "    include_bytes!({:?}),",
+            path.display().to_string()
+        )
+        .unwrap();
+    }
+
+    writeln!(table, "];").unwrap();
+    fs::write(Path::new(&out_dir).join("roms.rs"), table).unwrap();
+}
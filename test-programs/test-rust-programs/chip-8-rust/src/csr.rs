@@ -0,0 +1,43 @@
+use core::arch::asm;
+
+/// Read the low 32 bits of the hart cycle counter. Callers measuring an interval should use
+/// wrapping_sub so that a single rollover between reads is handled.
+pub fn read_cycle() -> u32 {
+    let cycle: u32;
+    // The target is plain rv32i, so Zicsr has to be enabled for the counter read to assemble
+    unsafe {
+        asm!(
+            ".option push",
+            ".option arch, +zicsr",
+            "rdcycle {0}",
+            ".option pop",
+            out(reg) cycle
+        );
+    }
+    cycle
+}
+
+/// Read the time CSR, which counts nanoseconds since reset. On rv32 the counter is read as two
+/// halves, re-reading if the upper half changes between the reads.
+pub fn read_time_ns() -> u64 {
+    loop {
+        let (upper, lower, upper_again): (u32, u32, u32);
+        unsafe {
+            asm!(
+                ".option push",
+                ".option arch, +zicsr",
+                "rdtimeh {0}",
+                "rdtime {1}",
+                "rdtimeh {2}",
+                ".option pop",
+                out(reg) upper,
+                out(reg) lower,
+                out(reg) upper_again
+            );
+        }
+
+        if upper == upper_again {
+            return ((upper as u64) << 32) | lower as u64;
+        }
+    }
+}
@@ -0,0 +1,383 @@
+//! A remote debugger for CHIP-8 programs. The host writes requests into a mailbox in RAM with a
+//! UART DMA packet, and the debugger replies with DMA packets back to the host.
+//!
+//! A request is a MAILBOX_SIZE byte block written in a single DMA packet:
+//!
+//! | Offset | Size | Field                                                      |
+//! |--------|------|------------------------------------------------------------|
+//! | 0      | 1    | command                                                    |
+//! | 1      | 1    | register index or length                                   |
+//! | 2      | 2    | address                                                    |
+//! | 4      | 4    | value                                                      |
+//! | 8      | 52   | data for memory writes                                     |
+//! | 60     | 4    | sequence number, a new request has a different sequence    |
+//!
+//! The sequence number is last so that it is the final word the DMA engine writes. Every reply
+//! starts with REPLY_MAGIC, the command it answers and the request sequence number, followed by
+//! the reply payload. Multi-byte fields are little-endian.
+use crate::display::{Display, DisplayMode};
+use crate::util::{report_fault, send_dma};
+use chip8_core::breakpoint::{Comparison, Condition, Register, Trigger};
+use chip8_core::cpu::StepOutcome;
+use chip8_core::machine::Machine;
+use chip8_core::rewind::Rewind;
+
+pub const MAILBOX_SIZE: usize = 64;
+pub const MAILBOX_DATA_SIZE: usize = 52;
+const SEQUENCE_OFFSET: usize = 60;
+
+/// Prefixes every debugger packet so the host can separate them from log messages
+pub const REPLY_MAGIC: [u8; 3] = *b"DBG";
+
+/// The largest memory read served by a single request
+pub const MAX_READ: usize = 255;
+
+/// Set a breakpoint at address
+pub const COMMAND_SET_BREAKPOINT: u8 = b'B';
+/// Clear the breakpoint at address
+pub const COMMAND_CLEAR_BREAKPOINT: u8 = b'b';
+/// Execute a single instruction, replying with a stop
+pub const COMMAND_STEP: u8 = b'S';
+/// Resume running until a breakpoint or fault
+pub const COMMAND_CONTINUE: u8 = b'C';
+/// Stop running, replying with a stop
+pub const COMMAND_HALT: u8 = b'H';
+/// Read V0 - VF, I and PC
+pub const COMMAND_READ_REGISTERS: u8 = b'R';
+/// Write value to the register index (0 - 15 for V0 - VF or a REGISTER_ constant)
+pub const COMMAND_WRITE_REGISTER: u8 = b'W';
+/// Read length bytes of memory from address
+pub const COMMAND_READ_MEMORY: u8 = b'M';
+/// Write length bytes of data to memory at address
+pub const COMMAND_WRITE_MEMORY: u8 = b'w';
+/// Stop after an instruction writes to any of value bytes of memory from address
+pub const COMMAND_SET_WATCHPOINT: u8 = b'A';
+/// Clear the watchpoint starting at address
+pub const COMMAND_CLEAR_WATCHPOINT: u8 = b'a';
+/// Stop after an instruction makes a register condition true. The register is index (0 - 15 for
+/// V0 - VF or a REGISTER_ constant), the low 16 bits of value are compared against and bits 16 -
+/// 23 of value are a COMPARE_ constant.
+pub const COMMAND_SET_CONDITION: u8 = b'X';
+/// Clear the condition encoded as for COMMAND_SET_CONDITION
+pub const COMMAND_CLEAR_CONDITION: u8 = b'x';
+/// Switch the display pipeline to the DisplayMode encoded in value
+pub const COMMAND_SET_DISPLAY_MODE: u8 = b'D';
+/// Load ROMs with value as the random seed from now on, or seed them from the CSRs again if value
+/// is 0. Takes effect at the next load.
+pub const COMMAND_SET_SEED: u8 = b'r';
+/// Run translated code for hot blocks if value is 1, or only interpret if value is 0. Applies to
+/// the running machine and every machine loaded after it. Replies with an error if the JIT is not
+/// built in.
+pub const COMMAND_SET_JIT: u8 = b'J';
+/// Step the machine back value frames through the rewind history and pause it there, replying
+/// with the number of frames stepped back and the PC. The machine lands on a rewind record, so it
+/// can go back further than asked, or less if the history is shorter. Replies with an error if
+/// rewind is not running.
+pub const COMMAND_STEP_BACK: u8 = b'U';
+/// Sent without a request when the machine stops, with a STOP_ reason and the PC
+pub const REPLY_STOPPED: u8 = b'T';
+/// Sent in reply to a request that could not be served
+pub const REPLY_ERROR: u8 = b'E';
+
+pub const REGISTER_I: u8 = 16;
+pub const REGISTER_PC: u8 = 17;
+pub const REGISTER_DELAY: u8 = 18;
+pub const REGISTER_SOUND: u8 = 19;
+
+pub const COMPARE_EQUAL: u8 = 0;
+pub const COMPARE_NOT_EQUAL: u8 = 1;
+pub const COMPARE_LESS: u8 = 2;
+pub const COMPARE_GREATER: u8 = 3;
+
+pub const STOP_HALTED: u8 = 0;
+pub const STOP_STEP: u8 = 1;
+pub const STOP_BREAKPOINT: u8 = 2;
+pub const STOP_FAULT: u8 = 3;
+pub const STOP_EXITED: u8 = 4;
+pub const STOP_WATCHPOINT: u8 = 5;
+pub const STOP_CONDITION: u8 = 6;
+
+/// Decode the condition of a COMMAND_SET_CONDITION or COMMAND_CLEAR_CONDITION request
+fn decode_condition(index: u8, value: u32) -> Option<Condition> {
+    let register = match index {
+        0..=15 => Register::V(index),
+        REGISTER_I => Register::I,
+        REGISTER_PC => Register::Pc,
+        REGISTER_DELAY => Register::Delay,
+        REGISTER_SOUND => Register::Sound,
+        _ => return None,
+    };
+    let comparison = match (value >> 16) as u8 {
+        COMPARE_EQUAL => Comparison::Equal,
+        COMPARE_NOT_EQUAL => Comparison::NotEqual,
+        COMPARE_LESS => Comparison::Less,
+        COMPARE_GREATER => Comparison::Greater,
+        _ => return None,
+    };
+    Some(Condition::new(register, comparison, value as u16))
+}
+
+pub struct Debugger {
+    mailbox: *const [u8; MAILBOX_SIZE],
+
+    /// The sequence number of the last request served
+    sequence: u32,
+
+    /// True while the machine is stopped and should not be run
+    pub paused: bool,
+
+    /// The seed ROMs are loaded with, chosen by the host. None seeds each load from the CSRs.
+    pub seed: Option<u32>,
+
+    /// Whether machines run translated code, chosen by the host
+    #[cfg(feature = "jit")]
+    pub jit_enabled: bool,
+}
+
+impl Debugger {
+    /// Serve requests from the mailbox at address. Anything already in the mailbox is treated as
+    /// served.
+    pub fn new(address: usize) -> Self {
+        let mut debugger = Self {
+            mailbox: address as *const [u8; MAILBOX_SIZE],
+            sequence: 0,
+            paused: false,
+            seed: None,
+            #[cfg(feature = "jit")]
+            jit_enabled: true,
+        };
+        debugger.sequence = debugger.read_u32(SEQUENCE_OFFSET);
+        debugger
+    }
+
+    fn read_u8(&self, offset: usize) -> u8 {
+        unsafe { core::ptr::read_volatile((self.mailbox as *const u8).add(offset)) }
+    }
+
+    fn read_u16(&self, offset: usize) -> u16 {
+        u16::from_le_bytes([self.read_u8(offset), self.read_u8(offset + 1)])
+    }
+
+    fn read_u32(&self, offset: usize) -> u32 {
+        u32::from_le_bytes([
+            self.read_u8(offset),
+            self.read_u8(offset + 1),
+            self.read_u8(offset + 2),
+            self.read_u8(offset + 3),
+        ])
+    }
+
+    /// Send a reply packet with the given payload
+    fn reply(&self, command: u8, payload: &[u8]) {
+        let mut packet = [0; REPLY_MAGIC.len() + 1 + 4 + 2 * 16 + MAX_READ];
+        packet[..3].copy_from_slice(&REPLY_MAGIC);
+        packet[3] = command;
+        packet[4..8].copy_from_slice(&self.sequence.to_le_bytes());
+        packet[8..8 + payload.len()].copy_from_slice(payload);
+        send_dma(&packet[..8 + payload.len()]);
+    }
+
+    /// Report that the machine stopped, pausing it
+    pub fn stopped(&mut self, machine: &Machine, reason: u8) {
+        self.paused = true;
+        let pc = machine.cpu.registers.pc.to_le_bytes();
+        self.reply(REPLY_STOPPED, &[reason, pc[0], pc[1]]);
+    }
+
+    /// Report the outcome of running the machine, pausing it if it stopped at a breakpoint or
+    /// exited
+    pub fn report(&mut self, machine: &Machine, outcome: StepOutcome) {
+        match outcome {
+            StepOutcome::Breakpoint(Trigger::Breakpoint { .. }) => {
+                self.stopped(machine, STOP_BREAKPOINT)
+            }
+            StepOutcome::Breakpoint(Trigger::Watchpoint { .. }) => {
+                self.stopped(machine, STOP_WATCHPOINT)
+            }
+            StepOutcome::Breakpoint(Trigger::Condition { .. }) => {
+                self.stopped(machine, STOP_CONDITION)
+            }
+            StepOutcome::Exited => self.stopped(machine, STOP_EXITED),
+            _ => {}
+        }
+    }
+
+    /// Take the request in the mailbox if the host has written a new one, returning whether it has
+    fn take_request(&mut self) -> bool {
+        let sequence = self.read_u32(SEQUENCE_OFFSET);
+        if sequence == self.sequence {
+            return false;
+        }
+        self.sequence = sequence;
+        true
+    }
+
+    /// Serve a request that changes how the emulator runs rather than the machine, replying with
+    /// an error to anything else
+    fn serve_setting(&mut self, command: u8, value: u32, display: &mut Display) {
+        match command {
+            COMMAND_SET_DISPLAY_MODE => match DisplayMode::from_u8(value as u8) {
+                Some(mode) => {
+                    display.set_mode(mode);
+                    self.reply(command, &[]);
+                }
+                None => self.reply(REPLY_ERROR, &[command]),
+            },
+            COMMAND_SET_SEED => {
+                self.seed = (value != 0).then_some(value);
+                self.reply(command, &[]);
+            }
+            #[cfg(feature = "jit")]
+            COMMAND_SET_JIT if value <= 1 => {
+                self.jit_enabled = value == 1;
+                self.reply(command, &[]);
+            }
+            #[cfg(not(feature = "jit"))]
+            COMMAND_SET_JIT => self.reply(REPLY_ERROR, &[command]),
+            _ => self.reply(REPLY_ERROR, &[command]),
+        }
+    }
+
+    /// Serve a request if the host has written a new one to the mailbox while no game is running,
+    /// such as while the menu is shown. Requests that need a machine are refused.
+    pub fn poll_idle(&mut self, display: &mut Display) {
+        if self.take_request() {
+            self.serve_setting(self.read_u8(0), self.read_u32(4), display);
+        }
+    }
+
+    /// Serve a request if the host has written a new one to the mailbox
+    pub fn poll(
+        &mut self,
+        machine: &mut Machine,
+        display: &mut Display,
+        rewind: Option<&mut Rewind>,
+    ) {
+        if !self.take_request() {
+            return;
+        }
+
+        let command = self.read_u8(0);
+        let index = self.read_u8(1);
+        let address = self.read_u16(2);
+        let value = self.read_u32(4);
+
+        match command {
+            COMMAND_SET_BREAKPOINT => {
+                if machine.add_breakpoint(address) {
+                    self.reply(command, &[]);
+                } else {
+                    self.reply(REPLY_ERROR, &[command]);
+                }
+            }
+            COMMAND_CLEAR_BREAKPOINT => {
+                machine.remove_breakpoint(address);
+                self.reply(command, &[]);
+            }
+            COMMAND_SET_WATCHPOINT => {
+                if machine.add_watchpoint(address, value as u16) {
+                    self.reply(command, &[]);
+                } else {
+                    self.reply(REPLY_ERROR, &[command]);
+                }
+            }
+            COMMAND_CLEAR_WATCHPOINT => {
+                machine.remove_watchpoint(address);
+                self.reply(command, &[]);
+            }
+            COMMAND_SET_CONDITION => match decode_condition(index, value) {
+                Some(condition) if machine.add_condition(condition) => self.reply(command, &[]),
+                _ => self.reply(REPLY_ERROR, &[command]),
+            },
+            COMMAND_CLEAR_CONDITION => match decode_condition(index, value) {
+                Some(condition) => {
+                    machine.remove_condition(condition);
+                    self.reply(command, &[]);
+                }
+                None => self.reply(REPLY_ERROR, &[command]),
+            },
+            COMMAND_STEP => {
+                machine.resume();
+                let reason = match machine.step() {
+                    Ok(StepOutcome::Exited) => STOP_EXITED,
+                    Ok(_) => STOP_STEP,
+                    Err(fault) => {
+                        report_fault(&fault);
+                        STOP_FAULT
+                    }
+                };
+                self.stopped(machine, reason);
+            }
+            COMMAND_CONTINUE => {
+                machine.resume();
+                self.paused = false;
+                self.reply(command, &[]);
+            }
+            COMMAND_HALT => self.stopped(machine, STOP_HALTED),
+            COMMAND_STEP_BACK => match rewind {
+                Some(rewind) => {
+                    let frames = rewind.step_back_frames(machine, value).to_le_bytes();
+                    let pc = machine.cpu.registers.pc.to_le_bytes();
+                    self.paused = true;
+                    self.reply(
+                        command,
+                        &[frames[0], frames[1], frames[2], frames[3], pc[0], pc[1]],
+                    );
+                }
+                None => self.reply(REPLY_ERROR, &[command]),
+            },
+            COMMAND_READ_REGISTERS => {
+                let registers = &machine.cpu.registers;
+                let mut payload = [0; 16 + 2 + 2];
+                payload[..16].copy_from_slice(&registers.v);
+                payload[16..18].copy_from_slice(&registers.i.to_le_bytes());
+                payload[18..20].copy_from_slice(&registers.pc.to_le_bytes());
+                self.reply(command, &payload);
+            }
+            COMMAND_WRITE_REGISTER => {
+                let registers = &mut machine.cpu.registers;
+                match index {
+                    0..=15 => registers.v[index as usize] = value as u8,
+                    REGISTER_I => registers.i = value as u16,
+                    REGISTER_PC => registers.pc = value as u16,
+                    REGISTER_DELAY => registers.delay = value as u8,
+                    REGISTER_SOUND => registers.sound = value as u8,
+                    _ => return self.reply(REPLY_ERROR, &[command]),
+                }
+                self.reply(command, &[]);
+            }
+            COMMAND_READ_MEMORY => {
+                let len = index as usize;
+                if machine.memory.check_range(address as usize, len).is_err() {
+                    return self.reply(REPLY_ERROR, &[command]);
+                }
+
+                let mut payload = [0; MAX_READ];
+                for (offset, byte) in payload[..len].iter_mut().enumerate() {
+                    *byte = machine.memory.get(address as usize + offset);
+                }
+                self.reply(command, &payload[..len]);
+            }
+            COMMAND_WRITE_MEMORY => {
+                let len = index as usize;
+                if len > MAILBOX_DATA_SIZE
+                    || machine.memory.check_range(address as usize, len).is_err()
+                {
+                    return self.reply(REPLY_ERROR, &[command]);
+                }
+
+                for offset in 0..len {
+                    machine
+                        .memory
+                        .set(address as usize + offset, self.read_u8(8 + offset));
+                }
+                self.reply(command, &[]);
+            }
+            _ => {
+                self.serve_setting(command, value, display);
+                #[cfg(feature = "jit")]
+                machine.set_jit_enabled(self.jit_enabled);
+            }
+        }
+    }
+}
@@ -0,0 +1,99 @@
+//! The display pipeline. The machine always draws its 128x64 image into an off-screen buffer,
+//! which is downsampled into the 64x32 hardware framebuffer once per 60Hz frame, or after every
+//! change in Direct mode. CHIP-8 games erase and redraw sprites with XOR, so presenting once a
+//! frame also keeps sprites from going missing for part of a frame.
+use chip8_core::memory::{Framebuffer, LORES_SIZE, downsample};
+
+/// The hardware framebuffer the video out reads from, 64x32 packed as chip8_core::memory packs
+/// the framebuffer
+pub type HardwareFramebuffer = *mut [u8; LORES_SIZE];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisplayMode {
+    /// Present after every instruction that clears, scrolls or draws, so the video out shows each
+    /// sprite as it is erased and redrawn, flicker included
+    Direct,
+    /// Present the frame as drawn at the end of each frame
+    Buffered,
+    /// As Buffered, but each presented pixel is lit if it was lit in this frame or the last. A
+    /// sprite erased at the end of one frame and redrawn in the next does not blink.
+    Persist,
+}
+
+impl DisplayMode {
+    /// Decode the mode encoding used by the debugger
+    pub fn from_u8(mode: u8) -> Option<DisplayMode> {
+        match mode {
+            0 => Some(DisplayMode::Direct),
+            1 => Some(DisplayMode::Buffered),
+            2 => Some(DisplayMode::Persist),
+            _ => None,
+        }
+    }
+}
+
+pub struct Display {
+    mode: DisplayMode,
+    hardware: HardwareFramebuffer,
+    off_screen: Framebuffer,
+
+    /// The last presented frame before blending, blended in by Persist. Downsampling lights a
+    /// pixel if any pixel of its block is lit, so blending after downsampling gives the same image
+    /// as blending before.
+    previous: [u8; LORES_SIZE],
+}
+
+impl Display {
+    /// Present to the hardware framebuffer, using off_screen as the drawing buffer
+    pub fn new(hardware: HardwareFramebuffer, off_screen: Framebuffer, mode: DisplayMode) -> Self {
+        Self {
+            mode,
+            hardware,
+            off_screen,
+            previous: [0; LORES_SIZE],
+        }
+    }
+
+    /// The framebuffer a machine should draw into. It is fixed RAM that lives as long as the
+    /// program, so it can be handed to Machine::new.
+    pub fn target(&self) -> Framebuffer {
+        self.off_screen
+    }
+
+    /// Forget the last presented frame, called when a new machine is loaded so that nothing from
+    /// the previous game persists
+    pub fn reset(&mut self) {
+        self.previous = [0; LORES_SIZE];
+    }
+
+    /// Switch to a new mode and present the current image in it
+    pub fn set_mode(&mut self, mode: DisplayMode) {
+        self.mode = mode;
+        self.reset();
+        self.present();
+    }
+
+    /// Called after every instruction that changes the off-screen buffer, presenting it in Direct
+    /// mode
+    pub fn drawn(&mut self) {
+        if self.mode == DisplayMode::Direct {
+            self.present();
+        }
+    }
+
+    /// Show the frame the machine has drawn, called once at the end of each frame
+    pub fn present(&mut self) {
+        let (hardware, off_screen) = unsafe { (&mut *self.hardware, &*self.off_screen) };
+        match self.mode {
+            DisplayMode::Direct | DisplayMode::Buffered => downsample(off_screen, hardware),
+            DisplayMode::Persist => {
+                let mut frame = [0; LORES_SIZE];
+                downsample(off_screen, &mut frame);
+                for i in 0..LORES_SIZE {
+                    hardware[i] = frame[i] | self.previous[i];
+                    self.previous[i] = frame[i];
+                }
+            }
+        }
+    }
+}
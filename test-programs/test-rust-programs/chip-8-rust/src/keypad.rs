@@ -0,0 +1,35 @@
+use crate::util::send_dma;
+use chip8_core::container::KEYMAP_SIZE;
+
+/// Packets telling the host which keymap the loaded ROM wants start with this
+pub const KEYMAP_MAGIC: [u8; 3] = *b"KEY";
+
+/// The state of the 16 key hex keypad, written into RAM by the host with DMA packets. Bit n is
+/// set while key n is held. The host maps its own keyboard onto the keypad and writes the whole
+/// state after every key up or down event, so the region always holds the latest state and events
+/// do not queue.
+pub struct Keypad {
+    state: *const u16,
+}
+
+impl Keypad {
+    pub fn new(address: usize) -> Self {
+        Self {
+            state: address as *const u16,
+        }
+    }
+
+    /// The keys currently held
+    pub fn read(&self) -> u16 {
+        unsafe { core::ptr::read_volatile(self.state) }
+    }
+}
+
+/// Send the keymap of the ROM being loaded, so that the host keypad tool can map the host keys
+/// the game was written for
+pub fn send_keymap(keymap: &[u8; KEYMAP_SIZE]) {
+    let mut packet = [0; KEYMAP_MAGIC.len() + KEYMAP_SIZE];
+    packet[..3].copy_from_slice(&KEYMAP_MAGIC);
+    packet[3..].copy_from_slice(keymap);
+    send_dma(&packet);
+}
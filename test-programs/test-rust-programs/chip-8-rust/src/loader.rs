@@ -0,0 +1,62 @@
+//! Load ROMs sent by the host at runtime. The host writes a ROM container into the mailbox with
+//! DMA packets, then writes a new generation number to the first word. The generation is written
+//! last so that a change means the whole container has arrived.
+//!
+//! | Offset | Size                  | Field                                    |
+//! |--------|-----------------------|------------------------------------------|
+//! | 0      | 4                     | generation, u32 little-endian            |
+//! | 4      | ROM_MAILBOX_SIZE - 4  | ROM container, see chip8_core::container |
+use crate::roms::Rom;
+use crate::util::send_dma_l;
+
+pub const ROM_MAILBOX_SIZE: usize = 0x1000;
+const CONTAINER_OFFSET: usize = 4;
+
+pub struct RomLoader {
+    mailbox: *const u8,
+
+    /// The generation of the last ROM loaded
+    generation: u32,
+}
+
+impl RomLoader {
+    /// Watch the mailbox at address for new ROMs. Whatever is already in the mailbox is treated as
+    /// loaded.
+    pub fn new(address: usize) -> Self {
+        let mut loader = Self {
+            mailbox: address as *const u8,
+            generation: 0,
+        };
+        loader.generation = loader.read_generation();
+        loader
+    }
+
+    fn read_generation(&self) -> u32 {
+        unsafe { core::ptr::read_volatile(self.mailbox as *const u32) }
+    }
+
+    /// Return the ROM in the mailbox if the host has sent a new one since the last call. A ROM
+    /// that fails to parse is reported over DMA and ignored.
+    pub fn poll(&mut self) -> Option<Rom<'static>> {
+        let generation = self.read_generation();
+        if generation == self.generation {
+            return None;
+        }
+        self.generation = generation;
+
+        let image = unsafe {
+            core::slice::from_raw_parts(
+                self.mailbox.add(CONTAINER_OFFSET),
+                ROM_MAILBOX_SIZE - CONTAINER_OFFSET,
+            )
+        };
+
+        match Rom::parse(image) {
+            Ok(rom) => Some(rom),
+            Err(_) => {
+                send_dma_l("Bad ROM");
+                None
+            }
+        }
+    }
+}
@@ -1,15 +1,39 @@
 #![no_std]
 #![no_main]
-mod cpu;
-mod machine;
-mod memory;
-mod rand;
+mod csr;
+mod debugger;
+mod display;
+mod keypad;
+mod loader;
+mod menu;
+mod recording;
+mod roms;
+mod snapshot;
+mod sound;
+mod stack;
+mod timing;
 mod util;
 
+use chip8_core::cpu::StepOutcome;
+use chip8_core::cycles::VIP_INTERPRETER_CYCLES_PER_FRAME;
+use chip8_core::machine::Machine;
+use chip8_core::memory::Framebuffer;
+#[cfg(feature = "benchmark")]
+use chip8_core::rand::DEFAULT_SEED;
+use chip8_core::replay::{Recorder, Replay};
+use chip8_core::rewind::Rewind;
+use chip8_core::snapshot::SNAPSHOT_MAGIC;
 use core::{arch::global_asm, panic::PanicInfo};
-use machine::Machine;
-use memory::{Framebuffer, RawMemory};
-use util::send_dma_l;
+use debugger::{Debugger, STOP_FAULT};
+use display::{Display, DisplayMode, HardwareFramebuffer};
+use keypad::Keypad;
+use loader::RomLoader;
+use menu::{Choice, RESET_COMBO};
+use recording::ReplayLoader;
+use roms::Rom;
+use sound::Sound;
+use timing::{DEFAULT_INSTRUCTIONS_PER_SECOND, FRAMES_PER_SECOND, Timing};
+use util::{report_fault, send_dma_l};
 
 global_asm!(include_str!("entry.s"));
 
@@ -36,30 +60,363 @@ fn panic(info: &PanicInfo) -> ! {
     loop {}
 }
 
+/// Instructions executed per benchmark run
+#[cfg(feature = "benchmark")]
+const BENCHMARK_STEPS: u32 = 20000;
+
+/// The hart clock used to convert cycles into instructions per second
+#[cfg(feature = "benchmark")]
+const HART_CLOCK_HZ: u32 = 100_000_000;
+
+/// Run the last bundled ROM for BENCHMARK_STEPS instructions and report the cycles per
+/// instruction and instructions per second over DMA. Gives back memory when done.
+#[cfg(feature = "benchmark")]
+fn benchmark(
+    memory: &'static mut [u8],
+    frame_buffer: Framebuffer,
+    cache: bool,
+) -> &'static mut [u8] {
+    let rom = Rom::parse(roms::ROMS[roms::ROMS.len() - 1]).unwrap();
+    // The off-screen buffer is fixed RAM that lives as long as the program
+    let mut machine = unsafe { load(&rom, memory, frame_buffer, DEFAULT_SEED) };
+    machine.memory.set_decode_cache_enabled(cache);
+
+    let start = csr::read_cycle();
+    let mut steps: u32 = 0;
+    while steps < BENCHMARK_STEPS {
+        match machine.step() {
+            Ok(StepOutcome::Executed | StepOutcome::WaitingForDisplay) => steps += 1,
+            Ok(StepOutcome::WaitingForKey | StepOutcome::Breakpoint(_)) => {}
+            Ok(StepOutcome::Exited) | Err(_) => break,
+        }
+    }
+    let cycles = csr::read_cycle().wrapping_sub(start);
+
+    send_dma_l(if cache {
+        "Decode cache enabled"
+    } else {
+        "Decode cache disabled"
+    });
+    let cycles_per_instruction = cycles / steps.max(1);
+    let mut buffer = itoa::Buffer::new();
+    send_dma_l(buffer.format(cycles_per_instruction));
+    let mut buffer = itoa::Buffer::new();
+    send_dma_l(buffer.format(HART_CLOCK_HZ / cycles_per_instruction.max(1)));
+    machine.into_memory()
+}
+
+/// Clear memory, load a ROM and create a machine configured to run it, with its random number
+/// generator started from seed and VIP timing if the ROM asks for it. The ROM's keymap is sent to
+/// the host keypad tool. A ROM too large for memory is reported and the machine is created
+/// halted, so it exits before running anything and the menu is shown again.
+///
+/// # Safety
+///
+/// frame_buffer must be valid for reads and writes for as long as the machine is used, see
+/// Machine::new.
+unsafe fn load(
+    rom: &Rom,
+    memory: &'static mut [u8],
+    frame_buffer: Framebuffer,
+    seed: u32,
+) -> Machine {
+    send_dma_l("Loading");
+    send_dma_l(rom.title);
+    keypad::send_keymap(&rom.keymap);
+    let too_large = rom.load(memory).is_err();
+    if too_large {
+        send_dma_l("ROM too large");
+    }
+    let mut machine = unsafe { Machine::new(memory, frame_buffer, rom.platform, rom.quirks) };
+    machine.cpu.registers.halted = too_large;
+    machine.seed(seed);
+    machine.cpu.vip_timing = rom.vip_timing;
+    machine
+}
+
+/// Serve the ROM and replay mailboxes. A ROM sent by the host is chosen to run next, and a
+/// recording restarts current_rom with the recorded seed, replaying it. A recording with no ROM
+/// to replay it on is reported and ignored.
+fn poll_host(
+    loader: &mut RomLoader,
+    replay_loader: &mut ReplayLoader,
+    current_rom: Option<Rom<'static>>,
+) -> Option<Choice> {
+    let mut choice = loader.poll().map(|rom| Choice {
+        rom,
+        replay_seed: None,
+    });
+    if let Some(seed) = replay_loader.poll() {
+        match current_rom {
+            Some(rom) => {
+                choice = Some(Choice {
+                    rom,
+                    replay_seed: Some(seed),
+                })
+            }
+            None => send_dma_l("No ROM to replay"),
+        }
+    }
+    choice
+}
+
+/// The machine memory. ROMs are bundled without padding and copied here to run.
+const MACHINE_MEMORY_ADDRESS: usize = 0x9000;
+const MACHINE_MEMORY_SIZE: usize = 0x1000;
+
+/// A snapshot image written to this address before reset is restored instead of booting the ROM.
+/// Its magic is cleared once restored so that it is only restored once.
+const SNAPSHOT_ADDRESS: usize = 0xA000;
+const SNAPSHOT_MAX_SIZE: usize = 0x2000;
+
+/// The 64x32 hardware framebuffer the video out reads from
+const FRAMEBUFFER_ADDRESS: usize = 0x8000;
+
+/// The machine draws its 128x64 image here and it is downsampled into the hardware framebuffer
+/// once a frame. It sits between the hardware framebuffer and the debugger mailbox.
+const OFF_SCREEN_ADDRESS: usize = 0x8400;
+
+/// The host writes remote debugger requests here over DMA
+const DEBUG_MAILBOX_ADDRESS: usize = 0x8800;
+
+/// The host writes the keypad state here over DMA, after the debugger mailbox. See keypad.rs.
+const KEYPAD_ADDRESS: usize = 0x8840;
+
+/// The host writes recordings to replay here over DMA, after the keypad. See recording.rs.
+const REPLAY_MAILBOX_ADDRESS: usize = 0x8880;
+
+/// The host writes ROMs to load at runtime here over DMA, between the stack and the framebuffer
+const ROM_MAILBOX_ADDRESS: usize = 0x7000;
+
+/// Rewind history is kept in RAM above the snapshot image. The budget covers a shadow copy of
+/// memory and the framebuffer, and the rest holds records taken every REWIND_INTERVAL_FRAMES.
+const REWIND_ADDRESS: usize = 0xC000;
+const REWIND_BUDGET: usize = 0x3800;
+const REWIND_INTERVAL_FRAMES: u32 = 10;
+
+/// Recordings are copied here out of the replay mailbox to be replayed, after the rewind history
+const REPLAY_RECORDING_ADDRESS: usize = 0xF800;
+
 #[unsafe(no_mangle)]
 extern "C" fn main() -> () {
+    stack::paint();
     send_dma_l("Starting up");
-    let program: &[u8; 4096] = include_bytes!("space_invaders_padded.ch8");
-    let program = program.as_ptr() as RawMemory;
+    // Machine memory is only ever reached through this slice, which is handed from each machine
+    // to the next as ROMs are loaded
+    let memory = unsafe {
+        core::slice::from_raw_parts_mut(MACHINE_MEMORY_ADDRESS as *mut u8, MACHINE_MEMORY_SIZE)
+    };
+    let frame_buffer = FRAMEBUFFER_ADDRESS as HardwareFramebuffer;
+    let off_screen = OFF_SCREEN_ADDRESS as Framebuffer;
+    send_dma_l("Initialized framebuffer");
 
-    // Print out the program location in the ROM
-    send_dma_l("Transmitting program location");
-    let mut buffer = itoa::Buffer::new();
-    send_dma_l(buffer.format(program as usize));
+    #[cfg(feature = "benchmark")]
+    let memory = {
+        let memory = benchmark(memory, off_screen, false);
+        let memory = benchmark(memory, off_screen, true);
+        send_dma_l("Stack used");
+        let mut buffer = itoa::Buffer::new();
+        send_dma_l(buffer.format(stack::used()));
+        memory
+    };
 
-    send_dma_l("Got handle on program");
-    let frame_buffer: Framebuffer = (0x8000) as Framebuffer;
-    send_dma_l("Initialized framebuffer");
-    let mut machine = Machine::new(program, frame_buffer);
+    let mut display = Display::new(frame_buffer, off_screen, DisplayMode::Direct);
+    let keypad = Keypad::new(KEYPAD_ADDRESS);
+    let mut loader = RomLoader::new(ROM_MAILBOX_ADDRESS);
+    let replay_recording = unsafe {
+        core::slice::from_raw_parts_mut(
+            REPLAY_RECORDING_ADDRESS as *mut u8,
+            recording::MAX_RECORDING_SIZE,
+        )
+    };
+    let mut replay_loader = ReplayLoader::new(REPLAY_MAILBOX_ADDRESS, replay_recording);
+    let mut debugger = Debugger::new(DEBUG_MAILBOX_ADDRESS);
+    let mut timing = Timing::new(DEFAULT_INSTRUCTIONS_PER_SECOND);
+    let snapshot_image =
+        unsafe { core::slice::from_raw_parts_mut(SNAPSHOT_ADDRESS as *mut u8, SNAPSHOT_MAX_SIZE) };
+
+    // The ROM running, kept so that a replay can reload it, and the recording of its input. A
+    // restored snapshot has neither, the seed and input that led up to it are not known.
+    let mut current_rom = None;
+    let mut recorder = None;
+
+    let mut machine = if snapshot_image.starts_with(&SNAPSHOT_MAGIC) {
+        send_dma_l("Restoring snapshot");
+        match unsafe { chip8_core::snapshot::restore(snapshot_image, memory, display.target()) } {
+            Ok(machine) => {
+                // Clear the magic so that a later reset boots the menu rather than going back to
+                // the snapshot
+                snapshot_image[..SNAPSHOT_MAGIC.len()].fill(0);
+                machine
+            }
+            Err(_) => {
+                send_dma_l("Bad snapshot");
+                return;
+            }
+        }
+    } else {
+        // Nothing has run yet, so there is no ROM for a recording to replay on
+        let Choice { rom, .. } = menu::choose(frame_buffer, &keypad, &mut timing, 0, || {
+            debugger.poll_idle(&mut display);
+            poll_host(&mut loader, &mut replay_loader, None)
+        });
+        timing.set_instructions_per_second(rom.instructions_per_second());
+        let seed = debugger.seed.unwrap_or_else(recording::hardware_seed);
+        let machine = unsafe { load(&rom, memory, display.target(), seed) };
+        recording::send_header(seed);
+        recorder = Some(Recorder::new());
+        current_rom = Some(rom);
+        machine
+    };
+    // The host may have turned translation off from the menu
+    #[cfg(feature = "jit")]
+    machine.set_jit_enabled(debugger.jit_enabled);
     send_dma_l("Initialized, stepping");
+
+    let rewind_storage =
+        unsafe { core::slice::from_raw_parts_mut(REWIND_ADDRESS as *mut u8, REWIND_BUDGET) };
+    let mut rewind = Rewind::new(rewind_storage, &mut machine, REWIND_INTERVAL_FRAMES).ok();
+    if rewind.is_none() {
+        send_dma_l("Rewind budget too small");
+    }
+
+    let mut replay: Option<Replay> = None;
+    let mut sound = Sound::new();
+    let mut frame: u32 = 0;
+    let mut worst_overrun_ns: u64 = 0;
+    let mut reported_overruns: u32 = 0;
+
     loop {
-        machine.step();
-        /* 
-        for _i in 0..5000 {
-            unsafe {
-                let x = 0x9000 as *mut u8;
-                core::ptr::write_volatile(x, 0);
+        debugger.poll(&mut machine, &mut display, rewind.as_mut());
+
+        // Keys are sampled once a frame. A key pressed and released within a frame is missed. A
+        // replay drives the keypad in place of the host until it runs out of events.
+        let keys = keypad.read();
+        if replay.is_none() {
+            machine.set_keys(keys);
+        }
+        let mut return_to_menu = keys & RESET_COMBO == RESET_COMBO;
+
+        if !debugger.paused {
+            if let Some(playing) = replay.as_mut() {
+                match replay_loader.recording() {
+                    Some(recording) => {
+                        playing.apply(&recording, &mut machine);
+                        if playing.finished(&recording) {
+                            send_dma_l("Replay finished");
+                            replay = None;
+                        }
+                    }
+                    // A recording that failed to parse has replaced the one being replayed
+                    None => replay = None,
+                }
+            }
+
+            // Keys changed while the debugger has the machine paused are recorded at the next
+            // frame, so a key pressed and released while paused is missed
+            if let Some(recorder) = recorder.as_mut() {
+                recorder.record(&machine, recording::send_event);
             }
-        } */
+
+            // With VIP timing the frame budget is in VIP machine cycles rather than instructions
+            let budget = if machine.cpu.vip_timing {
+                VIP_INTERPRETER_CYCLES_PER_FRAME as usize
+            } else {
+                timing.instructions_for_frame()
+            };
+
+            match machine.run_frame_drawing(budget, || display.drawn()) {
+                Ok(StepOutcome::Exited) => {
+                    send_dma_l("Exited");
+                    return_to_menu = true;
+                }
+                Ok(outcome) => debugger.report(&machine, outcome),
+                Err(fault) => {
+                    report_fault(&fault);
+
+                    // Send the machine state so the fault can be reproduced off the FPGA, followed
+                    // by the oldest state in the rewind history. The machine is left paused there
+                    // so the lead up to the fault can be stepped through with the debugger.
+                    snapshot::save(&machine);
+                    if let Some(rewind) = rewind.as_mut() {
+                        while rewind.step_back(&mut machine) {}
+                        snapshot::save(&machine);
+                    }
+                    debugger.stopped(&machine, STOP_FAULT);
+                }
+            }
+
+            if let Some(rewind) = rewind.as_mut() {
+                rewind.frame(&mut machine);
+            }
+        }
+
+        // The timers do not run while the debugger has the machine paused, so neither does the
+        // buzzer
+        sound.update(
+            machine.sound() && !debugger.paused,
+            machine.cpu.registers.pitch,
+        );
+
+        // A ROM or recording sent by the host replaces the running game. The menu keeps serving
+        // the host while it is shown.
+        let mut next = poll_host(&mut loader, &mut replay_loader, current_rom);
+        if next.is_none() && return_to_menu {
+            next = Some(menu::choose(
+                frame_buffer,
+                &keypad,
+                &mut timing,
+                keypad.read(),
+                || {
+                    debugger.poll_idle(&mut display);
+                    poll_host(&mut loader, &mut replay_loader, current_rom)
+                },
+            ));
+        }
+
+        if let Some(Choice { rom, replay_seed }) = next {
+            let seed = replay_seed
+                .or(debugger.seed)
+                .unwrap_or_else(recording::hardware_seed);
+            timing.set_instructions_per_second(rom.instructions_per_second());
+            machine = unsafe { load(&rom, machine.into_memory(), display.target(), seed) };
+            #[cfg(feature = "jit")]
+            machine.set_jit_enabled(debugger.jit_enabled);
+            recording::send_header(seed);
+            recorder = Some(Recorder::new());
+            replay = replay_seed.map(|_| Replay::new());
+            current_rom = Some(rom);
+            display.reset();
+            if let Some(rewind) = rewind.as_mut() {
+                rewind.reset(&mut machine);
+            }
+            debugger.paused = false;
+            continue;
+        }
+
+        display.present();
+
+        // Past the end of the image the stack overwrites code and data, so stop before any of it
+        // runs
+        if stack::overflowed() {
+            send_dma_l("Stack overflow");
+            return;
+        }
+
+        if let Some(overrun_ns) = timing.end_frame() {
+            worst_overrun_ns = worst_overrun_ns.max(overrun_ns);
+        }
+
+        // Report overruns at most once a second so that a slow hart is not slowed further by DMA
+        frame += 1;
+        if frame.is_multiple_of(FRAMES_PER_SECOND) && timing.overruns != reported_overruns {
+            send_dma_l("Frame overruns");
+            let mut buffer = itoa::Buffer::new();
+            send_dma_l(buffer.format(timing.overruns - reported_overruns));
+            let mut buffer = itoa::Buffer::new();
+            send_dma_l(buffer.format(worst_overrun_ns));
+            reported_overruns = timing.overruns;
+            worst_overrun_ns = 0;
+        }
     }
 }
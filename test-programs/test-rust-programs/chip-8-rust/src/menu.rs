@@ -0,0 +1,169 @@
+//! The boot menu. Lists the bundled ROMs in the 64x32 hardware framebuffer and waits for the
+//! player to pick one by pressing its number on the keypad.
+use crate::display::HardwareFramebuffer;
+use crate::keypad::Keypad;
+use crate::roms::{ROMS, Rom};
+use crate::timing::Timing;
+use chip8_core::memory::{LORES_HEIGHT, LORES_SIZE, LORES_WIDTH};
+
+/// Holding 0 and F together returns to the menu from a running game
+pub const RESET_COMBO: u16 = (1 << 0x0) | (1 << 0xF);
+
+/// Glyphs are 3x5 pixels drawn on a 4x6 grid, giving five lines of sixteen characters
+const GLYPH_WIDTH: usize = 3;
+const GLYPH_HEIGHT: usize = 5;
+const CHAR_WIDTH: usize = GLYPH_WIDTH + 1;
+const LINE_HEIGHT: usize = GLYPH_HEIGHT + 1;
+
+/// A 3x5 font for 0-9 then A-Z. Each row is 3 bits with the leftmost pixel in the high bit.
+const FONT: [[u8; GLYPH_HEIGHT]; 36] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b001, 0b010, 0b010],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+    [0b010, 0b101, 0b111, 0b101, 0b101],
+    [0b110, 0b101, 0b110, 0b101, 0b110],
+    [0b011, 0b100, 0b100, 0b100, 0b011],
+    [0b110, 0b101, 0b101, 0b101, 0b110],
+    [0b111, 0b100, 0b110, 0b100, 0b111],
+    [0b111, 0b100, 0b110, 0b100, 0b100],
+    [0b011, 0b100, 0b101, 0b101, 0b011],
+    [0b101, 0b101, 0b111, 0b101, 0b101],
+    [0b111, 0b010, 0b010, 0b010, 0b111],
+    [0b001, 0b001, 0b001, 0b101, 0b010],
+    [0b101, 0b101, 0b110, 0b101, 0b101],
+    [0b100, 0b100, 0b100, 0b100, 0b111],
+    [0b101, 0b111, 0b111, 0b101, 0b101],
+    [0b110, 0b101, 0b101, 0b101, 0b101],
+    [0b010, 0b101, 0b101, 0b101, 0b010],
+    [0b110, 0b101, 0b110, 0b100, 0b100],
+    [0b010, 0b101, 0b101, 0b110, 0b011],
+    [0b110, 0b101, 0b110, 0b101, 0b101],
+    [0b011, 0b100, 0b010, 0b001, 0b110],
+    [0b111, 0b010, 0b010, 0b010, 0b010],
+    [0b101, 0b101, 0b101, 0b101, 0b111],
+    [0b101, 0b101, 0b101, 0b101, 0b010],
+    [0b101, 0b101, 0b111, 0b111, 0b101],
+    [0b101, 0b101, 0b010, 0b101, 0b101],
+    [0b101, 0b101, 0b010, 0b010, 0b010],
+    [0b111, 0b001, 0b010, 0b100, 0b111],
+];
+
+const HYPHEN: [u8; GLYPH_HEIGHT] = [0, 0, 0b111, 0, 0];
+
+/// The glyph for an ASCII character. Anything without a glyph is drawn as a space.
+fn glyph(c: u8) -> [u8; GLYPH_HEIGHT] {
+    match c {
+        b'0'..=b'9' => FONT[(c - b'0') as usize],
+        b'A'..=b'Z' => FONT[(c - b'A') as usize + 10],
+        b'a'..=b'z' => FONT[(c - b'a') as usize + 10],
+        b'-' => HYPHEN,
+        _ => [0; GLYPH_HEIGHT],
+    }
+}
+
+fn set_pixel(frame_buffer: HardwareFramebuffer, x: usize, y: usize) {
+    let fb_idx = (y * LORES_WIDTH) + x;
+    unsafe {
+        (*frame_buffer)[fb_idx >> 3] |= 1 << (fb_idx & 0b111);
+    }
+}
+
+/// Draw text with its top left corner at (x, y) in framebuffer coordinates. Text is cut off at
+/// the last character that fits, and lines that do not fit are not drawn.
+fn draw_text(frame_buffer: HardwareFramebuffer, x: usize, y: usize, text: &[u8]) {
+    if y + GLYPH_HEIGHT > LORES_HEIGHT {
+        return;
+    }
+
+    for (index, c) in text.iter().enumerate() {
+        let left = x + index * CHAR_WIDTH;
+        if left + GLYPH_WIDTH > LORES_WIDTH {
+            return;
+        }
+
+        for (row, bits) in glyph(*c).iter().enumerate() {
+            for column in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - column)) != 0 {
+                    set_pixel(frame_buffer, left + column, y + row);
+                }
+            }
+        }
+    }
+}
+
+/// Clear the framebuffer and draw the title, the ROM list and how to get back to the menu. ROMs
+/// past the fourth push the reminder off the bottom of the screen.
+fn draw(frame_buffer: HardwareFramebuffer) {
+    unsafe {
+        for i in 0..LORES_SIZE {
+            (*frame_buffer)[i] = 0;
+        }
+    }
+
+    draw_text(frame_buffer, 1, 1, b"CHIP-8");
+    for (index, image) in ROMS.iter().enumerate() {
+        let y = 1 + (index + 1) * LINE_HEIGHT;
+        let title = Rom::parse(image).map_or("BAD ROM", |rom| rom.title);
+        draw_text(frame_buffer, 1, y, &[b'1' + index as u8]);
+        draw_text(frame_buffer, 1 + 2 * CHAR_WIDTH, y, title.as_bytes());
+    }
+    draw_text(
+        frame_buffer,
+        1,
+        1 + (ROMS.len() + 1) * LINE_HEIGHT,
+        b"0 AND F MENU",
+    );
+}
+
+/// A ROM to run, with the seed of the recording to replay on it if the host sent one
+#[derive(Clone, Copy)]
+pub struct Choice {
+    pub rom: Rom<'static>,
+    pub replay_seed: Option<u32>,
+}
+
+/// Draw the menu and wait for a ROM to be picked. Keys are checked once per frame. Keys in held
+/// do not select a ROM until they have been released, so that a key held down when a game ends is
+/// not taken as a choice. ROMs that fail to parse cannot be picked.
+///
+/// poll is called once a frame to serve the host's mailboxes while the menu is shown. A ROM or
+/// recording it returns is chosen as if it had been picked.
+pub fn choose(
+    frame_buffer: HardwareFramebuffer,
+    keypad: &Keypad,
+    timing: &mut Timing,
+    held: u16,
+    mut poll: impl FnMut() -> Option<Choice>,
+) -> Choice {
+    draw(frame_buffer);
+    let mut previous = held;
+
+    loop {
+        if let Some(choice) = poll() {
+            return choice;
+        }
+
+        let keys = keypad.read();
+        let pressed = keys & !previous;
+        previous = keys;
+
+        if let Some(rom) = (0..ROMS.len())
+            .filter(|index| pressed & (1 << (index + 1)) != 0)
+            .find_map(|index| Rom::parse(ROMS[index]).ok())
+        {
+            return Choice {
+                rom,
+                replay_seed: None,
+            };
+        }
+
+        timing.end_frame();
+    }
+}
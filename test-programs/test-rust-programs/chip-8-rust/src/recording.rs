@@ -0,0 +1,120 @@
+//! Input recording and replay, see chip8_core::replay for the recording format. Every ROM is
+//! loaded with a seed for the random number generator, from the time and cycle CSRs unless the
+//! host has chosen one with the debugger. The recording is streamed to the host as it is made, in
+//! DMA packets of INPUT_MAGIC followed by a piece of the recording: the header when a ROM is
+//! loaded, then an event each time a key changes. Joining the pieces after the last header gives
+//! the recording of the running game.
+//!
+//! The host replays a recording by writing it into the replay mailbox with DMA packets, then
+//! writing a new generation number to the first word. The recording is copied out of the mailbox,
+//! so the host can write the next one while it plays, and the running ROM is reloaded with the
+//! recording's seed. The recording then drives the keypad until it runs out of events.
+//!
+//! | Offset | Size                     | Field                                  |
+//! |--------|--------------------------|----------------------------------------|
+//! | 0      | 4                        | generation, u32 little-endian          |
+//! | 4      | 4                        | recording length, u32 little-endian    |
+//! | 8      | REPLAY_MAILBOX_SIZE - 8  | recording                              |
+use crate::csr::{read_cycle, read_time_ns};
+use crate::util::{send_dma, send_dma_l};
+use chip8_core::replay::{self, EVENT_SIZE, HEADER_SIZE, InputEvent, Recording};
+
+pub const INPUT_MAGIC: [u8; 3] = *b"INP";
+
+pub const REPLAY_MAILBOX_SIZE: usize = 0x780;
+const LENGTH_OFFSET: usize = 4;
+const RECORDING_OFFSET: usize = 8;
+
+/// The largest recording the mailbox holds, and the size of the copy ReplayLoader replays from
+pub const MAX_RECORDING_SIZE: usize = REPLAY_MAILBOX_SIZE - RECORDING_OFFSET;
+
+/// A seed that differs between boots, for when the host has not chosen one
+pub fn hardware_seed() -> u32 {
+    read_time_ns() as u32 ^ read_cycle()
+}
+
+/// Send the header that starts the recording of a machine loaded with seed
+pub fn send_header(seed: u32) {
+    let mut packet = [0; INPUT_MAGIC.len() + HEADER_SIZE];
+    packet[..3].copy_from_slice(&INPUT_MAGIC);
+    packet[3..].copy_from_slice(&replay::header(seed));
+    send_dma(&packet);
+}
+
+/// Send an event of the recording, passed to Recorder::record
+pub fn send_event(event: InputEvent) {
+    let mut packet = [0; INPUT_MAGIC.len() + EVENT_SIZE];
+    packet[..3].copy_from_slice(&INPUT_MAGIC);
+    packet[3..].copy_from_slice(&event.to_bytes());
+    send_dma(&packet);
+}
+
+pub struct ReplayLoader {
+    mailbox: *const u8,
+
+    /// The last recording received, copied out of the mailbox so that the host writing the next
+    /// one cannot change it while it is replayed
+    recording: &'static mut [u8],
+    len: usize,
+
+    /// The generation of the last recording replayed
+    generation: u32,
+}
+
+impl ReplayLoader {
+    /// Watch the mailbox at address for recordings to replay, copying them into recording, which
+    /// must hold MAX_RECORDING_SIZE bytes. Whatever is already in the mailbox is treated as
+    /// replayed.
+    pub fn new(address: usize, recording: &'static mut [u8]) -> Self {
+        let mut loader = Self {
+            mailbox: address as *const u8,
+            recording,
+            len: 0,
+            generation: 0,
+        };
+        loader.generation = loader.read_u32(0);
+        loader
+    }
+
+    fn read_u32(&self, offset: usize) -> u32 {
+        unsafe { core::ptr::read_volatile(self.mailbox.add(offset) as *const u32) }
+    }
+
+    /// Copy the recording in the mailbox if the host has sent a new one since the last call,
+    /// returning its seed. The copy replaces the last recording, so a replay of that is over. A
+    /// recording that fails to parse is reported over DMA and leaves no recording.
+    pub fn poll(&mut self) -> Option<u32> {
+        let generation = self.read_u32(0);
+        if generation == self.generation {
+            return None;
+        }
+        self.generation = generation;
+        self.len = 0;
+
+        let length = self.read_u32(LENGTH_OFFSET) as usize;
+        if length > MAX_RECORDING_SIZE {
+            send_dma_l("Recording too large");
+            return None;
+        }
+
+        for (offset, byte) in self.recording[..length].iter_mut().enumerate() {
+            *byte =
+                unsafe { core::ptr::read_volatile(self.mailbox.add(RECORDING_OFFSET + offset)) };
+        }
+        self.len = length;
+
+        match self.recording() {
+            Some(recording) => Some(recording.seed),
+            None => {
+                send_dma_l("Bad recording");
+                self.len = 0;
+                None
+            }
+        }
+    }
+
+    /// The last recording received, if it parsed
+    pub fn recording(&self) -> Option<Recording<'_>> {
+        Recording::parse(&self.recording[..self.len]).ok()
+    }
+}
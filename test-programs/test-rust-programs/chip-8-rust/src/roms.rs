@@ -0,0 +1,57 @@
+//! The ROMs bundled into the image, listed by the boot menu. Each ROM is a container generated by
+//! build.rs from roms/roms.txt.
+use crate::timing::FRAMES_PER_SECOND;
+use chip8_core::container::{Container, ContainerError, FLAG_VIP_TIMING, KEYMAP_SIZE};
+use chip8_core::cpu::Platform;
+use chip8_core::memory::PROGRAM_ADDRESS;
+use chip8_core::quirks::Quirks;
+
+// Defines ROMS, the container image of every bundled ROM in menu order
+include!(concat!(env!("OUT_DIR"), "/roms.rs"));
+
+/// A ROM and the settings it should be run with
+#[derive(Clone, Copy)]
+pub struct Rom<'a> {
+    pub title: &'a str,
+    pub platform: Platform,
+    pub quirks: Quirks,
+    pub instructions_per_frame: u16,
+    /// Run with COSMAC VIP timing rather than instructions_per_frame
+    pub vip_timing: bool,
+    /// The host keyboard key for each keypad key, sent to the host when the ROM is loaded
+    pub keymap: [u8; KEYMAP_SIZE],
+    /// The program, loaded at PROGRAM_ADDRESS
+    pub program: &'a [u8],
+}
+
+impl<'a> Rom<'a> {
+    /// Parse and check a ROM container
+    pub fn parse(image: &'a [u8]) -> Result<Self, ContainerError> {
+        let container = Container::parse(image)?;
+        Ok(Self {
+            title: container.title,
+            platform: Platform::from_u8(container.platform).ok_or(ContainerError::Invalid)?,
+            quirks: Quirks::from_bits(container.quirks),
+            instructions_per_frame: container.instructions_per_frame,
+            vip_timing: container.flags & FLAG_VIP_TIMING != 0,
+            keymap: container.keymap,
+            program: container.program,
+        })
+    }
+
+    pub fn instructions_per_second(&self) -> u32 {
+        self.instructions_per_frame as u32 * FRAMES_PER_SECOND
+    }
+
+    /// Clear memory and copy the program to PROGRAM_ADDRESS. Memory is cleared even if the program
+    /// does not fit, so nothing of the last program is left behind.
+    pub fn load(&self, memory: &mut [u8]) -> Result<(), ContainerError> {
+        memory.fill(0);
+        if PROGRAM_ADDRESS + self.program.len() > memory.len() {
+            return Err(ContainerError::TooLarge);
+        }
+
+        memory[PROGRAM_ADDRESS..PROGRAM_ADDRESS + self.program.len()].copy_from_slice(self.program);
+        Ok(())
+    }
+}
@@ -0,0 +1,30 @@
+//! Sends snapshots of the machine to the host over DMA. The snapshot format is in
+//! chip8_core::snapshot.
+use crate::util::{send_dma, send_dma_l};
+use chip8_core::machine::Machine;
+
+/// Snapshots are streamed in DMA packets of at most this many bytes
+const CHUNK_SIZE: usize = 256;
+
+/// Stream a snapshot of machine over DMA. The snapshot is preceded by a SNAPSHOT string packet so
+/// the host can find it in the DMA log.
+pub fn save(machine: &Machine) {
+    let mut buffer = [0; CHUNK_SIZE];
+    let mut len = 0;
+
+    send_dma_l("SNAPSHOT");
+    chip8_core::snapshot::save(machine, |bytes| {
+        for byte in bytes {
+            buffer[len] = *byte;
+            len += 1;
+            if len == CHUNK_SIZE {
+                send_dma(&buffer);
+                len = 0;
+            }
+        }
+    });
+
+    if len > 0 {
+        send_dma(&buffer[..len]);
+    }
+}
@@ -0,0 +1,43 @@
+//! Sound output. The machine's buzzer is on while the sound timer is non-zero. There is no audio
+//! peripheral on the board, so each time the buzzer starts or stops an event is sent over DMA for
+//! a host tool to play:
+//!
+//! | Field  | Size | Notes                                                  |
+//! |--------|------|--------------------------------------------------------|
+//! | magic  | 3    | `SND`                                                  |
+//! | state  | 1    | SOUND_ON or SOUND_OFF                                  |
+//! | time   | 8    | the time CSR in nanoseconds, u64 little-endian         |
+//! | pitch  | 1    | the XO-CHIP pitch register, DEFAULT_PITCH otherwise    |
+use crate::csr::read_time_ns;
+use crate::util::send_dma;
+
+pub const SOUND_MAGIC: [u8; 3] = *b"SND";
+pub const SOUND_OFF: u8 = 0;
+pub const SOUND_ON: u8 = 1;
+
+/// Tracks the buzzer so that events are only sent when it changes
+pub struct Sound {
+    playing: bool,
+}
+
+impl Sound {
+    pub fn new() -> Self {
+        Self { playing: false }
+    }
+
+    /// Called once per frame with whether the buzzer should be on, sending an event if it has
+    /// started or stopped since the last frame
+    pub fn update(&mut self, playing: bool, pitch: u8) {
+        if playing == self.playing {
+            return;
+        }
+        self.playing = playing;
+
+        let mut event = [0; SOUND_MAGIC.len() + 1 + 8 + 1];
+        event[..3].copy_from_slice(&SOUND_MAGIC);
+        event[3] = if playing { SOUND_ON } else { SOUND_OFF };
+        event[4..12].copy_from_slice(&read_time_ns().to_le_bytes());
+        event[12] = pitch;
+        send_dma(&event);
+    }
+}
@@ -0,0 +1,56 @@
+//! Stack measurement and overflow detection. The stack grows down from _stack towards the end of
+//! the program image at _stack_limit and nothing stops it running into the image. A Machine is
+//! around 11K, most of it the decode cache and the JIT tables, and lives in main's frame.
+//!
+//! At boot the free stack is painted with STACK_PAINT. The lowest word doubles as a canary that
+//! main checks once a frame, and the deepest painted word overwritten gives the stack used.
+use core::arch::asm;
+
+const STACK_PAINT: u32 = 0x57AC_CA11;
+
+/// Left unpainted below the stack pointer for the calls paint itself makes
+const PAINT_MARGIN: usize = 256;
+
+unsafe extern "C" {
+    static _stack: u8;
+    static _stack_limit: u8;
+}
+
+#[cfg(feature = "benchmark")]
+fn top() -> usize {
+    &raw const _stack as usize
+}
+
+fn limit() -> *mut u32 {
+    &raw const _stack_limit as *mut u32
+}
+
+/// Paint the free stack below the current stack pointer. Call it first thing in main so that
+/// everything main keeps on the stack is already allocated.
+pub fn paint() {
+    let sp: usize;
+    unsafe { asm!("mv {0}, sp", out(reg) sp) };
+    let mut word = limit();
+    while (word as usize) + PAINT_MARGIN < sp {
+        unsafe {
+            word.write_volatile(STACK_PAINT);
+            word = word.add(1);
+        }
+    }
+}
+
+/// True once the stack has reached the end of the program image. Anything past the image, the
+/// JIT code buffer included, may be corrupt.
+pub fn overflowed() -> bool {
+    unsafe { limit().read_volatile() != STACK_PAINT }
+}
+
+/// The most bytes of stack used since paint
+#[cfg(feature = "benchmark")]
+pub fn used() -> usize {
+    let mut word = limit();
+    while (word as usize) < top() && unsafe { word.read_volatile() } == STACK_PAINT {
+        word = unsafe { word.add(1) };
+    }
+    top() - word as usize
+}
@@ -0,0 +1,68 @@
+use crate::csr::read_time_ns;
+
+/// The delay and sound timers tick at 60Hz, and the machine runs one frame per tick
+pub const FRAMES_PER_SECOND: u32 = 60;
+
+/// The length of a frame in nanoseconds
+pub const FRAME_NS: u64 = 1_000_000_000 / FRAMES_PER_SECOND as u64;
+
+/// The default CPU speed, fast enough for most CHIP-8 and SUPER-CHIP games
+pub const DEFAULT_INSTRUCTIONS_PER_SECOND: u32 = 600;
+
+/// Paces the machine against the hart time CSR. Each frame runs the instructions for 1/60th of a
+/// second and then waits for the frame deadline. A frame that finishes after its deadline is an
+/// overrun, the next deadline is then measured from the current time rather than trying to catch up.
+pub struct Timing {
+    instructions_per_second: u32,
+
+    /// Instructions carried over between frames when instructions_per_second is not a multiple of
+    /// FRAMES_PER_SECOND, in 1/60ths of an instruction
+    remainder: u32,
+
+    next_frame_ns: u64,
+
+    /// The number of frames that finished after their deadline
+    pub overruns: u32,
+}
+
+impl Timing {
+    /// Start pacing with the first frame deadline one frame from now
+    pub fn new(instructions_per_second: u32) -> Self {
+        Self {
+            instructions_per_second,
+            remainder: 0,
+            next_frame_ns: read_time_ns() + FRAME_NS,
+            overruns: 0,
+        }
+    }
+
+    /// Change the CPU speed, taking effect from the next frame
+    pub fn set_instructions_per_second(&mut self, instructions_per_second: u32) {
+        self.instructions_per_second = instructions_per_second;
+        self.remainder = 0;
+    }
+
+    /// The number of instructions to run in the next frame
+    pub fn instructions_for_frame(&mut self) -> usize {
+        let total = self.instructions_per_second + self.remainder;
+        self.remainder = total % FRAMES_PER_SECOND;
+        (total / FRAMES_PER_SECOND) as usize
+    }
+
+    /// Wait for the current frame deadline. Returns the number of nanoseconds the frame overran by
+    /// if the deadline had already passed.
+    pub fn end_frame(&mut self) -> Option<u64> {
+        let now = read_time_ns();
+
+        if now > self.next_frame_ns {
+            let overrun = now - self.next_frame_ns;
+            self.overruns += 1;
+            self.next_frame_ns = now + FRAME_NS;
+            return Some(overrun);
+        }
+
+        while read_time_ns() < self.next_frame_ns {}
+        self.next_frame_ns += FRAME_NS;
+        None
+    }
+}
@@ -1,7 +1,24 @@
+use chip8_core::fault::Fault;
+
 unsafe extern "C" {
     pub fn system_call(code: u32, msg: *const u8, len: u32) -> bool;
 }
 
 pub fn send_dma_l(s: &str) {
-    unsafe { while !system_call(0, s.as_ptr(), s.len() as u32) {} }
+    send_dma(s.as_bytes())
+}
+
+/// Send raw bytes over DMA, retrying until the DMA controller accepts them
+pub fn send_dma(data: &[u8]) {
+    unsafe { while !system_call(0, data.as_ptr(), data.len() as u32) {} }
+}
+
+/// Report a fault over DMA as the fault description followed by the PC and opcode
+pub fn report_fault(fault: &Fault) {
+    send_dma_l("FAULT");
+    send_dma_l(fault.kind.description());
+    let mut buffer = itoa::Buffer::new();
+    send_dma_l(buffer.format(fault.pc));
+    let mut buffer = itoa::Buffer::new();
+    send_dma_l(buffer.format(fault.opcode));
 }
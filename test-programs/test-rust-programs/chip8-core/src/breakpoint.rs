@@ -0,0 +1,254 @@
+//! The debug table: PC breakpoints, memory write watchpoints and conditional register
+//! breakpoints. Machine checks the table as it steps and reports the entry that triggered in
+//! StepOutcome::Breakpoint.
+use crate::cpu::Registers;
+
+/// The maximum number of PC breakpoints set at once
+pub const MAX_BREAKPOINTS: usize = 16;
+
+/// The maximum number of memory write watchpoints set at once
+pub const MAX_WATCHPOINTS: usize = 8;
+
+/// The maximum number of conditional register breakpoints set at once
+pub const MAX_CONDITIONS: usize = 8;
+
+/// The debug table entry that stopped the machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    /// PC reached a breakpoint. The instruction at the breakpoint has not executed.
+    Breakpoint { pc: u16 },
+    /// The instruction at pc wrote value to a watched address. The instruction has executed.
+    Watchpoint { pc: u16, address: u16, value: u8 },
+    /// The instruction at pc made the condition true. The instruction has executed.
+    Condition { pc: u16, condition: Condition },
+}
+
+/// A register a condition can test
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    V(u8),
+    I,
+    Pc,
+    Delay,
+    Sound,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+}
+
+/// A conditional register breakpoint such as V3 == 0x10. The machine stops after the instruction
+/// that makes the condition true, not on every instruction while it holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Condition {
+    pub register: Register,
+    pub comparison: Comparison,
+    pub value: u16,
+}
+
+impl Condition {
+    pub fn new(register: Register, comparison: Comparison, value: u16) -> Self {
+        Self {
+            register,
+            comparison,
+            value,
+        }
+    }
+
+    /// Evaluate the condition against the registers
+    pub fn holds(&self, registers: &Registers) -> bool {
+        let current = match self.register {
+            Register::V(index) => registers.v[(index & 0xF) as usize] as u16,
+            Register::I => registers.i,
+            Register::Pc => registers.pc,
+            Register::Delay => registers.delay as u16,
+            Register::Sound => registers.sound as u16,
+        };
+
+        match self.comparison {
+            Comparison::Equal => current == self.value,
+            Comparison::NotEqual => current != self.value,
+            Comparison::Less => current < self.value,
+            Comparison::Greater => current > self.value,
+        }
+    }
+}
+
+/// Insert value into the first free slot of table. Returns false if the table is full.
+fn insert<T: PartialEq + Copy>(table: &mut [Option<T>], value: T) -> bool {
+    if table.contains(&Some(value)) {
+        return true;
+    }
+
+    match table.iter_mut().find(|slot| slot.is_none()) {
+        Some(slot) => {
+            *slot = Some(value);
+            true
+        }
+        None => false,
+    }
+}
+
+/// PC breakpoints and conditional register breakpoints, checked by Machine around each step.
+/// Watchpoints live in Watchpoints because Memory checks them on every write.
+pub struct DebugTable {
+    breakpoints: [Option<u16>; MAX_BREAKPOINTS],
+
+    /// Each condition along with whether it held after the last step
+    conditions: [Option<(Condition, bool)>; MAX_CONDITIONS],
+}
+
+impl DebugTable {
+    pub fn new() -> Self {
+        Self {
+            breakpoints: [None; MAX_BREAKPOINTS],
+            conditions: [None; MAX_CONDITIONS],
+        }
+    }
+
+    /// True if any breakpoint or condition is set
+    #[cfg(feature = "jit")]
+    pub fn is_active(&self) -> bool {
+        self.breakpoints.iter().any(Option::is_some) || self.conditions.iter().any(Option::is_some)
+    }
+
+    /// Stop before executing the instruction at pc. Returns false if every breakpoint slot is
+    /// already in use.
+    pub fn add_breakpoint(&mut self, pc: u16) -> bool {
+        insert(&mut self.breakpoints, pc)
+    }
+
+    pub fn remove_breakpoint(&mut self, pc: u16) {
+        for slot in self.breakpoints.iter_mut() {
+            if *slot == Some(pc) {
+                *slot = None;
+            }
+        }
+    }
+
+    pub fn is_breakpoint(&self, pc: u16) -> bool {
+        self.breakpoints.contains(&Some(pc))
+    }
+
+    /// Stop after any instruction that makes condition true. Returns false if every condition
+    /// slot is already in use.
+    pub fn add_condition(&mut self, condition: Condition, registers: &Registers) -> bool {
+        if self
+            .conditions
+            .iter()
+            .flatten()
+            .any(|(existing, _)| *existing == condition)
+        {
+            return true;
+        }
+
+        match self.conditions.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => {
+                *slot = Some((condition, condition.holds(registers)));
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn remove_condition(&mut self, condition: Condition) {
+        for slot in self.conditions.iter_mut() {
+            if matches!(slot, Some((existing, _)) if *existing == condition) {
+                *slot = None;
+            }
+        }
+    }
+
+    /// Re-evaluate every condition after a step, returning the first that became true
+    pub fn check_conditions(&mut self, registers: &Registers) -> Option<Condition> {
+        let mut triggered = None;
+
+        for (condition, held) in self.conditions.iter_mut().flatten() {
+            let holds = condition.holds(registers);
+            if holds && !*held && triggered.is_none() {
+                triggered = Some(*condition);
+            }
+            *held = holds;
+        }
+
+        triggered
+    }
+}
+
+impl Default for DebugTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Memory write watchpoints, each covering an inclusive range of addresses
+pub struct Watchpoints {
+    ranges: [Option<(u16, u16)>; MAX_WATCHPOINTS],
+
+    /// Set when no watchpoints are in use so writes skip the range checks
+    empty: bool,
+
+    /// The first watched write since the last take_hit, as the address and the value written
+    hit: Option<(u16, u8)>,
+}
+
+impl Watchpoints {
+    pub fn new() -> Self {
+        Self {
+            ranges: [None; MAX_WATCHPOINTS],
+            empty: true,
+            hit: None,
+        }
+    }
+
+    /// Watch writes to length bytes starting at address. Returns false if every watchpoint slot
+    /// is already in use.
+    pub fn add(&mut self, address: u16, length: u16) -> bool {
+        let last = address.saturating_add(length.max(1) - 1);
+        let added = insert(&mut self.ranges, (address, last));
+        self.empty = !self.ranges.iter().any(Option::is_some);
+        added
+    }
+
+    /// Remove the watchpoint starting at address
+    pub fn remove(&mut self, address: u16) {
+        for slot in self.ranges.iter_mut() {
+            if matches!(slot, Some((first, _)) if *first == address) {
+                *slot = None;
+            }
+        }
+        self.empty = !self.ranges.iter().any(Option::is_some);
+    }
+
+    /// Record a write if it lands in a watched range
+    pub fn check(&mut self, address: usize, value: u8) {
+        if self.empty || self.hit.is_some() {
+            return;
+        }
+
+        let address = address as u16;
+        if self
+            .ranges
+            .iter()
+            .flatten()
+            .any(|(first, last)| (*first..=*last).contains(&address))
+        {
+            self.hit = Some((address, value));
+        }
+    }
+
+    /// Take the first watched write since the last call, if any
+    pub fn take_hit(&mut self) -> Option<(u16, u8)> {
+        self.hit.take()
+    }
+}
+
+impl Default for Watchpoints {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,150 @@
+//! The ROM container format. ROMs are wrapped in a header describing how the game should be run.
+//! chip-8-rust's build.rs wraps the bundled ROMs listed in its roms/roms.txt, and the host wraps
+//! ROMs it loads at runtime.
+//!
+//! A container is a little-endian binary image laid out as:
+//!
+//! | Field                  | Size | Notes                                                     |
+//! |------------------------|------|-----------------------------------------------------------|
+//! | magic                  | 4    | `C8RM`                                                    |
+//! | version                | 1    | CONTAINER_VERSION                                         |
+//! | platform               | 1    | 0 CHIP-8, 1 SUPER-CHIP, 2 XO-CHIP                         |
+//! | quirks                 | 1    | Quirks::to_bits, a bit per flag then the font             |
+//! | flags                  | 1    | FLAG_ bits, zero for none                                 |
+//! | instructions per frame | 2    |                                                           |
+//! | program length         | 2    |                                                           |
+//! | checksum               | 4    | FNV-1a of the program                                     |
+//! | keymap                 | 16   | the host keyboard key (ASCII) for each keypad key 0 - F   |
+//! | title                  | 32   | ASCII, zero padded                                        |
+//! | program                | *    | loaded at PROGRAM_ADDRESS                                 |
+
+pub const CONTAINER_MAGIC: [u8; 4] = *b"C8RM";
+pub const CONTAINER_VERSION: u8 = 1;
+
+pub const KEYMAP_SIZE: usize = 16;
+pub const TITLE_SIZE: usize = 32;
+pub const HEADER_SIZE: usize = 32 + TITLE_SIZE;
+
+/// Every platform runs in 4K of machine memory with the program loaded at 0x200, XO-CHIP
+/// included, so programs larger than this are rejected
+pub const MAX_PROGRAM_SIZE: usize = 0x1000 - 0x200;
+
+/// Run the ROM with COSMAC VIP timing, see Cpu::vip_timing
+pub const FLAG_VIP_TIMING: u8 = 1 << 0;
+const KNOWN_FLAGS: u8 = FLAG_VIP_TIMING;
+
+/// The reason a container could not be parsed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContainerError {
+    /// The image does not start with CONTAINER_MAGIC
+    BadMagic,
+    /// The image was written by a different container format version
+    UnsupportedVersion(u8),
+    /// The image ends before the program does
+    Truncated,
+    /// The program does not match the header checksum
+    BadChecksum,
+    /// A header field holds a value that no ROM can have, such as an unknown flag or zero
+    /// instructions per frame
+    Invalid,
+    /// The program does not fit in the machine memory
+    TooLarge,
+}
+
+/// A parsed container. platform and quirks are left encoded, see Platform::from_u8 and
+/// Quirks::from_bits.
+#[derive(Debug, Clone, Copy)]
+pub struct Container<'a> {
+    pub title: &'a str,
+    pub platform: u8,
+    pub quirks: u8,
+    pub flags: u8,
+    pub instructions_per_frame: u16,
+    pub keymap: [u8; KEYMAP_SIZE],
+    pub program: &'a [u8],
+}
+
+/// The FNV-1a hash of data
+pub fn checksum(data: &[u8]) -> u32 {
+    let mut hash: u32 = 0x811c_9dc5;
+    for byte in data {
+        hash ^= *byte as u32;
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    hash
+}
+
+impl<'a> Container<'a> {
+    /// Parse a container image, checking the program against the checksum
+    pub fn parse(image: &'a [u8]) -> Result<Self, ContainerError> {
+        if image.len() < HEADER_SIZE {
+            return Err(if image.starts_with(&CONTAINER_MAGIC) {
+                ContainerError::Truncated
+            } else {
+                ContainerError::BadMagic
+            });
+        }
+
+        if image[..4] != CONTAINER_MAGIC {
+            return Err(ContainerError::BadMagic);
+        }
+
+        if image[4] != CONTAINER_VERSION {
+            return Err(ContainerError::UnsupportedVersion(image[4]));
+        }
+
+        let u16_at = |offset: usize| u16::from_le_bytes([image[offset], image[offset + 1]]);
+        let program_len = u16_at(10) as usize;
+        if program_len > MAX_PROGRAM_SIZE {
+            return Err(ContainerError::TooLarge);
+        }
+
+        let expected_checksum = u32::from_le_bytes([image[12], image[13], image[14], image[15]]);
+
+        let program = image
+            .get(HEADER_SIZE..HEADER_SIZE + program_len)
+            .ok_or(ContainerError::Truncated)?;
+        if checksum(program) != expected_checksum {
+            return Err(ContainerError::BadChecksum);
+        }
+
+        if image[7] & !KNOWN_FLAGS != 0 || u16_at(8) == 0 {
+            return Err(ContainerError::Invalid);
+        }
+
+        let mut keymap = [0; KEYMAP_SIZE];
+        keymap.copy_from_slice(&image[16..16 + KEYMAP_SIZE]);
+
+        let title = &image[32..32 + TITLE_SIZE];
+        let title_len = title.iter().position(|c| *c == 0).unwrap_or(TITLE_SIZE);
+        let title =
+            core::str::from_utf8(&title[..title_len]).map_err(|_| ContainerError::Invalid)?;
+
+        Ok(Self {
+            title,
+            platform: image[5],
+            quirks: image[6],
+            flags: image[7],
+            instructions_per_frame: u16_at(8),
+            keymap,
+            program,
+        })
+    }
+
+    /// Encode the header for this container. The program follows it.
+    pub fn header(&self) -> [u8; HEADER_SIZE] {
+        let mut header = [0; HEADER_SIZE];
+        header[..4].copy_from_slice(&CONTAINER_MAGIC);
+        header[4] = CONTAINER_VERSION;
+        header[5] = self.platform;
+        header[6] = self.quirks;
+        header[7] = self.flags;
+        header[8..10].copy_from_slice(&self.instructions_per_frame.to_le_bytes());
+        header[10..12].copy_from_slice(&(self.program.len() as u16).to_le_bytes());
+        header[12..16].copy_from_slice(&checksum(self.program).to_le_bytes());
+        header[16..16 + KEYMAP_SIZE].copy_from_slice(&self.keymap);
+        let title = &self.title.as_bytes()[..self.title.len().min(TITLE_SIZE)];
+        header[32..32 + title.len()].copy_from_slice(title);
+        header
+    }
+}
@@ -0,0 +1,591 @@
+use crate::breakpoint::Trigger;
+use crate::cycles::vip_cycles;
+use crate::fault::{Fault, FaultKind};
+use crate::font::{BIG_GLYPH_SIZE, SMALL_GLYPH_SIZE};
+use crate::memory::{BIG_FONT_ADDRESS, FONT_ADDRESS, MEMORY_SIZE, Memory, PROGRAM_ADDRESS};
+use crate::opcode::Opcode;
+use crate::quirks::Quirks;
+use crate::rand::Rand;
+
+/// Size of an instruction (CHIP-8 uses fixed width opcodes)
+pub const INSTRUCTION_SIZE: u16 = 0x2;
+
+/// The number of key registers
+pub const NUM_KEYS: usize = 16;
+
+/// The XO-CHIP F000 NNNN instruction is followed by a 16-bit address, making it twice as wide as
+/// every other instruction
+pub const LONG_LOAD_I_OPCODE: u16 = 0xF000;
+
+/// The XO-CHIP audio pattern buffer holds 128 1-bit samples
+pub const AUDIO_PATTERN_SIZE: usize = 16;
+
+/// The XO-CHIP default pitch register value (4000Hz playback of the audio pattern)
+pub const DEFAULT_PITCH: u8 = 64;
+
+/// The instruction set extensions the CPU accepts. SUPER-CHIP instructions are available on the
+/// SUPER-CHIP and XO-CHIP platforms, XO-CHIP instructions are only decoded in XO-CHIP mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Platform {
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+impl Platform {
+    /// The platform encoding used by snapshots and ROM containers
+    pub fn to_u8(self) -> u8 {
+        match self {
+            Platform::Chip8 => 0,
+            Platform::SuperChip => 1,
+            Platform::XoChip => 2,
+        }
+    }
+
+    pub fn from_u8(platform: u8) -> Option<Platform> {
+        match platform {
+            0 => Some(Platform::Chip8),
+            1 => Some(Platform::SuperChip),
+            2 => Some(Platform::XoChip),
+            _ => None,
+        }
+    }
+
+    /// The size of the address space in bytes. CHIP-8 and SUPER-CHIP addresses are 12 bits and
+    /// XO-CHIP addresses 16 bits, addresses wrap at the end. Addresses past the end of backing
+    /// memory fault, see Memory::new.
+    pub fn address_space(self) -> usize {
+        match self {
+            Platform::Chip8 | Platform::SuperChip => 0x1000,
+            Platform::XoChip => MEMORY_SIZE,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Registers {
+    /// The CHIP architecture has 16 8-bit general purpose registers.
+    /// Register v[f] also doubles as the carry flag, collision flag, or borrow flag dependent on
+    /// the operation.
+    pub v: [u8; 16],
+    /// The program counter
+    pub pc: u16,
+    /// The address register
+    pub i: u16,
+
+    /// The stack is only used for return
+    pub stack: [u8; 256],
+    pub stack_idx: usize,
+
+    /// The delay timer counts down to zero at 60hz
+    pub delay: u8,
+
+    /// The sound timer emits a sound if it is not zero.
+    /// This timer counts down to zero at 60hz and then stops.
+    pub sound: u8,
+
+    /// True if a given key is currently pressed
+    pub keys: [bool; NUM_KEYS],
+
+    /// If we are waiting for a key then this is Some of the register to write the key to
+    /// otherwise None
+    pub wait_for_key: Option<usize>,
+
+    /// Set once the program has exited through the SUPER-CHIP 00FD instruction
+    pub halted: bool,
+
+    /// The instruction set extensions that are enabled
+    pub platform: Platform,
+
+    /// The behaviour of instructions that differ between interpreters
+    pub quirks: Quirks,
+
+    /// The XO-CHIP audio pattern buffer, loaded from memory at I by F002
+    pub audio_pattern: [u8; AUDIO_PATTERN_SIZE],
+
+    /// The XO-CHIP playback pitch of the audio pattern, set by FX3A
+    pub pitch: u8,
+
+    rng: Rand,
+}
+
+/// The result of successfully stepping the CPU or machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    /// An instruction was executed
+    Executed,
+    /// No instruction was executed because the machine is waiting for a key press
+    WaitingForKey,
+    /// A sprite was drawn with VIP timing enabled, the rest of the frame is spent waiting for
+    /// the display interrupt
+    WaitingForDisplay,
+    /// The program has exited through 00FD and no further instructions will run
+    Exited,
+    /// An entry in the machine debug table triggered. For a PC breakpoint no instruction was
+    /// executed, for a watchpoint or condition the instruction that triggered it was.
+    Breakpoint(Trigger),
+}
+
+impl Registers {
+    /// Increment the PC by a given amount
+    pub fn inc_pc(&mut self, val: u16) {
+        self.pc = self.pc.wrapping_add(val)
+    }
+
+    /// Push a u16 to the stack in big-endian format, failing if the stack is full
+    pub fn stack_push16(&mut self, value: u16) -> Result<(), FaultKind> {
+        if self.stack_idx + 2 > self.stack.len() {
+            return Err(FaultKind::StackOverflow);
+        }
+
+        let lower_part = value & 0x00FF;
+        let upper_part = (value & 0xFF00) >> 8;
+        self.stack[self.stack_idx] = upper_part as u8;
+        self.stack[self.stack_idx + 1] = lower_part as u8;
+        self.stack_idx += 2;
+        Ok(())
+    }
+
+    /// The random number generator state, saved and restored by snapshots
+    pub fn rng_state(&self) -> u32 {
+        self.rng.state()
+    }
+
+    pub fn set_rng_state(&mut self, state: u32) {
+        self.rng = Rand::from_state(state);
+    }
+
+    /// Pop a u16 from the stack, failing if the stack is empty
+    /// TODO: Since stack is only ever used for retcodes I could just keep them as usize or u16's
+    pub fn stack_pop16(&mut self) -> Result<u16, FaultKind> {
+        if self.stack_idx < 2 {
+            return Err(FaultKind::StackUnderflow);
+        }
+
+        self.stack_idx -= 2;
+        let upper_part = self.stack[self.stack_idx];
+        let lower_part = self.stack[self.stack_idx + 1];
+
+        Ok(((upper_part as u16) << 8) | (lower_part as u16))
+    }
+}
+
+/// The CPU holds the current program registers
+pub struct Cpu {
+    pub registers: Registers,
+
+    /// Charge each instruction its COSMAC VIP machine cycle cost and wait for the display
+    /// interrupt after drawing
+    pub vip_timing: bool,
+
+    /// The VIP machine cycles used so far, only counted while vip_timing is set. Wraps on overflow.
+    pub cycles: u32,
+}
+
+impl Cpu {
+    /// Create a fresh CPU instance with 0 / false set for all registers and PC set to 0x200 (the
+    /// typical ROM start location)
+    pub fn new(platform: Platform, quirks: Quirks) -> Self {
+        Self {
+            registers: Registers {
+                pc: PROGRAM_ADDRESS as u16,
+                v: [0; 16],
+                i: 0,
+                stack: [(0); 256],
+                stack_idx: 0,
+                delay: 0,
+                sound: 0,
+                keys: [false; NUM_KEYS],
+                wait_for_key: None,
+                halted: false,
+                platform,
+                quirks,
+                audio_pattern: [0; AUDIO_PATTERN_SIZE],
+                pitch: DEFAULT_PITCH,
+                rng: Rand::new(),
+            },
+            vip_timing: false,
+            cycles: 0,
+        }
+    }
+
+    /// Execute the instruction at PC. A fault leaves the registers at the faulting instruction.
+    pub fn step(&mut self, memory: &mut Memory) -> Result<StepOutcome, Fault> {
+        let pc = self.registers.pc;
+        let fault = |kind, opcode| Fault { kind, pc, opcode };
+
+        memory
+            .check_range(pc as usize, INSTRUCTION_SIZE as usize)
+            .map_err(|kind| fault(kind, 0))?;
+
+        let opcode = memory.fetch(pc as usize);
+        self.execute(opcode, memory)
+            .map_err(|kind| fault(kind, memory.get16(pc as usize)))?;
+
+        if self.registers.halted {
+            return Ok(StepOutcome::Exited);
+        }
+
+        if self.vip_timing {
+            let skipped = self.registers.pc == pc.wrapping_add(INSTRUCTION_SIZE * 2);
+            self.cycles = self.cycles.wrapping_add(vip_cycles(opcode, skipped));
+
+            if let Opcode::Draw { .. } = opcode {
+                return Ok(StepOutcome::WaitingForDisplay);
+            }
+        }
+
+        Ok(StepOutcome::Executed)
+    }
+
+    /// Advance past the current instruction and, if condition is true, the instruction after it.
+    /// In XO-CHIP mode the skipped instruction may be the double width F000 NNNN.
+    fn skip_if(&mut self, memory: &Memory, condition: bool) -> Result<(), FaultKind> {
+        let registers = &mut self.registers;
+        let next_pc = registers.pc as usize + INSTRUCTION_SIZE as usize;
+        let skip_long = condition && registers.platform == Platform::XoChip && {
+            memory.check_range(next_pc, INSTRUCTION_SIZE as usize)?;
+            memory.get16(next_pc) == LONG_LOAD_I_OPCODE
+        };
+
+        registers.inc_pc(INSTRUCTION_SIZE);
+
+        if skip_long {
+            registers.inc_pc(INSTRUCTION_SIZE * 2);
+        } else if condition {
+            registers.inc_pc(INSTRUCTION_SIZE);
+        }
+        Ok(())
+    }
+
+    /// We cannot run machine code routines, so 0NNN calls are skipped
+    fn skip_machine_call(&mut self) {
+        self.registers.inc_pc(2);
+    }
+
+    /// Fail with an unsupported extension fault on a plain CHIP-8
+    fn require_super_chip(&self) -> Result<(), FaultKind> {
+        if self.registers.platform == Platform::Chip8 {
+            Err(FaultKind::UnsupportedExtension)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Fail with an unsupported extension fault unless the platform is XO-CHIP
+    fn require_xo_chip(&self) -> Result<(), FaultKind> {
+        if self.registers.platform == Platform::XoChip {
+            Ok(())
+        } else {
+            Err(FaultKind::UnsupportedExtension)
+        }
+    }
+
+    /// The register a shift reads from, VY with the shift_uses_vy quirk and VX otherwise
+    fn shift_source(&self, x: usize, y: usize) -> usize {
+        if self.registers.quirks.shift_uses_vy {
+            y
+        } else {
+            x
+        }
+    }
+
+    /// Execute a decoded instruction, with the change in state being reflected in registers and
+    /// memory
+    pub fn execute(&mut self, opcode: Opcode, memory: &mut Memory) -> Result<(), FaultKind> {
+        match opcode {
+            // On a plain CHIP-8 the SUPER-CHIP display and flow instructions are machine calls
+            Opcode::ScrollDown { .. }
+            | Opcode::ScrollRight
+            | Opcode::ScrollLeft
+            | Opcode::Exit
+            | Opcode::LoRes
+            | Opcode::HiRes
+                if self.registers.platform == Platform::Chip8 =>
+            {
+                self.skip_machine_call();
+            }
+            Opcode::ScrollDown { n } => {
+                memory.scroll_down(n as usize);
+                self.registers.inc_pc(2);
+            }
+            Opcode::ClearDisplay => {
+                memory.clear_display();
+                self.registers.inc_pc(2);
+            }
+            Opcode::Return => {
+                self.registers.pc = self.registers.stack_pop16()?;
+            }
+            Opcode::ScrollRight => {
+                memory.scroll_right();
+                self.registers.inc_pc(2);
+            }
+            Opcode::ScrollLeft => {
+                memory.scroll_left();
+                self.registers.inc_pc(2);
+            }
+            Opcode::Exit => {
+                self.registers.halted = true;
+            }
+            Opcode::LoRes => {
+                memory.set_hires(false);
+                self.registers.inc_pc(2);
+            }
+            Opcode::HiRes => {
+                memory.set_hires(true);
+                self.registers.inc_pc(2);
+            }
+            Opcode::MachineCall { .. } => {
+                self.skip_machine_call();
+            }
+            Opcode::Jump { nnn } => {
+                self.registers.pc = nnn;
+            }
+            Opcode::Call { nnn } => {
+                // First save the current PC + 2, then jump to the immediate
+                let registers = &mut self.registers;
+                registers.stack_push16(registers.pc.wrapping_add(INSTRUCTION_SIZE))?;
+                registers.pc = nnn;
+            }
+            Opcode::SkipEqImm { x, nn } => {
+                self.skip_if(memory, self.registers.v[x as usize] == nn)?;
+            }
+            Opcode::SkipNeImm { x, nn } => {
+                self.skip_if(memory, self.registers.v[x as usize] != nn)?;
+            }
+            Opcode::SkipEqReg { x, y } => {
+                let v = &self.registers.v;
+                self.skip_if(memory, v[x as usize] == v[y as usize])?;
+            }
+            Opcode::SaveRange { x, y } | Opcode::LoadRange { x, y } => {
+                // Registers VX through VY (in either direction) are transferred to or from I
+                // without modifying I
+                self.require_xo_chip()?;
+                let registers = &mut self.registers;
+                let (x, y) = (x as usize, y as usize);
+                let count = x.abs_diff(y) + 1;
+                memory.check_range(registers.i as usize, count)?;
+                for offset in 0..count {
+                    let register = if x <= y { x + offset } else { x - offset };
+                    let address = registers.i as usize + offset;
+                    if let Opcode::SaveRange { .. } = opcode {
+                        memory.set(address, registers.v[register]);
+                    } else {
+                        registers.v[register] = memory.get(address);
+                    }
+                }
+                registers.inc_pc(2);
+            }
+            Opcode::LoadImm { x, nn } => {
+                self.registers.v[x as usize] = nn;
+                self.registers.inc_pc(2);
+            }
+            Opcode::AddImm { x, nn } => {
+                let vx = &mut self.registers.v[x as usize];
+                *vx = vx.wrapping_add(nn);
+                self.registers.inc_pc(2);
+            }
+            Opcode::Move { x, y } => {
+                self.registers.v[x as usize] = self.registers.v[y as usize];
+                self.registers.inc_pc(2);
+            }
+            Opcode::Or { x, y } | Opcode::And { x, y } | Opcode::Xor { x, y } => {
+                let registers = &mut self.registers;
+                let vy = registers.v[y as usize];
+                let vx = &mut registers.v[x as usize];
+                match opcode {
+                    Opcode::Or { .. } => *vx |= vy,
+                    Opcode::And { .. } => *vx &= vy,
+                    _ => *vx ^= vy,
+                }
+                if registers.quirks.logic_resets_vf {
+                    registers.v[0xF] = 0;
+                }
+                registers.inc_pc(2);
+            }
+            // The arithmetic and shift instructions write VF after VX, so when X is F the flag
+            // replaces the result
+            Opcode::AddReg { x, y } => {
+                let registers = &mut self.registers;
+                let (x, y) = (x as usize, y as usize);
+                let (result, carry) = registers.v[x].overflowing_add(registers.v[y]);
+                registers.v[x] = result;
+                registers.v[0xF] = carry as u8;
+                registers.inc_pc(2);
+            }
+            Opcode::SubReg { x, y } => {
+                // VF is 1 when there is no borrow
+                let registers = &mut self.registers;
+                let (x, y) = (x as usize, y as usize);
+                let (result, borrow) = registers.v[x].overflowing_sub(registers.v[y]);
+                registers.v[x] = result;
+                registers.v[0xF] = !borrow as u8;
+                registers.inc_pc(2);
+            }
+            Opcode::ShiftRight { x, y } => {
+                let source = self.shift_source(x as usize, y as usize);
+                let registers = &mut self.registers;
+                let value = registers.v[source];
+                registers.v[x as usize] = value >> 1;
+                registers.v[0xF] = value & 0x1;
+                registers.inc_pc(2);
+            }
+            Opcode::SubRev { x, y } => {
+                let registers = &mut self.registers;
+                let (x, y) = (x as usize, y as usize);
+                let (result, borrow) = registers.v[y].overflowing_sub(registers.v[x]);
+                registers.v[x] = result;
+                registers.v[0xF] = !borrow as u8;
+                registers.inc_pc(2);
+            }
+            Opcode::ShiftLeft { x, y } => {
+                let source = self.shift_source(x as usize, y as usize);
+                let registers = &mut self.registers;
+                let value = registers.v[source];
+                registers.v[x as usize] = value << 1;
+                registers.v[0xF] = value >> 7;
+                registers.inc_pc(2);
+            }
+            Opcode::SkipNeReg { x, y } => {
+                let v = &self.registers.v;
+                self.skip_if(memory, v[x as usize] != v[y as usize])?;
+            }
+            Opcode::SetI { nnn } => {
+                self.registers.i = nnn;
+                self.registers.inc_pc(2);
+            }
+            Opcode::JumpOffset { nnn } => {
+                let register = self.registers.quirks.jump_register(nnn);
+                self.registers.pc = self.registers.v[register] as u16 + nnn;
+            }
+            Opcode::Random { x, nn } => {
+                let rval = self.registers.rng.next_u32() as u8;
+                self.registers.v[x as usize] = rval & nn;
+                self.registers.inc_pc(2);
+            }
+            Opcode::Draw { x, y, n } => {
+                let registers = &mut self.registers;
+
+                // A plain CHIP-8 draws nothing for a zero depth sprite
+                registers.v[0xF] = if n == 0 && registers.platform == Platform::Chip8 {
+                    0
+                } else {
+                    memory.draw_sprite(
+                        registers.v[x as usize] as usize,
+                        registers.v[y as usize] as usize,
+                        n as usize,
+                        registers.i as usize,
+                        registers.quirks.clip_sprites,
+                        registers.quirks.lores_dxy0_draws_8x16,
+                    )?
+                };
+                registers.inc_pc(2);
+            }
+            Opcode::SkipKeyPressed { x } => {
+                let pressed = self.registers.keys[(self.registers.v[x as usize] & 0xF) as usize];
+                self.skip_if(memory, pressed)?;
+            }
+            Opcode::SkipKeyNotPressed { x } => {
+                let pressed = self.registers.keys[(self.registers.v[x as usize] & 0xF) as usize];
+                self.skip_if(memory, !pressed)?;
+            }
+            Opcode::LongLoadI => {
+                self.require_xo_chip()?;
+                let address = self.registers.pc as usize + 2;
+                memory.check_range(address, 2)?;
+                self.registers.i = memory.get16(address);
+                self.registers.inc_pc(4);
+            }
+            Opcode::SelectPlanes { n } => {
+                self.require_xo_chip()?;
+                memory.select_planes(n);
+                self.registers.inc_pc(2);
+            }
+            Opcode::LoadAudioPattern => {
+                self.require_xo_chip()?;
+                let registers = &mut self.registers;
+                memory.check_range(registers.i as usize, AUDIO_PATTERN_SIZE)?;
+                for offset in 0..AUDIO_PATTERN_SIZE {
+                    registers.audio_pattern[offset] = memory.get(registers.i as usize + offset);
+                }
+                registers.inc_pc(2);
+            }
+            Opcode::GetDelay { x } => {
+                self.registers.v[x as usize] = self.registers.delay;
+                self.registers.inc_pc(2);
+            }
+            Opcode::WaitForKey { x } => {
+                self.registers.wait_for_key = Some(x as usize);
+                self.registers.inc_pc(2);
+            }
+            Opcode::SetDelay { x } => {
+                self.registers.delay = self.registers.v[x as usize];
+                self.registers.inc_pc(2);
+            }
+            Opcode::SetSound { x } => {
+                self.registers.sound = self.registers.v[x as usize];
+                self.registers.inc_pc(2);
+            }
+            Opcode::AddI { x } => {
+                let registers = &mut self.registers;
+                registers.i = registers.i.wrapping_add(registers.v[x as usize] as u16);
+                registers.inc_pc(2);
+            }
+            Opcode::FontCharacter { x } => {
+                let registers = &mut self.registers;
+                let digit = (registers.v[x as usize] & 0x0F) as usize;
+                registers.i = (FONT_ADDRESS + digit * SMALL_GLYPH_SIZE) as u16;
+                registers.inc_pc(2);
+            }
+            Opcode::BigFontCharacter { x } => {
+                self.require_super_chip()?;
+                let registers = &mut self.registers;
+                let digit = (registers.v[x as usize] & 0x0F) as usize;
+                registers.i = (BIG_FONT_ADDRESS + digit * BIG_GLYPH_SIZE) as u16;
+                registers.inc_pc(2);
+            }
+            Opcode::Bcd { x } => {
+                let registers = &mut self.registers;
+                let mut tmp = registers.v[x as usize];
+                memory.check_range(registers.i as usize, 3)?;
+
+                // Least significant digit. The digits wrap at the end of the address space.
+                memory.set(registers.i as usize + 2, tmp % 10);
+                tmp /= 10;
+
+                // Middle digit
+                memory.set(registers.i as usize + 1, tmp % 10);
+                tmp /= 10;
+
+                // Most significant digit
+                memory.set(registers.i as usize, tmp % 10);
+
+                registers.inc_pc(2);
+            }
+            Opcode::SetPitch { x } => {
+                self.require_xo_chip()?;
+                self.registers.pitch = self.registers.v[x as usize];
+                self.registers.inc_pc(2);
+            }
+            Opcode::StoreRegisters { x } | Opcode::LoadRegisters { x } => {
+                let registers = &mut self.registers;
+                let count = x as usize + 1;
+                memory.check_range(registers.i as usize, count)?;
+                for i in 0..count {
+                    let address = registers.i as usize + i;
+                    if let Opcode::StoreRegisters { .. } = opcode {
+                        memory.set(address, registers.v[i]);
+                    } else {
+                        registers.v[i] = memory.get(address);
+                    }
+                }
+                if registers.quirks.load_store_increments_i {
+                    registers.i = registers.i.wrapping_add(count as u16);
+                }
+                registers.inc_pc(2);
+            }
+            Opcode::Invalid { .. } => return Err(FaultKind::InvalidOpcode),
+        }
+        Ok(())
+    }
+}
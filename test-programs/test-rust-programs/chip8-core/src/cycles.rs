@@ -0,0 +1,62 @@
+use crate::opcode::Opcode;
+
+/// The VIP clocks the 1802 at 1.7609MHz with 8 clocks per machine cycle, giving 3668 machine cycles
+/// per 60Hz frame
+pub const VIP_CYCLES_PER_FRAME: u32 = 3668;
+
+/// The 1861 display steals one machine cycle per byte it reads (8 bytes per line, 128 lines) and
+/// the display interrupt routine takes a little more, leaving the rest of the frame to the
+/// interpreter
+pub const VIP_DISPLAY_CYCLES: u32 = 1024 + 46;
+
+/// The machine cycles available to the interpreter in each frame
+pub const VIP_INTERPRETER_CYCLES_PER_FRAME: u32 = VIP_CYCLES_PER_FRAME - VIP_DISPLAY_CYCLES;
+
+/// Fetching and dispatching an instruction costs the same for every opcode
+const FETCH_CYCLES: u32 = 40;
+
+/// A skip that is taken costs a few more cycles to advance the PC again
+const SKIP_TAKEN_CYCLES: u32 = 4;
+
+/// The approximate number of VIP machine cycles the original interpreter spends executing opcode.
+/// skipped is true if the opcode was a skip and the skip was taken. The SUPER-CHIP and XO-CHIP
+/// extensions did not exist on the VIP and are charged as a simple register operation.
+pub fn vip_cycles(opcode: Opcode, skipped: bool) -> u32 {
+    let skip = if skipped { SKIP_TAKEN_CYCLES } else { 0 };
+
+    let execute = match opcode {
+        Opcode::ClearDisplay => 3078,
+        Opcode::Return => 10,
+        Opcode::MachineCall { .. } => 0,
+        Opcode::Jump { .. } => 12,
+        Opcode::Call { .. } => 26,
+        Opcode::SkipEqImm { .. } | Opcode::SkipNeImm { .. } => 10 + skip,
+        Opcode::SkipEqReg { .. } | Opcode::SkipNeReg { .. } => 14 + skip,
+        Opcode::LoadImm { .. } => 6,
+        Opcode::AddImm { .. } => 10,
+        Opcode::Move { .. }
+        | Opcode::Or { .. }
+        | Opcode::And { .. }
+        | Opcode::Xor { .. }
+        | Opcode::AddReg { .. }
+        | Opcode::SubReg { .. }
+        | Opcode::ShiftRight { .. }
+        | Opcode::SubRev { .. }
+        | Opcode::ShiftLeft { .. } => 44,
+        Opcode::SetI { .. } => 12,
+        Opcode::JumpOffset { .. } => 22,
+        Opcode::Random { .. } => 36,
+        // Each sprite row is shifted into place and XORed into two display bytes
+        Opcode::Draw { n, .. } => 22 + 46 * n as u32,
+        Opcode::SkipKeyPressed { .. } | Opcode::SkipKeyNotPressed { .. } => 14 + skip,
+        Opcode::GetDelay { .. } | Opcode::SetDelay { .. } | Opcode::SetSound { .. } => 10,
+        Opcode::WaitForKey { .. } => 19,
+        Opcode::AddI { .. } => 16,
+        Opcode::FontCharacter { .. } => 20,
+        Opcode::Bcd { .. } => 100,
+        Opcode::StoreRegisters { x } | Opcode::LoadRegisters { x } => 14 + 14 * (x as u32 + 1),
+        _ => 10,
+    };
+
+    FETCH_CYCLES + execute
+}
@@ -0,0 +1,35 @@
+/// The reason an instruction could not be executed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultKind {
+    /// The opcode does not decode to any instruction
+    InvalidOpcode,
+    /// A call was made with the stack already full
+    StackOverflow,
+    /// A return was made with the stack empty
+    StackUnderflow,
+    /// An instruction accessed an address past the end of memory
+    MemoryOutOfRange { address: usize },
+    /// The opcode belongs to an extension that the current platform does not enable
+    UnsupportedExtension,
+}
+
+impl FaultKind {
+    /// A short human readable description of the fault
+    pub fn description(&self) -> &'static str {
+        match self {
+            FaultKind::InvalidOpcode => "Invalid opcode",
+            FaultKind::StackOverflow => "Stack overflow",
+            FaultKind::StackUnderflow => "Stack underflow",
+            FaultKind::MemoryOutOfRange { .. } => "Memory out of range",
+            FaultKind::UnsupportedExtension => "Unsupported extension",
+        }
+    }
+}
+
+/// A fault raised by the CPU, along with the location and opcode of the faulting instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fault {
+    pub kind: FaultKind,
+    pub pc: u16,
+    pub opcode: u16,
+}
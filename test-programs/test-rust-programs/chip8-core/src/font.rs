@@ -0,0 +1,125 @@
+//! The built-in fonts. Every interpreter has a 4x5 hex font for FX29, but the glyphs differ
+//! between them and games drawing scores with it were written against whichever one the author
+//! had. SUPER-CHIP adds an 8x10 font for FX30.
+
+/// The small font glyph set, selected through Quirks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Font {
+    /// SUPER-CHIP 1.1 on the HP48, also used by CHIP-48 and Octo
+    SuperChip,
+    /// The original COSMAC VIP interpreter
+    Vip,
+    /// CHIPOS on the DREAM 6800, three pixels wide
+    Dream6800,
+    /// The ETI-660 interpreter, three pixels wide
+    Eti660,
+}
+
+/// The bytes in a small font glyph, one per row
+pub const SMALL_GLYPH_SIZE: usize = 5;
+
+/// The bytes in a big font glyph, one per row
+pub const BIG_GLYPH_SIZE: usize = 10;
+
+impl Font {
+    /// The encoding used in the quirks bits. SuperChip is 0 so that quirks from before fonts were
+    /// selectable keep the font they ran with.
+    pub fn to_u8(self) -> u8 {
+        match self {
+            Font::SuperChip => 0,
+            Font::Vip => 1,
+            Font::Dream6800 => 2,
+            Font::Eti660 => 3,
+        }
+    }
+
+    pub fn from_u8(font: u8) -> Option<Font> {
+        match font {
+            0 => Some(Font::SuperChip),
+            1 => Some(Font::Vip),
+            2 => Some(Font::Dream6800),
+            3 => Some(Font::Eti660),
+            _ => None,
+        }
+    }
+
+    /// The glyphs for the hex digits 0 - F, SMALL_GLYPH_SIZE bytes each
+    pub fn glyphs(self) -> &'static [u8; SMALL_GLYPH_SIZE * 16] {
+        match self {
+            Font::SuperChip => &SUPER_CHIP,
+            Font::Vip => &VIP,
+            Font::Dream6800 => &DREAM_6800,
+            Font::Eti660 => &ETI_660,
+        }
+    }
+}
+
+#[rustfmt::skip]
+const SUPER_CHIP: [u8; SMALL_GLYPH_SIZE * 16] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, 0x20, 0x60, 0x20, 0x20, 0x70, // 0 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, 0xF0, 0x10, 0xF0, 0x10, 0xF0, // 2 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, 0xF0, 0x80, 0xF0, 0x10, 0xF0, // 4 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, 0xF0, 0x10, 0x20, 0x40, 0x40, // 6 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, 0xF0, 0x90, 0xF0, 0x10, 0xF0, // 8 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, 0xE0, 0x90, 0xE0, 0x90, 0xE0, // A B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, 0xE0, 0x90, 0x90, 0x90, 0xE0, // C D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, 0xF0, 0x80, 0xF0, 0x80, 0x80, // E F
+];
+
+#[rustfmt::skip]
+const VIP: [u8; SMALL_GLYPH_SIZE * 16] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, 0x60, 0x20, 0x20, 0x20, 0x70, // 0 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, 0xF0, 0x10, 0x70, 0x10, 0xF0, // 2 3
+    0xA0, 0xA0, 0xF0, 0x20, 0x20, 0xF0, 0x80, 0xF0, 0x10, 0xF0, // 4 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, 0xF0, 0x10, 0x10, 0x10, 0x10, // 6 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, 0xF0, 0x90, 0xF0, 0x10, 0xF0, // 8 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, 0xF0, 0x50, 0x70, 0x50, 0xF0, // A B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, 0xF0, 0x50, 0x50, 0x50, 0xF0, // C D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, 0xF0, 0x80, 0xF0, 0x80, 0x80, // E F
+];
+
+#[rustfmt::skip]
+const DREAM_6800: [u8; SMALL_GLYPH_SIZE * 16] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, 0x40, 0x40, 0x40, 0x40, 0x40, // 0 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, 0xE0, 0x20, 0xE0, 0x20, 0xE0, // 2 3
+    0x80, 0xA0, 0xA0, 0xE0, 0x20, 0xE0, 0x80, 0xE0, 0x20, 0xE0, // 4 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, 0xE0, 0x20, 0x20, 0x20, 0x20, // 6 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, 0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 8 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, 0xC0, 0xA0, 0xE0, 0xA0, 0xC0, // A B
+    0xE0, 0x80, 0x80, 0x80, 0xE0, 0xC0, 0xA0, 0xA0, 0xA0, 0xC0, // C D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, 0xE0, 0x80, 0xC0, 0x80, 0x80, // E F
+];
+
+#[rustfmt::skip]
+const ETI_660: [u8; SMALL_GLYPH_SIZE * 16] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, 0x20, 0x20, 0x20, 0x20, 0x20, // 0 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, 0xE0, 0x20, 0xE0, 0x20, 0xE0, // 2 3
+    0xA0, 0xA0, 0xE0, 0x20, 0x20, 0xE0, 0x80, 0xE0, 0x20, 0xE0, // 4 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, 0xE0, 0x20, 0x20, 0x20, 0x20, // 6 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, 0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 8 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, 0x80, 0x80, 0xE0, 0xA0, 0xE0, // A B
+    0xE0, 0x80, 0x80, 0x80, 0xE0, 0x20, 0x20, 0xE0, 0xA0, 0xE0, // C D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, 0xE0, 0x80, 0xE0, 0x80, 0x80, // E F
+];
+
+/// The SUPER-CHIP 8x10 font. SUPER-CHIP 1.1 only has the digits 0 - 9, A - F are the XO-CHIP
+/// additions from Octo.
+#[rustfmt::skip]
+pub const BIG_FONT: [u8; BIG_GLYPH_SIZE * 16] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xC0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
@@ -0,0 +1,438 @@
+use crate::cpu::{INSTRUCTION_SIZE, Platform, Registers};
+use crate::memory::{DECODE_CACHE_END, DECODE_CACHE_ENTRIES, Memory};
+use crate::opcode::Opcode;
+use core::arch::asm;
+
+/// The size of the native code buffer in 32-bit words
+pub const CODE_BUFFER_WORDS: usize = 1024;
+
+/// The maximum number of translated blocks live at once
+pub const MAX_BLOCKS: usize = 128;
+
+/// The number of times a block entry has to be reached by the interpreter before it is translated
+pub const HOT_THRESHOLD: u8 = 8;
+
+/// The maximum number of CHIP-8 instructions translated into a single block
+pub const MAX_BLOCK_INSTRUCTIONS: usize = 32;
+
+/// An upper bound on the native words emitted for a single block, used to decide whether the
+/// code buffer has room before translating
+const MAX_BLOCK_WORDS: usize = MAX_BLOCK_INSTRUCTIONS * 6 + 16;
+
+/// Marks an entry with no translated block, past every block index
+const NO_BLOCK: u8 = u8::MAX;
+
+/// Marks the heat of an entry that is never translated, either because the first instruction is
+/// not supported or because the block was modified by the program. Past every heat count and
+/// distinct from NO_BLOCK so that the two tables cannot be confused.
+const BLACKLISTED: u8 = u8::MAX - 1;
+
+const _: () = assert!(MAX_BLOCKS < BLACKLISTED as usize && HOT_THRESHOLD < BLACKLISTED);
+
+/// Translated code lives in RAM reserved by the linker script. The RAM region is rwx so the hart
+/// can execute it directly.
+#[unsafe(link_section = ".jit")]
+static mut CODE_BUFFER: [u32; CODE_BUFFER_WORDS] = [0; CODE_BUFFER_WORDS];
+
+/// A translated block takes a pointer to V0 - VF and a pointer to I and returns the next PC
+type BlockFn = extern "C" fn(*mut u8, *mut u16) -> u32;
+
+/// RV32I registers used by the generated code
+const ZERO: u32 = 0;
+const RA: u32 = 1;
+const T0: u32 = 5;
+const T1: u32 = 6;
+const T2: u32 = 7;
+const A0: u32 = 10;
+const A1: u32 = 11;
+
+/// The VF flag register offset from V0
+const VF: u32 = 0xF;
+
+/// RV32I instruction encoders for the handful of instructions the translator emits
+fn i_type(opcode: u32, funct3: u32, rd: u32, rs1: u32, imm: i32) -> u32 {
+    ((imm as u32 & 0xFFF) << 20) | (rs1 << 15) | (funct3 << 12) | (rd << 7) | opcode
+}
+
+fn s_type(funct3: u32, rs1: u32, rs2: u32, imm: i32) -> u32 {
+    let imm = imm as u32;
+    (((imm >> 5) & 0x7F) << 25)
+        | (rs2 << 20)
+        | (rs1 << 15)
+        | (funct3 << 12)
+        | ((imm & 0x1F) << 7)
+        | 0x23
+}
+
+fn r_type(funct3: u32, rd: u32, rs1: u32, rs2: u32) -> u32 {
+    (rs2 << 20) | (rs1 << 15) | (funct3 << 12) | (rd << 7) | 0x33
+}
+
+fn b_type(funct3: u32, rs1: u32, rs2: u32, offset: i32) -> u32 {
+    let imm = offset as u32;
+    (((imm >> 12) & 0x1) << 31)
+        | (((imm >> 5) & 0x3F) << 25)
+        | (rs2 << 20)
+        | (rs1 << 15)
+        | (funct3 << 12)
+        | (((imm >> 1) & 0xF) << 8)
+        | (((imm >> 11) & 0x1) << 7)
+        | 0x63
+}
+
+fn addi(rd: u32, rs1: u32, imm: i32) -> u32 {
+    i_type(0x13, 0b000, rd, rs1, imm)
+}
+
+fn srli(rd: u32, rs1: u32, shamt: i32) -> u32 {
+    i_type(0x13, 0b101, rd, rs1, shamt)
+}
+
+fn lbu(rd: u32, rs1: u32, offset: i32) -> u32 {
+    i_type(0x03, 0b100, rd, rs1, offset)
+}
+
+fn lhu(rd: u32, rs1: u32, offset: i32) -> u32 {
+    i_type(0x03, 0b101, rd, rs1, offset)
+}
+
+fn sb(rs2: u32, rs1: u32, offset: i32) -> u32 {
+    s_type(0b000, rs1, rs2, offset)
+}
+
+fn sh(rs2: u32, rs1: u32, offset: i32) -> u32 {
+    s_type(0b001, rs1, rs2, offset)
+}
+
+fn add(rd: u32, rs1: u32, rs2: u32) -> u32 {
+    r_type(0b000, rd, rs1, rs2)
+}
+
+fn xor(rd: u32, rs1: u32, rs2: u32) -> u32 {
+    r_type(0b100, rd, rs1, rs2)
+}
+
+fn or(rd: u32, rs1: u32, rs2: u32) -> u32 {
+    r_type(0b110, rd, rs1, rs2)
+}
+
+fn and(rd: u32, rs1: u32, rs2: u32) -> u32 {
+    r_type(0b111, rd, rs1, rs2)
+}
+
+fn beq(rs1: u32, rs2: u32, offset: i32) -> u32 {
+    b_type(0b000, rs1, rs2, offset)
+}
+
+fn bne(rs1: u32, rs2: u32, offset: i32) -> u32 {
+    b_type(0b001, rs1, rs2, offset)
+}
+
+fn lui(rd: u32, imm: u32) -> u32 {
+    (imm << 12) | (rd << 7) | 0x37
+}
+
+fn ret() -> u32 {
+    i_type(0x67, 0b000, ZERO, RA, 0)
+}
+
+/// Load a 16-bit constant into rd, returning the one or two words needed
+fn li(rd: u32, value: u16) -> ([u32; 2], usize) {
+    let value = value as i32;
+    if value < 0x800 {
+        ([addi(rd, ZERO, value), 0], 1)
+    } else {
+        // addi sign extends, so round the upper part up when the low 12 bits look negative
+        let upper = (value + 0x800) >> 12;
+        let lower = value - (upper << 12);
+        ([lui(rd, upper as u32), addi(rd, rd, lower)], 2)
+    }
+}
+
+/// A translated run of CHIP-8 instructions
+#[derive(Debug, Clone, Copy)]
+struct Block {
+    /// The CHIP-8 address range [start, end) the block was translated from
+    start: u16,
+    end: u16,
+    /// The offset of the block in the code buffer, in words
+    offset: u16,
+    /// The number of CHIP-8 instructions executed by one run of the block
+    instructions: u8,
+}
+
+/// Translates hot CHIP-8 basic blocks into RV32I machine code. Only register and I arithmetic,
+/// jumps and skips are translated, a block ends at the first instruction that is not and the
+/// interpreter executes it. Blocks the program writes to are discarded and never translated again.
+pub struct Jit {
+    /// The block index for each instruction address in the decode cache range
+    entries: [u8; DECODE_CACHE_ENTRIES],
+    /// The number of times the interpreter has reached each instruction address
+    heat: [u8; DECODE_CACHE_ENTRIES],
+    blocks: [Block; MAX_BLOCKS],
+    num_blocks: usize,
+    /// The number of words of the code buffer in use
+    code_len: usize,
+}
+
+impl Jit {
+    pub fn new() -> Self {
+        Self {
+            entries: [NO_BLOCK; DECODE_CACHE_ENTRIES],
+            heat: [0; DECODE_CACHE_ENTRIES],
+            blocks: [Block {
+                start: 0,
+                end: 0,
+                offset: 0,
+                instructions: 0,
+            }; MAX_BLOCKS],
+            num_blocks: 0,
+            code_len: 0,
+        }
+    }
+
+    /// Drop every translated block and reuse the code buffer from the start
+    fn flush(&mut self) {
+        self.entries = [NO_BLOCK; DECODE_CACHE_ENTRIES];
+        self.num_blocks = 0;
+        self.code_len = 0;
+    }
+
+    /// Discard the blocks overlapping [low, high] and blacklist their entry points so that self
+    /// modifying code is left to the interpreter
+    fn invalidate(&mut self, low: usize, high: usize) {
+        for block in &self.blocks[..self.num_blocks] {
+            if (block.start as usize) <= high && (block.end as usize) > low {
+                let entry = Memory::decode_cache_index(block.start as usize).unwrap();
+                self.entries[entry] = NO_BLOCK;
+                self.heat[entry] = BLACKLISTED;
+            }
+        }
+    }
+
+    /// Run the translated block at PC, translating it first if it has become hot. Returns the
+    /// number of CHIP-8 instructions executed, 0 if the interpreter should execute the next
+    /// instruction instead.
+    pub fn run(&mut self, registers: &mut Registers, memory: &mut Memory) -> usize {
+        if let Some((low, high)) = memory.take_dirty_code() {
+            self.invalidate(low, high);
+        }
+
+        let Some(entry) = Memory::decode_cache_index(registers.pc as usize) else {
+            return 0;
+        };
+
+        let index = match self.entries[entry] {
+            NO_BLOCK => {
+                if self.heat[entry] == BLACKLISTED {
+                    return 0;
+                }
+
+                self.heat[entry] = (self.heat[entry] + 1).min(HOT_THRESHOLD);
+                if self.heat[entry] < HOT_THRESHOLD {
+                    return 0;
+                }
+
+                match self.translate(registers, memory) {
+                    Some(index) => index,
+                    None => {
+                        self.heat[entry] = BLACKLISTED;
+                        return 0;
+                    }
+                }
+            }
+            index => index,
+        };
+
+        let block = self.blocks[index as usize];
+        let next_pc = unsafe {
+            let code = (&raw mut CODE_BUFFER as *mut u32).add(block.offset as usize);
+            let function: BlockFn = core::mem::transmute(code);
+            function(registers.v.as_mut_ptr(), &mut registers.i)
+        };
+        registers.pc = next_pc as u16;
+        block.instructions as usize
+    }
+
+    /// Translate the block starting at PC, returning its index or None if the first instruction
+    /// cannot be translated
+    fn translate(&mut self, registers: &Registers, memory: &mut Memory) -> Option<u8> {
+        if self.num_blocks == MAX_BLOCKS || self.code_len + MAX_BLOCK_WORDS > CODE_BUFFER_WORDS {
+            self.flush();
+        }
+
+        let mut code = [0u32; MAX_BLOCK_WORDS];
+        let mut len = 0;
+        let mut emit = |words: &[u32]| {
+            code[len..len + words.len()].copy_from_slice(words);
+            len += words.len();
+        };
+
+        let start = registers.pc;
+        let mut pc = start;
+        let mut instructions = 0;
+
+        // Skips over an XO-CHIP F000 NNNN are four bytes wide so are left to the interpreter
+        let translate_skips = registers.platform != Platform::XoChip;
+
+        let end = loop {
+            if instructions == MAX_BLOCK_INSTRUCTIONS
+                || pc as usize >= DECODE_CACHE_END
+                || memory
+                    .check_range(pc as usize, INSTRUCTION_SIZE as usize)
+                    .is_err()
+            {
+                let (words, count) = li(A0, pc);
+                emit(&words[..count]);
+                emit(&[ret()]);
+                break pc;
+            }
+
+            let opcode = memory.fetch(pc as usize);
+            let (x, y) = match opcode {
+                Opcode::Move { x, y }
+                | Opcode::Or { x, y }
+                | Opcode::And { x, y }
+                | Opcode::Xor { x, y }
+                | Opcode::AddReg { x, y }
+                | Opcode::SkipEqReg { x, y }
+                | Opcode::SkipNeReg { x, y } => (x as i32, y as i32),
+                Opcode::LoadImm { x, .. }
+                | Opcode::AddImm { x, .. }
+                | Opcode::SkipEqImm { x, .. }
+                | Opcode::SkipNeImm { x, .. }
+                | Opcode::AddI { x } => (x as i32, 0),
+                _ => (0, 0),
+            };
+
+            match opcode {
+                Opcode::LoadImm { nn, .. } => {
+                    emit(&[addi(T0, ZERO, nn as i32), sb(T0, A0, x)]);
+                }
+                Opcode::AddImm { nn, .. } => {
+                    emit(&[lbu(T0, A0, x), addi(T0, T0, nn as i32), sb(T0, A0, x)]);
+                }
+                Opcode::Move { .. } => {
+                    emit(&[lbu(T0, A0, y), sb(T0, A0, x)]);
+                }
+                Opcode::Or { .. } | Opcode::And { .. } | Opcode::Xor { .. } => {
+                    let op = match opcode {
+                        Opcode::Or { .. } => or(T0, T0, T1),
+                        Opcode::And { .. } => and(T0, T0, T1),
+                        _ => xor(T0, T0, T1),
+                    };
+                    emit(&[lbu(T0, A0, x), lbu(T1, A0, y), op, sb(T0, A0, x)]);
+                    if registers.quirks.logic_resets_vf {
+                        emit(&[sb(ZERO, A0, VF as i32)]);
+                    }
+                }
+                Opcode::AddReg { .. } => {
+                    // The carry is bit 8 of the sum, VF is written after VX as the interpreter does
+                    emit(&[
+                        lbu(T0, A0, x),
+                        lbu(T1, A0, y),
+                        add(T0, T0, T1),
+                        srli(T2, T0, 8),
+                        sb(T0, A0, x),
+                        sb(T2, A0, VF as i32),
+                    ]);
+                }
+                Opcode::SetI { nnn } => {
+                    let (words, count) = li(T0, nnn);
+                    emit(&words[..count]);
+                    emit(&[sh(T0, A1, 0)]);
+                }
+                Opcode::AddI { .. } => {
+                    emit(&[
+                        lhu(T0, A1, 0),
+                        lbu(T1, A0, x),
+                        add(T0, T0, T1),
+                        sh(T0, A1, 0),
+                    ]);
+                }
+                Opcode::Jump { nnn } => {
+                    let (words, count) = li(A0, nnn);
+                    emit(&words[..count]);
+                    emit(&[ret()]);
+                    instructions += 1;
+                    break pc + INSTRUCTION_SIZE;
+                }
+                Opcode::SkipEqImm { nn, .. } | Opcode::SkipNeImm { nn, .. } if translate_skips => {
+                    emit(&[lbu(T0, A0, x), addi(T1, ZERO, nn as i32)]);
+                    emit_skip(&mut emit, opcode, pc);
+                    instructions += 1;
+                    break pc + INSTRUCTION_SIZE;
+                }
+                Opcode::SkipEqReg { .. } | Opcode::SkipNeReg { .. } if translate_skips => {
+                    emit(&[lbu(T0, A0, x), lbu(T1, A0, y)]);
+                    emit_skip(&mut emit, opcode, pc);
+                    instructions += 1;
+                    break pc + INSTRUCTION_SIZE;
+                }
+                _ => {
+                    // Hand the unsupported instruction back to the interpreter
+                    let (words, count) = li(A0, pc);
+                    emit(&words[..count]);
+                    emit(&[ret()]);
+                    break pc;
+                }
+            }
+
+            instructions += 1;
+            pc += INSTRUCTION_SIZE;
+        };
+
+        if instructions == 0 {
+            return None;
+        }
+
+        let offset = self.code_len;
+        unsafe {
+            let buffer = &raw mut CODE_BUFFER as *mut u32;
+            for (word, instruction) in code[..len].iter().enumerate() {
+                buffer.add(offset + word).write_volatile(*instruction);
+            }
+
+            // Make the new code visible to instruction fetch
+            asm!(
+                ".option push",
+                ".option arch, +zifencei",
+                "fence.i",
+                ".option pop"
+            );
+        }
+        self.code_len += len;
+
+        let index = self.num_blocks;
+        self.blocks[index] = Block {
+            start,
+            end,
+            offset: offset as u16,
+            instructions: instructions as u8,
+        };
+        self.num_blocks += 1;
+
+        let entry = Memory::decode_cache_index(start as usize).unwrap();
+        self.entries[entry] = index as u8;
+        Some(index as u8)
+    }
+}
+
+/// Emit the tail of a skip instruction with the compared values in T0 and T1. The block returns
+/// the address of the next instruction, or the one after it if the skip is taken.
+fn emit_skip(emit: &mut impl FnMut(&[u32]), opcode: Opcode, pc: u16) {
+    let (next, next_count) = li(A0, pc + INSTRUCTION_SIZE);
+    let (skipped, skipped_count) = li(A0, pc + INSTRUCTION_SIZE * 2);
+
+    // Branch over the skipped PC load when the skip is not taken
+    let offset = 4 * (skipped_count as i32 + 1);
+    let branch = match opcode {
+        Opcode::SkipEqImm { .. } | Opcode::SkipEqReg { .. } => bne(T0, T1, offset),
+        _ => beq(T0, T1, offset),
+    };
+
+    emit(&next[..next_count]);
+    emit(&[branch]);
+    emit(&skipped[..skipped_count]);
+    emit(&[ret()]);
+}
@@ -0,0 +1,21 @@
+//! The CHIP-8 interpreter: the CPU, memory and the machine that steps them. The crate is no_std
+//! and has no dependencies on the hart, so it builds on the host for testing as well as for
+//! riscv32i. Anything that talks to the board (DMA, CSRs, the video out) lives in chip-8-rust.
+#![no_std]
+
+pub mod breakpoint;
+pub mod container;
+pub mod cpu;
+pub mod cycles;
+pub mod fault;
+pub mod font;
+#[cfg(feature = "jit")]
+pub mod jit;
+pub mod machine;
+pub mod memory;
+pub mod opcode;
+pub mod quirks;
+pub mod rand;
+pub mod replay;
+pub mod rewind;
+pub mod snapshot;
@@ -0,0 +1,268 @@
+use crate::breakpoint::{Condition, DebugTable, Trigger};
+use crate::cpu::{Cpu, NUM_KEYS, Platform, StepOutcome};
+use crate::fault::Fault;
+#[cfg(feature = "jit")]
+use crate::jit::Jit;
+use crate::memory::{Framebuffer, Memory};
+use crate::quirks::Quirks;
+use crate::rand::Rand;
+
+pub struct Machine {
+    pub cpu: Cpu,
+    pub memory: Memory,
+    /// Translated code for hot blocks, used in place of the interpreter while jit_enabled is set
+    #[cfg(feature = "jit")]
+    jit: Jit,
+    #[cfg(feature = "jit")]
+    jit_enabled: bool,
+
+    /// The fault that stopped the machine, if any. A faulted machine no longer steps.
+    pub fault: Option<Fault>,
+
+    /// PC breakpoints and conditional register breakpoints. Watchpoints are in memory.
+    debug: DebugTable,
+
+    /// Set when resuming from a breakpoint so the instruction at the breakpoint executes
+    resuming: bool,
+}
+
+impl Machine {
+    /// Create a new machine running the program already in memory
+    ///
+    /// # Safety
+    ///
+    /// framebuffer must be valid for reads and writes for as long as the machine is used, see
+    /// Memory::new.
+    pub unsafe fn new(
+        memory: &'static mut [u8],
+        framebuffer: Framebuffer,
+        platform: Platform,
+        quirks: Quirks,
+    ) -> Self {
+        Self {
+            cpu: Cpu::new(platform, quirks),
+            memory: unsafe { Memory::new(memory, platform, quirks.font, framebuffer) },
+            #[cfg(feature = "jit")]
+            jit: Jit::new(),
+            #[cfg(feature = "jit")]
+            jit_enabled: true,
+            fault: None,
+            debug: DebugTable::new(),
+            resuming: false,
+        }
+    }
+
+    /// Restart the random number generator from seed, see Rand::from_seed. A new machine uses
+    /// DEFAULT_SEED.
+    pub fn seed(&mut self, seed: u32) {
+        self.cpu
+            .registers
+            .set_rng_state(Rand::from_seed(seed).state());
+    }
+
+    /// Give back the memory backing the machine, so a new machine can be loaded into it
+    pub fn into_memory(self) -> &'static mut [u8] {
+        self.memory.into_memory()
+    }
+
+    /// Stop before executing the instruction at pc. Returns false if every breakpoint slot is
+    /// already in use.
+    pub fn add_breakpoint(&mut self, pc: u16) -> bool {
+        self.debug.add_breakpoint(pc)
+    }
+
+    pub fn remove_breakpoint(&mut self, pc: u16) {
+        self.debug.remove_breakpoint(pc);
+    }
+
+    /// Stop after any instruction that writes to the length bytes starting at address. Returns
+    /// false if every watchpoint slot is already in use.
+    pub fn add_watchpoint(&mut self, address: u16, length: u16) -> bool {
+        self.memory.watchpoints.add(address, length)
+    }
+
+    /// Remove the watchpoint starting at address
+    pub fn remove_watchpoint(&mut self, address: u16) {
+        self.memory.watchpoints.remove(address);
+    }
+
+    /// Stop after any instruction that makes condition true. Returns false if every condition
+    /// slot is already in use.
+    pub fn add_condition(&mut self, condition: Condition) -> bool {
+        self.debug.add_condition(condition, &self.cpu.registers)
+    }
+
+    pub fn remove_condition(&mut self, condition: Condition) {
+        self.debug.remove_condition(condition);
+    }
+
+    /// Let the next step execute the instruction at the current breakpoint rather than stopping
+    pub fn resume(&mut self) {
+        self.resuming = true;
+    }
+
+    /// Select between the JIT and the interpreter. The JIT is enabled by default.
+    #[cfg(feature = "jit")]
+    pub fn set_jit_enabled(&mut self, enabled: bool) {
+        self.jit_enabled = enabled;
+    }
+
+    /// Run a translated block at PC if the JIT has one, returning the number of instructions it
+    /// executed
+    #[cfg(feature = "jit")]
+    fn run_native(&mut self) -> usize {
+        // Translated blocks are not charged VIP cycles and do not check the debug table, so VIP
+        // timing and breakpoints always interpret. Translated blocks never write memory so
+        // watchpoints do not need the interpreter.
+        if self.jit_enabled && !self.cpu.vip_timing && !self.debug.is_active() {
+            self.jit.run(&mut self.cpu.registers, &mut self.memory)
+        } else {
+            0
+        }
+    }
+
+    #[cfg(not(feature = "jit"))]
+    fn run_native(&mut self) -> usize {
+        0
+    }
+
+    /// Set the machine key to the given state and clear the wait_for_key register if necessary
+    pub fn set_key(&mut self, key: u8, state: bool) {
+        let current = self.cpu.registers.keys[key as usize];
+
+        if state
+            && !current
+            && let Some(register) = self.cpu.registers.wait_for_key
+        {
+            self.cpu.registers.v[register] = key;
+            self.cpu.registers.wait_for_key = None;
+        }
+
+        self.cpu.registers.keys[key as usize] = state;
+    }
+
+    /// Set every key from a keypad bitmask where bit n is set while key n is held
+    pub fn set_keys(&mut self, keys: u16) {
+        for key in 0..NUM_KEYS as u8 {
+            self.set_key(key, keys & (1 << key) != 0);
+        }
+    }
+
+    /// The keypad state as a bitmask where bit n is set while key n is held
+    pub fn keys(&self) -> u16 {
+        self.cpu
+            .registers
+            .keys
+            .iter()
+            .enumerate()
+            .fold(0, |keys, (key, held)| keys | (*held as u16) << key)
+    }
+
+    /// Return true if the device should currently be playing sound
+    pub fn sound(&self) -> bool {
+        self.cpu.registers.sound > 0
+    }
+
+    /// Decrement the delay and sound timers, called once per 60Hz frame
+    pub fn tick_timers(&mut self) {
+        if self.cpu.registers.sound > 0 {
+            self.cpu.registers.sound -= 1;
+        }
+
+        if self.cpu.registers.delay > 0 {
+            self.cpu.registers.delay -= 1;
+        }
+    }
+
+    /// Run one 60Hz frame and then tick the timers. budget is the number of instructions to run,
+    /// or the number of VIP machine cycles with VIP timing enabled. The frame ends early if the
+    /// machine starts waiting for a key or the display, or exits.
+    pub fn run_frame(&mut self, budget: usize) -> Result<StepOutcome, Fault> {
+        self.run_frame_drawing(budget, || {})
+    }
+
+    /// As run_frame, calling drawn after every instruction that clears, scrolls or draws to the
+    /// framebuffer
+    pub fn run_frame_drawing(
+        &mut self,
+        budget: usize,
+        mut drawn: impl FnMut(),
+    ) -> Result<StepOutcome, Fault> {
+        let mut used = 0;
+        let mut outcome = StepOutcome::Executed;
+        self.memory.take_drawn();
+
+        while used < budget {
+            let (step_outcome, cost) = self.step_counted()?;
+            if self.memory.take_drawn() {
+                drawn();
+            }
+            outcome = step_outcome;
+            used += cost;
+            match outcome {
+                StepOutcome::Executed => {}
+                StepOutcome::WaitingForKey
+                | StepOutcome::WaitingForDisplay
+                | StepOutcome::Exited
+                | StepOutcome::Breakpoint(_) => break,
+            }
+        }
+
+        self.tick_timers();
+        Ok(outcome)
+    }
+
+    /// Step the machine. The timers are not ticked, see run_frame. If the CPU faults the fault is
+    /// returned and the machine stops, every later step returns the same fault.
+    pub fn step(&mut self) -> Result<StepOutcome, Fault> {
+        self.step_counted().map(|(outcome, _)| outcome)
+    }
+
+    /// Step the machine, returning the outcome and its cost. The cost is the number of instructions
+    /// executed, or the VIP machine cycles used with VIP timing enabled.
+    fn step_counted(&mut self) -> Result<(StepOutcome, usize), Fault> {
+        if let Some(fault) = self.fault {
+            return Err(fault);
+        }
+
+        // Only step the CPU if we are not waiting for a key press and the program has not exited.
+        // A translated block can execute several instructions in one step.
+        let mut instructions = 1;
+        let cycles = self.cpu.cycles;
+        let pc = self.cpu.registers.pc;
+        let outcome = if self.cpu.registers.halted {
+            StepOutcome::Exited
+        } else if self.cpu.registers.wait_for_key.is_some() {
+            StepOutcome::WaitingForKey
+        } else if !core::mem::take(&mut self.resuming) && self.debug.is_breakpoint(pc) {
+            StepOutcome::Breakpoint(Trigger::Breakpoint { pc })
+        } else if let native @ 1.. = self.run_native() {
+            instructions = native;
+            StepOutcome::Executed
+        } else {
+            // Drop writes made outside of a step, such as by the debugger or rewind
+            self.memory.watchpoints.take_hit();
+            let outcome = match self.cpu.step(&mut self.memory) {
+                Ok(outcome) => outcome,
+                Err(fault) => {
+                    self.fault = Some(fault);
+                    return Err(fault);
+                }
+            };
+
+            if let Some((address, value)) = self.memory.watchpoints.take_hit() {
+                StepOutcome::Breakpoint(Trigger::Watchpoint { pc, address, value })
+            } else if let Some(condition) = self.debug.check_conditions(&self.cpu.registers) {
+                StepOutcome::Breakpoint(Trigger::Condition { pc, condition })
+            } else {
+                outcome
+            }
+        };
+
+        if self.cpu.vip_timing {
+            Ok((outcome, self.cpu.cycles.wrapping_sub(cycles) as usize))
+        } else {
+            Ok((outcome, instructions))
+        }
+    }
+}
@@ -0,0 +1,476 @@
+use crate::breakpoint::Watchpoints;
+use crate::cpu::Platform;
+use crate::fault::FaultKind;
+use crate::font::{BIG_FONT, Font, SMALL_GLYPH_SIZE};
+use crate::opcode::{Opcode, decode};
+
+pub type Framebuffer = *mut [u8; SCREEN_SIZE];
+
+/// The CHIP-8 VM has 4kb of user accessible memory, XO-CHIP extends this to a full 16-bit (64kb)
+/// address space. See Platform::address_space. chip-8-rust backs every platform with 4kb, so
+/// XO-CHIP ROMs there are limited to 4kb and accesses at 0x1000 and above fault, see Memory::new.
+pub const MEMORY_SIZE: usize = 1024 * 64;
+
+/// Programs are loaded and start executing here, below is reserved for the interpreter
+pub const PROGRAM_ADDRESS: usize = 0x200;
+
+/// The hex font is copied into the reserved interpreter area, at the address most interpreters
+/// use, followed by the SUPER-CHIP big font
+pub const FONT_ADDRESS: usize = 0x050;
+pub const BIG_FONT_ADDRESS: usize = FONT_ADDRESS + SMALL_GLYPH_SIZE * 16;
+
+/// XO-CHIP has two bitplanes. Only plane 1 is selected by default.
+pub const DEFAULT_PLANES: u8 = 0b01;
+
+/// Instructions at even addresses in the CHIP-8 program area (0x200 - 0x1000) are pre-decoded and
+/// cached. Instructions outside of this range, or at odd addresses, are decoded on every fetch.
+pub const DECODE_CACHE_START: usize = 0x200;
+pub const DECODE_CACHE_END: usize = 0x1000;
+pub const DECODE_CACHE_ENTRIES: usize = (DECODE_CACHE_END - DECODE_CACHE_START) / 2;
+
+/// Writes to memory are tracked in pages of this many bytes so rewind can save only what changed
+pub const PAGE_SIZE: usize = 64;
+pub const NUM_PAGES: usize = MEMORY_SIZE / PAGE_SIZE;
+pub const DIRTY_PAGE_WORDS: usize = NUM_PAGES / 32;
+
+/// The framebuffer is always stored at the SUPER-CHIP hi-res resolution. In lo-res mode every
+/// CHIP-8 pixel is drawn as a 2x2 block so the framebuffer layout never changes.
+pub const SCREEN_WIDTH: usize = 128;
+pub const SCREEN_HEIGHT: usize = 64;
+
+/// The framebuffer packs one pixel per bit, with the lowest bit of each byte leftmost
+pub const SCREEN_SIZE: usize = (SCREEN_WIDTH * SCREEN_HEIGHT) / 8;
+
+/// The original CHIP-8 (lo-res) screen resolution
+pub const LORES_WIDTH: usize = 64;
+pub const LORES_HEIGHT: usize = 32;
+
+/// A lo-res image packed as the framebuffer is. This is the layout of the board's hardware
+/// framebuffer, see downsample.
+pub const LORES_SIZE: usize = (LORES_WIDTH * LORES_HEIGHT) / 8;
+
+/// The number of pixels SUPER-CHIP scrolls by on a scroll left or right
+pub const SCROLL_HORIZONTAL_AMOUNT: usize = 4;
+
+/// Downsample a framebuffer to lo-res. Each lo-res pixel is lit if any pixel of its 2x2 block is,
+/// so lo-res games come through exactly and hi-res lines one pixel wide stay visible.
+pub fn downsample(frame_buffer: &[u8; SCREEN_SIZE], lores: &mut [u8; LORES_SIZE]) {
+    const ROW_BYTES: usize = SCREEN_WIDTH / 8;
+    const LORES_ROW_BYTES: usize = LORES_WIDTH / 8;
+
+    for y in 0..LORES_HEIGHT {
+        let top = &frame_buffer[2 * y * ROW_BYTES..][..ROW_BYTES];
+        let bottom = &frame_buffer[(2 * y + 1) * ROW_BYTES..][..ROW_BYTES];
+        for column in 0..LORES_ROW_BYTES {
+            // 16 framebuffer pixels make up the 8 lo-res pixels of an output byte
+            let low = (top[2 * column] | bottom[2 * column]) as u16;
+            let high = (top[2 * column + 1] | bottom[2 * column + 1]) as u16;
+            let pairs = low | (high << 8);
+            let pairs = pairs | (pairs >> 1);
+            lores[y * LORES_ROW_BYTES + column] = (0..8)
+                .filter(|bit| pairs & (1 << (2 * bit)) != 0)
+                .fold(0, |byte, bit| byte | (1 << bit));
+        }
+    }
+}
+
+/// The memory structure contains the user accessible data and the current frame buffer.
+///
+/// The hardware framebuffer is 1bpp, so the two XO-CHIP bitplanes share it. Drawing, clearing
+/// and scrolling only happen when at least one plane is selected. When both planes are selected
+/// a sprite holds a layer for each plane back to back and the two layers are ORed together and
+/// drawn as a single monochrome sprite.
+pub struct Memory {
+    /// The bytes backing memory. Addresses in the platform address space past the end of this
+    /// fault.
+    memory: &'static mut [u8],
+    pub frame_buffer: Framebuffer,
+
+    /// Addresses are masked with this, so they wrap at the end of the platform address space
+    address_mask: usize,
+
+    /// True if the display is in SUPER-CHIP 128x64 hi-res mode
+    hires: bool,
+
+    /// The XO-CHIP bitplane mask selected by FN01
+    planes: u8,
+
+    /// Set whenever an instruction clears, scrolls or draws to the framebuffer, see take_drawn
+    drawn: bool,
+
+    /// Pre-decoded instructions indexed by (pc - DECODE_CACHE_START) / 2. Writes to memory
+    /// invalidate any entry whose instruction covers the written byte.
+    decode_cache: [Option<Opcode>; DECODE_CACHE_ENTRIES],
+    decode_cache_enabled: bool,
+
+    /// The lowest and highest address written in the decode cache range since the JIT last
+    /// checked, so that translated blocks covering those addresses can be discarded
+    #[cfg(feature = "jit")]
+    dirty_code: Option<(usize, usize)>,
+
+    /// A bit per page written since the last take_dirty_pages
+    dirty_pages: [u32; DIRTY_PAGE_WORDS],
+
+    /// Memory write watchpoints, checked on every set
+    pub watchpoints: Watchpoints,
+}
+
+impl Memory {
+    /// Create a memory for platform backed by memory, with font and the big font loaded. The rest
+    /// of memory is left as it is so a ROM can be copied in first. Backing memory smaller than the
+    /// platform address space is allowed, addresses still wrap at the end of the address space and
+    /// accesses past the end of backing memory fault with MemoryOutOfRange rather than landing on
+    /// memory in use. The framebuffer is cleared.
+    ///
+    /// # Safety
+    ///
+    /// frame_buffer must be valid for reads and writes for as long as the memory is used. On the
+    /// board it is a fixed region of RAM that lives as long as the program.
+    pub unsafe fn new(
+        memory: &'static mut [u8],
+        platform: Platform,
+        font: Font,
+        frame_buffer: Framebuffer,
+    ) -> Self {
+        for i in 0..SCREEN_SIZE {
+            unsafe { (*frame_buffer)[i] = 0 };
+        }
+
+        let address_mask = platform.address_space() - 1;
+        let mut memory = Self {
+            memory,
+            frame_buffer,
+            address_mask,
+            hires: false,
+            planes: DEFAULT_PLANES,
+            drawn: false,
+            decode_cache: [None; DECODE_CACHE_ENTRIES],
+            decode_cache_enabled: true,
+            #[cfg(feature = "jit")]
+            dirty_code: None,
+            dirty_pages: [0; DIRTY_PAGE_WORDS],
+            watchpoints: Watchpoints::new(),
+        };
+
+        for (offset, byte) in font.glyphs().iter().enumerate() {
+            memory.set(FONT_ADDRESS + offset, *byte);
+        }
+
+        for (offset, byte) in BIG_FONT.iter().enumerate() {
+            memory.set(BIG_FONT_ADDRESS + offset, *byte);
+        }
+
+        memory
+    }
+
+    /// Give back the memory backing this, so a new machine can reuse it
+    pub fn into_memory(self) -> &'static mut [u8] {
+        self.memory
+    }
+
+    /// Check that the len bytes starting at address are all backed by memory, wrapping at the end
+    /// of the address space. When the whole address space is backed every range is.
+    pub fn check_range(&self, address: usize, len: usize) -> Result<(), FaultKind> {
+        let start = address & self.address_mask;
+        if self.memory.len() > self.address_mask || start + len <= self.memory.len() {
+            Ok(())
+        } else {
+            Err(FaultKind::MemoryOutOfRange { address })
+        }
+    }
+
+    /// The number of bytes backing memory
+    pub fn size(&self) -> usize {
+        self.memory.len()
+    }
+
+    /// Get a u8 from memory. Addresses wrap at the end of the platform address space. Callers
+    /// check addresses computed by the guest with check_range first, an address past the end of
+    /// backing memory panics.
+    pub fn get(&self, idx: usize) -> u8 {
+        self.memory[idx & self.address_mask]
+    }
+
+    /// Set a u8 in memory. Addresses wrap as with get.
+    pub fn set(&mut self, idx: usize, val: u8) {
+        let idx = idx & self.address_mask;
+        self.memory[idx] = val;
+
+        let page = idx / PAGE_SIZE;
+        self.dirty_pages[page / 32] |= 1 << (page % 32);
+        self.watchpoints.check(idx, val);
+
+        // A write can land in either byte of a cached instruction
+        for address in [idx, idx.wrapping_sub(1)] {
+            if let Some(entry) = Self::decode_cache_index(address) {
+                self.decode_cache[entry] = None;
+
+                #[cfg(feature = "jit")]
+                {
+                    self.dirty_code = match self.dirty_code {
+                        Some((low, high)) => Some((low.min(idx), high.max(idx))),
+                        None => Some((idx, idx)),
+                    };
+                }
+            }
+        }
+    }
+
+    /// Take the range of code addresses written since the last call, if any
+    #[cfg(feature = "jit")]
+    pub fn take_dirty_code(&mut self) -> Option<(usize, usize)> {
+        self.dirty_code.take()
+    }
+
+    /// Take the set of pages written since the last call, as a bit per page
+    pub fn take_dirty_pages(&mut self) -> [u32; DIRTY_PAGE_WORDS] {
+        core::mem::replace(&mut self.dirty_pages, [0; DIRTY_PAGE_WORDS])
+    }
+
+    /// The decode cache entry for an instruction at address, if it is cacheable
+    pub fn decode_cache_index(address: usize) -> Option<usize> {
+        if (DECODE_CACHE_START..DECODE_CACHE_END).contains(&address) && address & 1 == 0 {
+            Some((address - DECODE_CACHE_START) / 2)
+        } else {
+            None
+        }
+    }
+
+    /// Enable or disable the decode cache. Disabling it also drops every cached entry.
+    pub fn set_decode_cache_enabled(&mut self, enabled: bool) {
+        self.decode_cache_enabled = enabled;
+        self.decode_cache = [None; DECODE_CACHE_ENTRIES];
+    }
+
+    /// Fetch and decode the instruction at pc, using the decode cache where possible
+    pub fn fetch(&mut self, pc: usize) -> Opcode {
+        let entry = match Self::decode_cache_index(pc) {
+            Some(entry) if self.decode_cache_enabled => entry,
+            _ => return decode(self.get16(pc)),
+        };
+
+        match self.decode_cache[entry] {
+            Some(opcode) => opcode,
+            None => {
+                let opcode = decode(self.get16(pc));
+                self.decode_cache[entry] = Some(opcode);
+                opcode
+            }
+        }
+    }
+
+    /// Return a u16 in system order from memory, performing necessary endianness conversion
+    pub fn get16(&self, idx: usize) -> u16 {
+        let first_part = self.get(idx);
+        let second_part = self.get(idx + 1);
+        let combined = first_part as u16 | (second_part as u16) << 8;
+        u16::from_be(combined)
+    }
+
+    /// The selected XO-CHIP bitplanes
+    pub fn planes(&self) -> u8 {
+        self.planes
+    }
+
+    /// Select the XO-CHIP bitplanes that drawing, clearing and scrolling apply to
+    pub fn select_planes(&mut self, planes: u8) {
+        self.planes = planes & 0b11;
+    }
+
+    /// True if the framebuffer has been cleared, scrolled or drawn to since the last call
+    pub fn take_drawn(&mut self) -> bool {
+        core::mem::take(&mut self.drawn)
+    }
+
+    /// Clear the entire framebuffer
+    pub fn clear_display(&mut self) {
+        if self.planes == 0 {
+            return;
+        }
+        self.drawn = true;
+
+        for i in 0..SCREEN_SIZE {
+            unsafe {
+                (*self.frame_buffer)[i] = 0;
+            }
+        }
+    }
+
+    /// Switch between lo-res (64x32) and hi-res (128x64) mode. The display is cleared on a switch.
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.drawn = true;
+        for i in 0..SCREEN_SIZE {
+            unsafe {
+                (*self.frame_buffer)[i] = 0;
+            }
+        }
+    }
+
+    /// True if the display is in hi-res mode
+    pub fn hires(&self) -> bool {
+        self.hires
+    }
+
+    /// Restore the display resolution and selected planes without clearing the framebuffer
+    pub fn set_display_mode(&mut self, hires: bool, planes: u8) {
+        self.hires = hires;
+        self.select_planes(planes);
+    }
+
+    /// The width of the screen in the current resolution
+    pub fn width(&self) -> usize {
+        if self.hires {
+            SCREEN_WIDTH
+        } else {
+            LORES_WIDTH
+        }
+    }
+
+    /// The height of the screen in the current resolution
+    pub fn height(&self) -> usize {
+        if self.hires {
+            SCREEN_HEIGHT
+        } else {
+            LORES_HEIGHT
+        }
+    }
+
+    /// The number of framebuffer pixels along each axis used to draw a single pixel
+    fn scale(&self) -> usize {
+        if self.hires { 1 } else { 2 }
+    }
+
+    /// Read the framebuffer pixel at (x, y) in framebuffer coordinates
+    fn pixel(&self, x: usize, y: usize) -> bool {
+        let fb_idx = (y * SCREEN_WIDTH) + x;
+        unsafe { ((*self.frame_buffer)[fb_idx >> 3] >> (fb_idx & 0b111)) & 1 == 1 }
+    }
+
+    /// Write the framebuffer pixel at (x, y) in framebuffer coordinates
+    fn set_pixel(&mut self, x: usize, y: usize, value: bool) {
+        let fb_idx = (y * SCREEN_WIDTH) + x;
+        let byte_index = fb_idx >> 3;
+        let bit_index = fb_idx & 0b111;
+        unsafe {
+            let fb = &mut (*self.frame_buffer);
+            fb[byte_index] = (fb[byte_index] & !(1 << bit_index)) | (value as u8) << bit_index;
+        }
+    }
+
+    /// Scroll the display down by n framebuffer pixels. As on the HP48 the scroll amounts are in
+    /// hi-res pixels even when the display is in lo-res mode.
+    pub fn scroll_down(&mut self, n: usize) {
+        if self.planes == 0 {
+            return;
+        }
+        self.drawn = true;
+
+        for y in (0..SCREEN_HEIGHT).rev() {
+            for x in 0..SCREEN_WIDTH {
+                let value = y >= n && self.pixel(x, y - n);
+                self.set_pixel(x, y, value);
+            }
+        }
+    }
+
+    /// Scroll the display right by 4 framebuffer pixels
+    pub fn scroll_right(&mut self) {
+        if self.planes == 0 {
+            return;
+        }
+        self.drawn = true;
+
+        for y in 0..SCREEN_HEIGHT {
+            for x in (0..SCREEN_WIDTH).rev() {
+                let value =
+                    x >= SCROLL_HORIZONTAL_AMOUNT && self.pixel(x - SCROLL_HORIZONTAL_AMOUNT, y);
+                self.set_pixel(x, y, value);
+            }
+        }
+    }
+
+    /// Scroll the display left by 4 framebuffer pixels
+    pub fn scroll_left(&mut self) {
+        if self.planes == 0 {
+            return;
+        }
+        self.drawn = true;
+
+        for y in 0..SCREEN_HEIGHT {
+            for x in 0..SCREEN_WIDTH {
+                let value = x + SCROLL_HORIZONTAL_AMOUNT < SCREEN_WIDTH
+                    && self.pixel(x + SCROLL_HORIZONTAL_AMOUNT, y);
+                self.set_pixel(x, y, value);
+            }
+        }
+    }
+
+    /// XOR a sprite from memory at i onto the screen at (x, y). The start position always wraps,
+    /// pixels past the screen edges are either clipped or wrapped around depending on clip.
+    /// A sprite is n rows of 8 pixels, or a 16x16 sprite (two bytes per row) when n is 0. In
+    /// lo-res a zero height sprite is 8x16 instead if lores_8x16 is set.
+    /// Returns 1 if any pixel that was set is erased, otherwise 0.
+    pub fn draw_sprite(
+        &mut self,
+        x: usize,
+        y: usize,
+        n: usize,
+        i: usize,
+        clip: bool,
+        lores_8x16: bool,
+    ) -> Result<u8, FaultKind> {
+        let (rows, columns) = match n {
+            0 if lores_8x16 && !self.hires => (16, 8),
+            0 => (16, 16),
+            n => (n, 8),
+        };
+        let bytes_per_row = columns / 8;
+        let layers = self.planes.count_ones() as usize;
+        let layer_size = rows * bytes_per_row;
+        self.check_range(i, layers * layer_size)?;
+
+        let scale = self.scale();
+        let x = x % self.width();
+        let y = y % self.height();
+        let mut vf_reg = 0;
+        self.drawn = true;
+
+        for yoff in 0..rows {
+            if clip && y + yoff >= self.height() {
+                break;
+            }
+
+            let y = (y + yoff) % self.height();
+
+            for xoff in 0..columns {
+                if clip && x + xoff >= self.width() {
+                    break;
+                }
+
+                let x = (x + xoff) % self.width();
+                let byte_offset = (yoff * bytes_per_row) + (xoff / 8);
+                let sprite = (0..layers).fold(0, |acc, layer| {
+                    acc | self.get(i + (layer * layer_size) + byte_offset)
+                });
+
+                if sprite & (1 << (7 - (xoff % 8))) == 0 {
+                    continue;
+                }
+
+                if self.pixel(x * scale, y * scale) {
+                    vf_reg = 1;
+                }
+
+                for py in (y * scale)..((y + 1) * scale) {
+                    for px in (x * scale)..((x + 1) * scale) {
+                        let current = self.pixel(px, py);
+                        self.set_pixel(px, py, !current);
+                    }
+                }
+            }
+        }
+
+        Ok(vf_reg)
+    }
+}
@@ -0,0 +1,286 @@
+use crate::quirks::Quirks;
+use core::fmt;
+
+/// If opcode has the form _XN_ or _XR_ then the first register can be extracted with this mask
+const X_MASK: u16 = 0x0F00;
+
+/// If the opcode has the form _XR_ the second register can be extracted with this mask
+const Y_MASK: u16 = 0x00F0;
+
+/// If opcodes have the form __NN then the immediate value can be extracted with this mask
+const NN_MASK: u16 = 0x00FF;
+
+/// If opcodes have the form _NNN then the address can be extracted with this mask
+const NNN_MASK: u16 = 0x0FFF;
+
+/// If the opcode immediate contains only a single nibble of data (the final nibble of the opcode)
+/// we extract it with this mask
+const N_MASK: u16 = 0x000F;
+
+/// A decoded CHIP-8, SUPER-CHIP or XO-CHIP instruction. Register operands (x and y) are register
+/// indices rather than register values. Decoding accepts every extension, it is up to the CPU to
+/// reject instructions that the current platform does not support.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opcode {
+    /// 00CN (SUPER-CHIP) scroll the display down n pixels
+    ScrollDown { n: u8 },
+    /// 00E0 clear the display
+    ClearDisplay,
+    /// 00EE return from a subroutine
+    Return,
+    /// 00FB (SUPER-CHIP) scroll the display right 4 pixels
+    ScrollRight,
+    /// 00FC (SUPER-CHIP) scroll the display left 4 pixels
+    ScrollLeft,
+    /// 00FD (SUPER-CHIP) exit the interpreter
+    Exit,
+    /// 00FE (SUPER-CHIP) switch to the 64x32 lo-res display
+    LoRes,
+    /// 00FF (SUPER-CHIP) switch to the 128x64 hi-res display
+    HiRes,
+    /// 0NNN call a machine code routine, which we cannot run and skip
+    MachineCall { nnn: u16 },
+    /// 1NNN jump to nnn
+    Jump { nnn: u16 },
+    /// 2NNN call the subroutine at nnn
+    Call { nnn: u16 },
+    /// 3XNN skip the next instruction if VX == nn
+    SkipEqImm { x: u8, nn: u8 },
+    /// 4XNN skip the next instruction if VX != nn
+    SkipNeImm { x: u8, nn: u8 },
+    /// 5XY0 skip the next instruction if VX == VY
+    SkipEqReg { x: u8, y: u8 },
+    /// 5XY2 (XO-CHIP) store VX through VY to memory at I
+    SaveRange { x: u8, y: u8 },
+    /// 5XY3 (XO-CHIP) load VX through VY from memory at I
+    LoadRange { x: u8, y: u8 },
+    /// 6XNN VX = nn
+    LoadImm { x: u8, nn: u8 },
+    /// 7XNN VX += nn, without touching the carry flag
+    AddImm { x: u8, nn: u8 },
+    /// 8XY0 VX = VY
+    Move { x: u8, y: u8 },
+    /// 8XY1 VX |= VY
+    Or { x: u8, y: u8 },
+    /// 8XY2 VX &= VY
+    And { x: u8, y: u8 },
+    /// 8XY3 VX ^= VY
+    Xor { x: u8, y: u8 },
+    /// 8XY4 VX += VY, VF is set to the carry
+    AddReg { x: u8, y: u8 },
+    /// 8XY5 VX -= VY, VF is set to the borrow
+    SubReg { x: u8, y: u8 },
+    /// 8XY6 VX >>= 1, VF is set to the bit shifted out
+    ShiftRight { x: u8, y: u8 },
+    /// 8XY7 VX = VY - VX, VF is set to the borrow
+    SubRev { x: u8, y: u8 },
+    /// 8XYE VX <<= 1, VF is set to the bit shifted out
+    ShiftLeft { x: u8, y: u8 },
+    /// 9XY0 skip the next instruction if VX != VY
+    SkipNeReg { x: u8, y: u8 },
+    /// ANNN I = nnn
+    SetI { nnn: u16 },
+    /// BNNN jump to nnn + V0
+    JumpOffset { nnn: u16 },
+    /// CXNN VX = a random byte masked with nn
+    Random { x: u8, nn: u8 },
+    /// DXYN draw the n row sprite at I to (VX, VY)
+    Draw { x: u8, y: u8, n: u8 },
+    /// EX9E skip the next instruction if the key in VX is pressed
+    SkipKeyPressed { x: u8 },
+    /// EXA1 skip the next instruction if the key in VX is not pressed
+    SkipKeyNotPressed { x: u8 },
+    /// F000 NNNN (XO-CHIP) load I with the 16-bit address in the following word
+    LongLoadI,
+    /// FN01 (XO-CHIP) select the bitplanes in the n bitmask
+    SelectPlanes { n: u8 },
+    /// F002 (XO-CHIP) load the audio pattern buffer from memory at I
+    LoadAudioPattern,
+    /// FX07 VX = the delay timer
+    GetDelay { x: u8 },
+    /// FX0A wait for a key press and store it in VX
+    WaitForKey { x: u8 },
+    /// FX15 the delay timer = VX
+    SetDelay { x: u8 },
+    /// FX18 the sound timer = VX
+    SetSound { x: u8 },
+    /// FX1E I += VX
+    AddI { x: u8 },
+    /// FX29 point I at the font sprite for the digit in VX
+    FontCharacter { x: u8 },
+    /// FX30 (SUPER-CHIP) point I at the big font sprite for the digit in VX
+    BigFontCharacter { x: u8 },
+    /// FX33 store the binary coded decimal representation of VX at I
+    Bcd { x: u8 },
+    /// FX3A (XO-CHIP) set the audio pattern pitch to VX
+    SetPitch { x: u8 },
+    /// FX55 store V0 through VX to memory at I
+    StoreRegisters { x: u8 },
+    /// FX65 load V0 through VX from memory at I
+    LoadRegisters { x: u8 },
+    /// An opcode that does not decode to any instruction
+    Invalid { opcode: u16 },
+}
+
+/// Decode a big-endian CHIP-8 opcode into an instruction
+pub fn decode(opcode: u16) -> Opcode {
+    let x = ((opcode & X_MASK) >> 8) as u8;
+    let y = ((opcode & Y_MASK) >> 4) as u8;
+    let n = (opcode & N_MASK) as u8;
+    let nn = (opcode & NN_MASK) as u8;
+    let nnn = opcode & NNN_MASK;
+
+    match opcode >> 12 {
+        0x0 => match nnn {
+            0x0C0..=0x0CF => Opcode::ScrollDown { n },
+            0x0E0 => Opcode::ClearDisplay,
+            0x0EE => Opcode::Return,
+            0x0FB => Opcode::ScrollRight,
+            0x0FC => Opcode::ScrollLeft,
+            0x0FD => Opcode::Exit,
+            0x0FE => Opcode::LoRes,
+            0x0FF => Opcode::HiRes,
+            _ => Opcode::MachineCall { nnn },
+        },
+        0x1 => Opcode::Jump { nnn },
+        0x2 => Opcode::Call { nnn },
+        0x3 => Opcode::SkipEqImm { x, nn },
+        0x4 => Opcode::SkipNeImm { x, nn },
+        0x5 => match n {
+            0x0 => Opcode::SkipEqReg { x, y },
+            0x2 => Opcode::SaveRange { x, y },
+            0x3 => Opcode::LoadRange { x, y },
+            _ => Opcode::Invalid { opcode },
+        },
+        0x6 => Opcode::LoadImm { x, nn },
+        0x7 => Opcode::AddImm { x, nn },
+        0x8 => match n {
+            0x0 => Opcode::Move { x, y },
+            0x1 => Opcode::Or { x, y },
+            0x2 => Opcode::And { x, y },
+            0x3 => Opcode::Xor { x, y },
+            0x4 => Opcode::AddReg { x, y },
+            0x5 => Opcode::SubReg { x, y },
+            0x6 => Opcode::ShiftRight { x, y },
+            0x7 => Opcode::SubRev { x, y },
+            0xE => Opcode::ShiftLeft { x, y },
+            _ => Opcode::Invalid { opcode },
+        },
+        0x9 if n == 0 => Opcode::SkipNeReg { x, y },
+        0xA => Opcode::SetI { nnn },
+        0xB => Opcode::JumpOffset { nnn },
+        0xC => Opcode::Random { x, nn },
+        0xD => Opcode::Draw { x, y, n },
+        0xE => match nn {
+            0x9E => Opcode::SkipKeyPressed { x },
+            0xA1 => Opcode::SkipKeyNotPressed { x },
+            _ => Opcode::Invalid { opcode },
+        },
+        0xF => match nn {
+            0x00 if x == 0 => Opcode::LongLoadI,
+            0x01 => Opcode::SelectPlanes { n: x },
+            0x02 if x == 0 => Opcode::LoadAudioPattern,
+            0x07 => Opcode::GetDelay { x },
+            0x0A => Opcode::WaitForKey { x },
+            0x15 => Opcode::SetDelay { x },
+            0x18 => Opcode::SetSound { x },
+            0x1E => Opcode::AddI { x },
+            0x29 => Opcode::FontCharacter { x },
+            0x30 => Opcode::BigFontCharacter { x },
+            0x33 => Opcode::Bcd { x },
+            0x3A => Opcode::SetPitch { x },
+            0x55 => Opcode::StoreRegisters { x },
+            0x65 => Opcode::LoadRegisters { x },
+            _ => Opcode::Invalid { opcode },
+        },
+        _ => Opcode::Invalid { opcode },
+    }
+}
+
+impl Opcode {
+    /// Disassemble as the CPU runs the instruction under quirks. BNNN is the only instruction
+    /// whose operands depend on them, see Quirks::jump_register.
+    pub fn disassemble(self, quirks: Quirks) -> Disassembly {
+        Disassembly {
+            opcode: self,
+            quirks,
+        }
+    }
+}
+
+/// An opcode disassembled under a set of quirks, see Opcode::disassemble
+#[derive(Debug, Clone, Copy)]
+pub struct Disassembly {
+    opcode: Opcode,
+    quirks: Quirks,
+}
+
+impl fmt::Display for Disassembly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.opcode {
+            Opcode::JumpOffset { nnn } => {
+                write!(f, "JP V{:X}, 0x{:03X}", self.quirks.jump_register(nnn), nnn)
+            }
+            opcode => write!(f, "{}", opcode),
+        }
+    }
+}
+
+/// Disassemble into the conventional CHIP-8 mnemonics (as used by Cowgod's reference), with the
+/// SUPER-CHIP and XO-CHIP extensions named after their Octo equivalents. BNNN is shown as the
+/// original CHIP-8 jump from V0, use Opcode::disassemble for the register the quirks select.
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Opcode::ScrollDown { n } => write!(f, "SCD {}", n),
+            Opcode::ClearDisplay => write!(f, "CLS"),
+            Opcode::Return => write!(f, "RET"),
+            Opcode::ScrollRight => write!(f, "SCR"),
+            Opcode::ScrollLeft => write!(f, "SCL"),
+            Opcode::Exit => write!(f, "EXIT"),
+            Opcode::LoRes => write!(f, "LOW"),
+            Opcode::HiRes => write!(f, "HIGH"),
+            Opcode::MachineCall { nnn } => write!(f, "SYS 0x{:03X}", nnn),
+            Opcode::Jump { nnn } => write!(f, "JP 0x{:03X}", nnn),
+            Opcode::Call { nnn } => write!(f, "CALL 0x{:03X}", nnn),
+            Opcode::SkipEqImm { x, nn } => write!(f, "SE V{:X}, 0x{:02X}", x, nn),
+            Opcode::SkipNeImm { x, nn } => write!(f, "SNE V{:X}, 0x{:02X}", x, nn),
+            Opcode::SkipEqReg { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+            Opcode::SaveRange { x, y } => write!(f, "SAVE V{:X} - V{:X}", x, y),
+            Opcode::LoadRange { x, y } => write!(f, "LOAD V{:X} - V{:X}", x, y),
+            Opcode::LoadImm { x, nn } => write!(f, "LD V{:X}, 0x{:02X}", x, nn),
+            Opcode::AddImm { x, nn } => write!(f, "ADD V{:X}, 0x{:02X}", x, nn),
+            Opcode::Move { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+            Opcode::Or { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+            Opcode::And { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+            Opcode::Xor { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Opcode::AddReg { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Opcode::SubReg { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Opcode::ShiftRight { x, y } => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Opcode::SubRev { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Opcode::ShiftLeft { x, y } => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Opcode::SkipNeReg { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Opcode::SetI { nnn } => write!(f, "LD I, 0x{:03X}", nnn),
+            Opcode::JumpOffset { nnn } => write!(f, "JP V0, 0x{:03X}", nnn),
+            Opcode::Random { x, nn } => write!(f, "RND V{:X}, 0x{:02X}", x, nn),
+            Opcode::Draw { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Opcode::SkipKeyPressed { x } => write!(f, "SKP V{:X}", x),
+            Opcode::SkipKeyNotPressed { x } => write!(f, "SKNP V{:X}", x),
+            Opcode::LongLoadI => write!(f, "LD I, LONG"),
+            Opcode::SelectPlanes { n } => write!(f, "PLANE {}", n),
+            Opcode::LoadAudioPattern => write!(f, "AUDIO"),
+            Opcode::GetDelay { x } => write!(f, "LD V{:X}, DT", x),
+            Opcode::WaitForKey { x } => write!(f, "LD V{:X}, K", x),
+            Opcode::SetDelay { x } => write!(f, "LD DT, V{:X}", x),
+            Opcode::SetSound { x } => write!(f, "LD ST, V{:X}", x),
+            Opcode::AddI { x } => write!(f, "ADD I, V{:X}", x),
+            Opcode::FontCharacter { x } => write!(f, "LD F, V{:X}", x),
+            Opcode::BigFontCharacter { x } => write!(f, "LD HF, V{:X}", x),
+            Opcode::Bcd { x } => write!(f, "LD B, V{:X}", x),
+            Opcode::SetPitch { x } => write!(f, "PITCH V{:X}", x),
+            Opcode::StoreRegisters { x } => write!(f, "LD [I], V{:X}", x),
+            Opcode::LoadRegisters { x } => write!(f, "LD V{:X}, [I]", x),
+            Opcode::Invalid { opcode } => write!(f, "DW 0x{:04X}", opcode),
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use crate::font::Font;
+
+/// CHIP-8 interpreters disagree on the behaviour of a handful of instructions and ROMs are
+/// written against whichever interpreter the author had. Quirks selects which behaviour the CPU
+/// emulates for each of these instructions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8XY6 / 8XYE shift VY and store the result in VX rather than shifting VX in place
+    pub shift_uses_vy: bool,
+
+    /// FX55 / FX65 leave I pointing one past the last register stored or loaded
+    pub load_store_increments_i: bool,
+
+    /// BNNN jumps to NNN + VX (where X is the top nibble of NNN) rather than NNN + V0
+    pub jump_uses_vx: bool,
+
+    /// Sprites are clipped at the edge of the screen rather than wrapping around
+    pub clip_sprites: bool,
+
+    /// 8XY1 / 8XY2 / 8XY3 reset VF to 0
+    pub logic_resets_vf: bool,
+
+    /// DXY0 draws an 8x16 sprite in lo-res rather than 16x16, as SUPER-CHIP 1.1 does. Hi-res
+    /// always draws 16x16.
+    pub lores_dxy0_draws_8x16: bool,
+
+    /// The glyphs of the small hex font used by FX29
+    pub font: Font,
+}
+
+impl Quirks {
+    /// The original COSMAC VIP interpreter
+    pub const COSMAC_VIP: Self = Self {
+        shift_uses_vy: true,
+        load_store_increments_i: true,
+        jump_uses_vx: false,
+        clip_sprites: true,
+        logic_resets_vf: true,
+        lores_dxy0_draws_8x16: false,
+        font: Font::Vip,
+    };
+
+    /// CHIP-48 on the HP48
+    pub const CHIP_48: Self = Self {
+        shift_uses_vy: false,
+        load_store_increments_i: false,
+        jump_uses_vx: true,
+        clip_sprites: true,
+        logic_resets_vf: false,
+        lores_dxy0_draws_8x16: false,
+        font: Font::SuperChip,
+    };
+
+    /// SUPER-CHIP 1.1 on the HP48
+    pub const SUPER_CHIP: Self = Self {
+        shift_uses_vy: false,
+        load_store_increments_i: false,
+        jump_uses_vx: true,
+        clip_sprites: true,
+        logic_resets_vf: false,
+        lores_dxy0_draws_8x16: true,
+        font: Font::SuperChip,
+    };
+
+    /// XO-CHIP as implemented by Octo
+    pub const XO_CHIP: Self = Self {
+        shift_uses_vy: true,
+        load_store_increments_i: true,
+        jump_uses_vx: false,
+        clip_sprites: false,
+        logic_resets_vf: false,
+        lores_dxy0_draws_8x16: false,
+        font: Font::SuperChip,
+    };
+}
+
+impl Quirks {
+    /// The register BNNN adds to NNN, VX with the jump_uses_vx quirk (where X is the top nibble of
+    /// NNN) and V0 otherwise
+    pub fn jump_register(self, nnn: u16) -> usize {
+        if self.jump_uses_vx {
+            (nnn >> 8) as usize
+        } else {
+            0
+        }
+    }
+
+    /// Pack the quirks into a bit per flag, in declaration order, with the font in the two bits
+    /// above logic_resets_vf and lores_dxy0_draws_8x16, added later, in the top bit. Used by
+    /// snapshots and ROM containers.
+    pub fn to_bits(self) -> u8 {
+        (self.shift_uses_vy as u8)
+            | (self.load_store_increments_i as u8) << 1
+            | (self.jump_uses_vx as u8) << 2
+            | (self.clip_sprites as u8) << 3
+            | (self.logic_resets_vf as u8) << 4
+            | self.font.to_u8() << 5
+            | (self.lores_dxy0_draws_8x16 as u8) << 7
+    }
+
+    pub fn from_bits(bits: u8) -> Self {
+        Self {
+            shift_uses_vy: bits & 1 != 0,
+            load_store_increments_i: bits & (1 << 1) != 0,
+            jump_uses_vx: bits & (1 << 2) != 0,
+            clip_sprites: bits & (1 << 3) != 0,
+            logic_resets_vf: bits & (1 << 4) != 0,
+            lores_dxy0_draws_8x16: bits & (1 << 7) != 0,
+            font: Font::from_u8((bits >> 5) & 0b11).unwrap(),
+        }
+    }
+}
@@ -0,0 +1,41 @@
+/// The seed used when none is given, so that a machine boots the same way every time
+pub const DEFAULT_SEED: u32 = 13923919;
+
+#[derive(Debug)]
+pub struct Rand {
+    state: u32,
+}
+
+impl Rand {
+    pub fn new() -> Self {
+        Self::from_seed(DEFAULT_SEED)
+    }
+    /// Start a generator from any seed. xorshift never leaves a zero state, so a zero seed uses
+    /// DEFAULT_SEED instead.
+    pub fn from_seed(seed: u32) -> Self {
+        Self {
+            state: if seed == 0 { DEFAULT_SEED } else { seed },
+        }
+    }
+    /// Resume a generator from a state previously returned by state
+    pub fn from_state(state: u32) -> Self {
+        Self { state }
+    }
+    pub fn state(&self) -> u32 {
+        self.state
+    }
+    pub fn next_u32(&mut self) -> u32 {
+        let c = self.state;
+        let c = c ^ (c << 13);
+        let c = c ^ (c >> 17);
+        let c = c ^ (c << 5);
+        self.state = c;
+        c
+    }
+}
+
+impl Default for Rand {
+    fn default() -> Self {
+        Self::new()
+    }
+}
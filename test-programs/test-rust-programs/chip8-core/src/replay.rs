@@ -0,0 +1,213 @@
+//! Recording and replaying keypad input. A machine is deterministic given its random seed and the
+//! keys held at the start of each frame, so a session can be reproduced from the seed and the
+//! frames where a key changed. Frame n is the nth call to Machine::run_frame since the machine was
+//! created, and the keys are those held when it starts.
+//!
+//! A recording is a little-endian binary image laid out as:
+//!
+//! | Field    | Size | Notes                                                        |
+//! |----------|------|--------------------------------------------------------------|
+//! | magic    | 4    | `C8IN`                                                       |
+//! | version  | 1    | RECORDING_VERSION                                            |
+//! | reserved | 3    | zero                                                         |
+//! | seed     | 4    | passed to Machine::seed before the first frame               |
+//! | events   | *    | EVENT_SIZE bytes each, in the order they were recorded       |
+//!
+//! Each event is the frame as a u32, the key and then 1 if it was pressed or 0 if released.
+use crate::cpu::NUM_KEYS;
+use crate::machine::Machine;
+
+pub const RECORDING_MAGIC: [u8; 4] = *b"C8IN";
+pub const RECORDING_VERSION: u8 = 1;
+pub const HEADER_SIZE: usize = 12;
+pub const EVENT_SIZE: usize = 6;
+
+/// The reason a recording could not be parsed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordingError {
+    /// The image does not start with RECORDING_MAGIC
+    BadMagic,
+    /// The image was written by a different recording format version
+    UnsupportedVersion(u8),
+    /// The image ends part way through the header or an event
+    Truncated,
+    /// An event is for a key that does not exist or is out of frame order
+    Invalid,
+}
+
+/// A key pressed or released at the start of a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputEvent {
+    pub frame: u32,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+impl InputEvent {
+    pub fn to_bytes(self) -> [u8; EVENT_SIZE] {
+        let frame = self.frame.to_le_bytes();
+        [
+            frame[0],
+            frame[1],
+            frame[2],
+            frame[3],
+            self.key,
+            self.pressed as u8,
+        ]
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            frame: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            key: bytes[4],
+            pressed: bytes[5] != 0,
+        }
+    }
+}
+
+/// Encode the header of a recording for a machine started from seed. The events follow it.
+pub fn header(seed: u32) -> [u8; HEADER_SIZE] {
+    let mut header = [0; HEADER_SIZE];
+    header[..4].copy_from_slice(&RECORDING_MAGIC);
+    header[4] = RECORDING_VERSION;
+    header[8..12].copy_from_slice(&seed.to_le_bytes());
+    header
+}
+
+/// Logs an event for every key that changes between frames
+pub struct Recorder {
+    /// The frame about to run
+    frame: u32,
+    /// The keys held at the start of the last frame
+    keys: u16,
+}
+
+impl Recorder {
+    /// Record a machine that has not run a frame yet. No keys are held on a new machine.
+    pub fn new() -> Self {
+        Self { frame: 0, keys: 0 }
+    }
+
+    /// Called before every Machine::run_frame with the machine about to run, passing an event to
+    /// log for each key that has changed since the last frame
+    pub fn record(&mut self, machine: &Machine, mut log: impl FnMut(InputEvent)) {
+        let keys = machine.keys();
+        let changed = keys ^ self.keys;
+        for key in 0..NUM_KEYS as u8 {
+            if changed & (1 << key) != 0 {
+                log(InputEvent {
+                    frame: self.frame,
+                    key,
+                    pressed: keys & (1 << key) != 0,
+                });
+            }
+        }
+
+        self.keys = keys;
+        self.frame = self.frame.wrapping_add(1);
+    }
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A parsed recording
+#[derive(Debug, Clone, Copy)]
+pub struct Recording<'a> {
+    pub seed: u32,
+    events: &'a [u8],
+}
+
+impl<'a> Recording<'a> {
+    /// Parse a recording image, checking that every event is for a real key and in frame order
+    pub fn parse(image: &'a [u8]) -> Result<Self, RecordingError> {
+        if image.len() < HEADER_SIZE {
+            return Err(if image.starts_with(&RECORDING_MAGIC) {
+                RecordingError::Truncated
+            } else {
+                RecordingError::BadMagic
+            });
+        }
+
+        if image[..4] != RECORDING_MAGIC {
+            return Err(RecordingError::BadMagic);
+        }
+
+        if image[4] != RECORDING_VERSION {
+            return Err(RecordingError::UnsupportedVersion(image[4]));
+        }
+
+        let events = &image[HEADER_SIZE..];
+        if !events.len().is_multiple_of(EVENT_SIZE) {
+            return Err(RecordingError::Truncated);
+        }
+
+        let recording = Self {
+            seed: u32::from_le_bytes([image[8], image[9], image[10], image[11]]),
+            events,
+        };
+
+        let mut frame = 0;
+        for event in recording.events() {
+            if event.key as usize >= NUM_KEYS || event.frame < frame {
+                return Err(RecordingError::Invalid);
+            }
+            frame = event.frame;
+        }
+
+        Ok(recording)
+    }
+
+    /// The events in the order they were recorded
+    pub fn events(&self) -> impl Iterator<Item = InputEvent> + 'a {
+        self.events
+            .chunks_exact(EVENT_SIZE)
+            .map(InputEvent::from_bytes)
+    }
+}
+
+/// Feeds the events of a recording back into a machine. The recording is passed to every call
+/// rather than held, so that the storage it was parsed from can be reused once the replay is over.
+/// Every call must be passed the same recording.
+pub struct Replay {
+    /// The frame about to run
+    frame: u32,
+    /// The index of the next event to apply
+    next: usize,
+}
+
+impl Replay {
+    /// Replay onto a machine that has been seeded with the recording's seed and has not run a frame
+    /// yet
+    pub fn new() -> Self {
+        Self { frame: 0, next: 0 }
+    }
+
+    /// Called before every Machine::run_frame in place of reading the keypad, pressing and
+    /// releasing keys through Machine::set_key as they were when the frame was recorded
+    pub fn apply(&mut self, recording: &Recording, machine: &mut Machine) {
+        for event in recording.events().skip(self.next) {
+            if event.frame != self.frame {
+                break;
+            }
+            machine.set_key(event.key, event.pressed);
+            self.next += 1;
+        }
+
+        self.frame = self.frame.wrapping_add(1);
+    }
+
+    /// True once every event of recording has been applied
+    pub fn finished(&self, recording: &Recording) -> bool {
+        self.next * EVENT_SIZE == recording.events.len()
+    }
+}
+
+impl Default for Replay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,511 @@
+//! Step-back debugging. Rewind records the machine every few frames into a fixed-size ring
+//! buffer, dropping the oldest records once the buffer is full, so a developer can step a faulted
+//! machine back to look at the seconds leading up to the fault.
+//!
+//! Rewind keeps a shadow copy of memory and the framebuffer as they were at the newest record.
+//! Each record holds the registers at the time it was taken, and undo data for the period since
+//! the record before it: the shadow contents of every memory page written in that period and the
+//! framebuffer bytes that changed, XORed against the shadow. Stepping back applies the newest
+//! record's undo data and loads the registers from the record before it.
+//!
+//! The storage passed to Rewind::new is the whole memory budget. The shadow takes the size of
+//! memory plus SCREEN_SIZE and the ring buffer gets the rest.
+//!
+//! Rewind counts the frames passed to it, and each record keeps the frame it was taken on, so a
+//! debugger can step back a number of frames rather than a number of records.
+use crate::cpu::{AUDIO_PATTERN_SIZE, Registers};
+use crate::machine::Machine;
+use crate::memory::{DIRTY_PAGE_WORDS, PAGE_SIZE, SCREEN_SIZE};
+
+/// The maximum number of records kept, regardless of the space left in the ring buffer
+pub const MAX_RECORDS: usize = 128;
+
+/// The size of the fixed part of a register record, the stack in use follows it
+const REGISTERS_SIZE: usize = 16 + 2 + 2 + 1 + 1 + 1 + 1 + 1 + 1 + AUDIO_PATTERN_SIZE + 4 + 4 + 2;
+
+const FLAG_HALTED: u8 = 1 << 0;
+const FLAG_WAITING_FOR_KEY: u8 = 1 << 1;
+const FLAG_HIRES: u8 = 1 << 2;
+
+/// The reason Rewind could not be created
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RewindError {
+    /// The storage cannot hold the shadow memory and framebuffer and leave room for records
+    BudgetTooSmall,
+}
+
+pub struct Rewind {
+    storage: &'static mut [u8],
+    memory_size: usize,
+
+    /// Records are taken every interval frames
+    interval: u32,
+    frames_since_record: u32,
+
+    /// The number of frames since the last reset, as far as the machine has got
+    frame: u32,
+
+    /// The ring of records, as offsets and lengths into the ring buffer area of storage. Records
+    /// never wrap around the end of the ring buffer.
+    starts: [u16; MAX_RECORDS],
+    lens: [u16; MAX_RECORDS],
+    /// The frame each record was taken on
+    frames: [u32; MAX_RECORDS],
+    oldest: usize,
+    count: usize,
+}
+
+/// Writes a record into the ring buffer
+struct Cursor<'a> {
+    buffer: &'a mut [u8],
+    offset: usize,
+}
+
+impl Cursor<'_> {
+    fn byte(&mut self, byte: u8) {
+        self.buffer[self.offset] = byte;
+        self.offset += 1;
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.buffer[self.offset..self.offset + bytes.len()].copy_from_slice(bytes);
+        self.offset += bytes.len();
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+}
+
+/// Reads a record back out of the ring buffer
+struct Reader<'a> {
+    buffer: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> &'a [u8] {
+        let bytes = &self.buffer[self.offset..self.offset + len];
+        self.offset += len;
+        bytes
+    }
+
+    fn byte(&mut self) -> u8 {
+        self.bytes(1)[0]
+    }
+
+    fn u16(&mut self) -> u16 {
+        let bytes = self.bytes(2);
+        u16::from_le_bytes([bytes[0], bytes[1]])
+    }
+
+    fn u32(&mut self) -> u32 {
+        let bytes = self.bytes(4);
+        u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+}
+
+/// Call f with the index of every page set in a dirty page bitmap
+fn for_each_page(dirty: &[u32; DIRTY_PAGE_WORDS], mut f: impl FnMut(usize)) {
+    for (word, bits) in dirty.iter().enumerate() {
+        for bit in 0..32 {
+            if bits & (1 << bit) != 0 {
+                f(word * 32 + bit);
+            }
+        }
+    }
+}
+
+/// Call f with the offset and length of every run of framebuffer bytes that differ from shadow
+fn for_each_run(frame: &[u8; SCREEN_SIZE], shadow: &[u8], mut f: impl FnMut(usize, usize)) {
+    let mut offset = 0;
+    while offset < SCREEN_SIZE {
+        if frame[offset] == shadow[offset] {
+            offset += 1;
+            continue;
+        }
+
+        let start = offset;
+        while offset < SCREEN_SIZE
+            && offset - start < u8::MAX as usize
+            && frame[offset] != shadow[offset]
+        {
+            offset += 1;
+        }
+        f(start, offset - start);
+    }
+}
+
+impl Rewind {
+    /// Create a rewind buffer that records machine every interval frames. storage is the memory
+    /// budget for the shadow copy and the records.
+    pub fn new(
+        storage: &'static mut [u8],
+        machine: &mut Machine,
+        interval: u32,
+    ) -> Result<Self, RewindError> {
+        let memory_size = machine.memory.size();
+        if storage.len() <= memory_size + SCREEN_SIZE + REGISTERS_SIZE {
+            return Err(RewindError::BudgetTooSmall);
+        }
+
+        let mut rewind = Self {
+            storage,
+            memory_size,
+            interval: interval.max(1),
+            frames_since_record: 0,
+            frame: 0,
+            starts: [0; MAX_RECORDS],
+            lens: [0; MAX_RECORDS],
+            frames: [0; MAX_RECORDS],
+            oldest: 0,
+            count: 0,
+        };
+
+        rewind.reset(machine);
+        Ok(rewind)
+    }
+
+    /// Drop every record and take a fresh first record of machine
+    pub fn reset(&mut self, machine: &mut Machine) {
+        for address in 0..self.memory_size {
+            self.storage[address] = machine.memory.get(address);
+        }
+        unsafe {
+            self.storage[self.memory_size..self.memory_size + SCREEN_SIZE]
+                .copy_from_slice(&*machine.memory.frame_buffer);
+        }
+        machine.memory.take_dirty_pages();
+
+        self.count = 0;
+        self.oldest = 0;
+        self.frames_since_record = 0;
+        self.frame = 0;
+
+        let len = REGISTERS_SIZE + machine.cpu.registers.stack_idx + 4;
+        if let Some(start) = self.allocate(len) {
+            let ring = self.ring_start();
+            let mut cursor = Cursor {
+                buffer: &mut self.storage[ring + start..],
+                offset: 0,
+            };
+            Self::write_registers(&mut cursor, machine);
+            cursor.u16(0);
+            cursor.u16(0);
+        }
+    }
+
+    /// Called at the end of every frame, taking a record every interval frames
+    pub fn frame(&mut self, machine: &mut Machine) {
+        self.frame += 1;
+        self.frames_since_record += 1;
+        if self.frames_since_record >= self.interval {
+            self.record(machine);
+        }
+    }
+
+    /// The offset of the ring buffer in storage
+    fn ring_start(&self) -> usize {
+        self.memory_size + SCREEN_SIZE
+    }
+
+    fn ring_len(&self) -> usize {
+        self.storage.len() - self.ring_start()
+    }
+
+    /// The index of the newest record
+    fn newest(&self) -> usize {
+        (self.oldest + self.count - 1) % MAX_RECORDS
+    }
+
+    /// Find space for a record of len bytes after the newest record, dropping the oldest records
+    /// until it fits. Returns the offset of the record in the ring buffer.
+    fn allocate(&mut self, len: usize) -> Option<usize> {
+        if len > self.ring_len() {
+            return None;
+        }
+
+        let mut start = if self.count == 0 {
+            0
+        } else {
+            let newest = self.newest();
+            self.starts[newest] as usize + self.lens[newest] as usize
+        };
+        if start + len > self.ring_len() {
+            start = 0;
+        }
+
+        if self.count == MAX_RECORDS {
+            self.drop_oldest();
+        }
+
+        while self.count > 0 {
+            let oldest_start = self.starts[self.oldest] as usize;
+            let oldest_end = oldest_start + self.lens[self.oldest] as usize;
+            if oldest_start < start + len && oldest_end > start {
+                self.drop_oldest();
+            } else {
+                break;
+            }
+        }
+
+        let index = (self.oldest + self.count) % MAX_RECORDS;
+        self.starts[index] = start as u16;
+        self.lens[index] = len as u16;
+        self.frames[index] = self.frame;
+        self.count += 1;
+        Some(start)
+    }
+
+    fn drop_oldest(&mut self) {
+        self.oldest = (self.oldest + 1) % MAX_RECORDS;
+        self.count -= 1;
+    }
+
+    fn write_registers(cursor: &mut Cursor, machine: &Machine) {
+        let registers = &machine.cpu.registers;
+
+        let mut flags = 0;
+        if registers.halted {
+            flags |= FLAG_HALTED;
+        }
+        if registers.wait_for_key.is_some() {
+            flags |= FLAG_WAITING_FOR_KEY;
+        }
+        if machine.memory.hires() {
+            flags |= FLAG_HIRES;
+        }
+
+        cursor.bytes(&registers.v);
+        cursor.u16(registers.pc);
+        cursor.u16(registers.i);
+        cursor.byte(registers.delay);
+        cursor.byte(registers.sound);
+        cursor.byte(flags);
+        cursor.byte(registers.wait_for_key.unwrap_or(0) as u8);
+        cursor.byte(machine.memory.planes());
+        cursor.byte(registers.pitch);
+        cursor.bytes(&registers.audio_pattern);
+        cursor.u32(registers.rng_state());
+        cursor.u32(machine.cpu.cycles);
+        cursor.u16(registers.stack_idx as u16);
+        cursor.bytes(&registers.stack[..registers.stack_idx]);
+    }
+
+    fn read_registers(reader: &mut Reader, machine: &mut Machine) {
+        let registers: &mut Registers = &mut machine.cpu.registers;
+        registers.v.copy_from_slice(reader.bytes(16));
+        registers.pc = reader.u16();
+        registers.i = reader.u16();
+        registers.delay = reader.byte();
+        registers.sound = reader.byte();
+        let flags = reader.byte();
+        let wait_for_key = reader.byte() as usize;
+        let planes = reader.byte();
+        registers.pitch = reader.byte();
+        registers
+            .audio_pattern
+            .copy_from_slice(reader.bytes(AUDIO_PATTERN_SIZE));
+        registers.set_rng_state(reader.u32());
+        let cycles = reader.u32();
+        registers.stack_idx = reader.u16() as usize;
+        registers.stack[..registers.stack_idx].copy_from_slice(reader.bytes(registers.stack_idx));
+
+        registers.halted = flags & FLAG_HALTED != 0;
+        registers.wait_for_key = if flags & FLAG_WAITING_FOR_KEY != 0 {
+            Some(wait_for_key)
+        } else {
+            None
+        };
+        machine.cpu.cycles = cycles;
+        machine
+            .memory
+            .set_display_mode(flags & FLAG_HIRES != 0, planes);
+    }
+
+    /// True if the machine registers are exactly those in the newest record
+    fn at_newest(&self, machine: &Machine) -> bool {
+        let mut buffer = [0; REGISTERS_SIZE + 256];
+        let mut cursor = Cursor {
+            buffer: &mut buffer,
+            offset: 0,
+        };
+        Self::write_registers(&mut cursor, machine);
+        let len = cursor.offset;
+
+        let start = self.ring_start() + self.starts[self.newest()] as usize;
+        self.storage[start..start + len] == buffer[..len]
+    }
+
+    /// Record the machine, saving undo data for everything that changed since the newest record
+    pub fn record(&mut self, machine: &mut Machine) {
+        self.frames_since_record = 0;
+
+        let dirty = machine.memory.take_dirty_pages();
+        let shadow_frame = self.memory_size;
+        let frame = unsafe { &*machine.memory.frame_buffer };
+
+        let mut pages = 0;
+        for_each_page(&dirty, |_| pages += 1);
+        let mut runs = 0;
+        let mut run_bytes = 0;
+        for_each_run(frame, &self.storage[shadow_frame..], |_, len| {
+            runs += 1;
+            run_bytes += len;
+        });
+
+        let len = REGISTERS_SIZE
+            + machine.cpu.registers.stack_idx
+            + 2
+            + pages * (2 + PAGE_SIZE)
+            + 2
+            + runs * 3
+            + run_bytes;
+
+        let Some(start) = self.allocate(len) else {
+            // A record larger than the whole ring buffer cannot be kept, so history restarts here
+            self.reset(machine);
+            return;
+        };
+
+        // The ring buffer follows the shadow in storage, so split it off to write the record
+        // while reading the shadow
+        let ring = self.ring_start();
+        let (shadow, records) = self.storage.split_at_mut(ring);
+        let mut cursor = Cursor {
+            buffer: &mut records[start..],
+            offset: 0,
+        };
+
+        Self::write_registers(&mut cursor, machine);
+
+        cursor.u16(pages as u16);
+        for_each_page(&dirty, |page| {
+            let address = page * PAGE_SIZE;
+            cursor.u16(page as u16);
+            cursor.bytes(&shadow[address..address + PAGE_SIZE]);
+            for (offset, byte) in shadow[address..address + PAGE_SIZE].iter_mut().enumerate() {
+                *byte = machine.memory.get(address + offset);
+            }
+        });
+
+        cursor.u16(runs as u16);
+        let (_, shadow_frame) = shadow.split_at_mut(self.memory_size);
+        for_each_run(frame, shadow_frame, |offset, len| {
+            cursor.u16(offset as u16);
+            cursor.byte(len as u8);
+            for byte in offset..offset + len {
+                cursor.byte(frame[byte] ^ shadow_frame[byte]);
+            }
+        });
+        shadow_frame.copy_from_slice(frame);
+    }
+
+    /// Step the machine back to the previous record. If the machine has run since the newest
+    /// record it is returned to that record first. Returns false if there is no earlier record to
+    /// step back to. Any fault is cleared so the machine can be stepped forward again.
+    pub fn step_back(&mut self, machine: &mut Machine) -> bool {
+        if self.count == 0 {
+            return false;
+        }
+
+        let ring = self.ring_start();
+        let dirty = machine.memory.take_dirty_pages();
+        let mut moved = false;
+
+        // Return to the newest record by copying back the shadow
+        let mut changed = false;
+        for_each_page(&dirty, |_| changed = true);
+        if changed || !self.at_newest(machine) {
+            for_each_page(&dirty, |page| {
+                let address = page * PAGE_SIZE;
+                for (offset, byte) in self.storage[address..address + PAGE_SIZE]
+                    .iter()
+                    .enumerate()
+                {
+                    machine.memory.set(address + offset, *byte);
+                }
+            });
+            moved = true;
+        } else if self.count >= 2 {
+            // Undo the newest record, leaving the shadow at the record before it
+            let newest = self.newest();
+            let start = ring + self.starts[newest] as usize;
+            let (shadow, records) = self.storage.split_at_mut(ring);
+            let mut reader = Reader {
+                buffer: &records[start - ring..],
+                offset: 0,
+            };
+
+            reader.offset += REGISTERS_SIZE - 2;
+            let stack_idx = reader.u16() as usize;
+            reader.offset += stack_idx;
+
+            for _ in 0..reader.u16() {
+                let address = reader.u16() as usize * PAGE_SIZE;
+                let old = reader.bytes(PAGE_SIZE);
+                shadow[address..address + PAGE_SIZE].copy_from_slice(old);
+                for (offset, byte) in old.iter().enumerate() {
+                    machine.memory.set(address + offset, *byte);
+                }
+            }
+
+            let shadow_frame = &mut shadow[self.memory_size..];
+            for _ in 0..reader.u16() {
+                let offset = reader.u16() as usize;
+                let len = reader.byte() as usize;
+                for (byte, delta) in reader.bytes(len).iter().enumerate() {
+                    shadow_frame[offset + byte] ^= delta;
+                }
+            }
+
+            self.count -= 1;
+            moved = true;
+        }
+
+        if !moved {
+            return false;
+        }
+
+        // The framebuffer always matches the shadow once the machine is back at a record
+        unsafe {
+            (*machine.memory.frame_buffer)
+                .copy_from_slice(&self.storage[self.memory_size..self.memory_size + SCREEN_SIZE]);
+        }
+
+        let newest = self.newest();
+        let start = ring + self.starts[newest] as usize;
+        let mut reader = Reader {
+            buffer: &self.storage[start..],
+            offset: 0,
+        };
+        Self::read_registers(&mut reader, machine);
+
+        machine.memory.take_dirty_pages();
+        machine.fault = None;
+        self.frames_since_record = 0;
+        self.frame = self.frames[newest];
+        true
+    }
+
+    /// Step the machine back at least frames frames, to the newest record taken that far back or
+    /// to the oldest record if the history does not go back that far. Records are taken every
+    /// interval frames, so the machine can land up to interval - 1 frames further back than
+    /// asked. Returns the number of frames stepped back.
+    pub fn step_back_frames(&mut self, machine: &mut Machine, frames: u32) -> u32 {
+        let start = self.frame;
+        let target = start.saturating_sub(frames);
+        while self.frame > target && self.step_back(machine) {}
+        start - self.frame
+    }
+
+    /// The number of frames since the last reset, as far as the machine has got. Stepping back
+    /// winds this back to the frame of the record stepped back to.
+    pub fn current_frame(&self) -> u32 {
+        self.frame
+    }
+}
@@ -0,0 +1,251 @@
+//! Save states for the whole machine, used to reproduce bug reports from the FPGA. chip-8-rust
+//! streams snapshots to the host over DMA and restores one written into its RAM before reset.
+//!
+//! A snapshot is a little-endian binary image laid out as:
+//!
+//! | Field            | Size           | Notes                                                     |
+//! |------------------|----------------|-----------------------------------------------------------|
+//! | magic            | 4              | `C8SS`                                                    |
+//! | version          | 1              | SNAPSHOT_VERSION                                          |
+//! | platform         | 1              | 0 CHIP-8, 1 SUPER-CHIP, 2 XO-CHIP                         |
+//! | quirks           | 1              | bit per Quirks field, in declaration order                |
+//! | flags            | 1              | halted, hires, vip_timing, waiting for key                |
+//! | wait_for_key     | 1              | the register a key press is written to                   |
+//! | planes           | 1              |                                                           |
+//! | v                | 16             |                                                           |
+//! | pc, i            | 2 + 2          |                                                           |
+//! | delay, sound     | 1 + 1          |                                                           |
+//! | keys             | 2              | bit per key                                               |
+//! | pitch            | 1              |                                                           |
+//! | audio_pattern    | 16             |                                                           |
+//! | rng              | 4              |                                                           |
+//! | cycles           | 4              |                                                           |
+//! | stack_idx        | 2              | followed by the stack_idx bytes in use                    |
+//! | memory size      | 4              | followed by the contents of memory                        |
+//! | framebuffer      | SCREEN_SIZE    |                                                           |
+//!
+//! A faulted machine is saved as it was before the faulting instruction, so restoring it and
+//! stepping reproduces the fault.
+use crate::cpu::{AUDIO_PATTERN_SIZE, Platform};
+use crate::machine::Machine;
+use crate::memory::{Framebuffer, SCREEN_SIZE};
+use crate::quirks::Quirks;
+
+pub const SNAPSHOT_MAGIC: [u8; 4] = *b"C8SS";
+/// Version 2 moved the font to 0x050, version 1 images have it at 0x000
+pub const SNAPSHOT_VERSION: u8 = 2;
+
+const FLAG_HALTED: u8 = 1 << 0;
+const FLAG_HIRES: u8 = 1 << 1;
+const FLAG_VIP_TIMING: u8 = 1 << 2;
+const FLAG_WAITING_FOR_KEY: u8 = 1 << 3;
+
+/// The reason a snapshot image could not be restored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotError {
+    /// The image does not start with SNAPSHOT_MAGIC
+    BadMagic,
+    /// The image was written by a different snapshot format version
+    UnsupportedVersion(u8),
+    /// The image ends before the snapshot does
+    Truncated,
+    /// The image holds more memory than the machine being restored has
+    MemoryTooLarge,
+    /// A field holds a value that no machine state can have
+    Invalid,
+}
+
+/// Passes snapshot fields to the function writing out the image
+struct Writer<F: FnMut(&[u8])> {
+    write: F,
+}
+
+impl<F: FnMut(&[u8])> Writer<F> {
+    fn byte(&mut self, byte: u8) {
+        (self.write)(&[byte]);
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        (self.write)(bytes);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+}
+
+/// Reads snapshot fields from an image, failing if the image is too short
+struct Reader<'a> {
+    image: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], SnapshotError> {
+        let bytes = self
+            .image
+            .get(self.offset..self.offset + len)
+            .ok_or(SnapshotError::Truncated)?;
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, SnapshotError> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, SnapshotError> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+/// Write a snapshot of machine, passing the image to write a piece at a time in order
+pub fn save(machine: &Machine, write: impl FnMut(&[u8])) {
+    let registers = &machine.cpu.registers;
+    let memory = &machine.memory;
+    let mut writer = Writer { write };
+
+    let mut flags = 0;
+    if registers.halted {
+        flags |= FLAG_HALTED;
+    }
+    if memory.hires() {
+        flags |= FLAG_HIRES;
+    }
+    if machine.cpu.vip_timing {
+        flags |= FLAG_VIP_TIMING;
+    }
+    if registers.wait_for_key.is_some() {
+        flags |= FLAG_WAITING_FOR_KEY;
+    }
+
+    let mut keys: u16 = 0;
+    for (key, pressed) in registers.keys.iter().enumerate() {
+        keys |= (*pressed as u16) << key;
+    }
+
+    writer.bytes(&SNAPSHOT_MAGIC);
+    writer.byte(SNAPSHOT_VERSION);
+    writer.byte(registers.platform.to_u8());
+    writer.byte(registers.quirks.to_bits());
+    writer.byte(flags);
+    writer.byte(registers.wait_for_key.unwrap_or(0) as u8);
+    writer.byte(memory.planes());
+    writer.bytes(&registers.v);
+    writer.u16(registers.pc);
+    writer.u16(registers.i);
+    writer.byte(registers.delay);
+    writer.byte(registers.sound);
+    writer.u16(keys);
+    writer.byte(registers.pitch);
+    writer.bytes(&registers.audio_pattern);
+    writer.u32(registers.rng_state());
+    writer.u32(machine.cpu.cycles);
+    writer.u16(registers.stack_idx as u16);
+    writer.bytes(&registers.stack[..registers.stack_idx]);
+
+    writer.u32(memory.size() as u32);
+    for address in 0..memory.size() {
+        writer.byte(memory.get(address));
+    }
+
+    unsafe {
+        writer.bytes(&*memory.frame_buffer);
+    }
+}
+
+/// Rebuild a machine from a snapshot image. The machine uses memory and framebuffer as its
+/// backing storage, the snapshot memory is copied into them.
+///
+/// # Safety
+///
+/// framebuffer must be valid for reads and writes for as long as the machine is used, see
+/// Machine::new.
+pub unsafe fn restore(
+    image: &[u8],
+    memory: &'static mut [u8],
+    framebuffer: Framebuffer,
+) -> Result<Machine, SnapshotError> {
+    let mut reader = Reader { image, offset: 0 };
+
+    if reader.bytes(SNAPSHOT_MAGIC.len())? != SNAPSHOT_MAGIC {
+        return Err(SnapshotError::BadMagic);
+    }
+
+    let version = reader.byte()?;
+    if version != SNAPSHOT_VERSION {
+        return Err(SnapshotError::UnsupportedVersion(version));
+    }
+
+    let platform = Platform::from_u8(reader.byte()?).ok_or(SnapshotError::Invalid)?;
+    let quirks = Quirks::from_bits(reader.byte()?);
+    let flags = reader.byte()?;
+    let wait_for_key = reader.byte()? as usize;
+    let planes = reader.byte()?;
+
+    if wait_for_key >= 16 {
+        return Err(SnapshotError::Invalid);
+    }
+
+    let memory_size = memory.len();
+    let mut machine = unsafe { Machine::new(memory, framebuffer, platform, quirks) };
+    machine.cpu.vip_timing = flags & FLAG_VIP_TIMING != 0;
+    machine.memory.set_hires(flags & FLAG_HIRES != 0);
+    machine.memory.select_planes(planes);
+
+    let registers = &mut machine.cpu.registers;
+    registers.halted = flags & FLAG_HALTED != 0;
+    if flags & FLAG_WAITING_FOR_KEY != 0 {
+        registers.wait_for_key = Some(wait_for_key);
+    }
+    registers.v.copy_from_slice(reader.bytes(16)?);
+    registers.pc = reader.u16()?;
+    registers.i = reader.u16()?;
+    registers.delay = reader.byte()?;
+    registers.sound = reader.byte()?;
+
+    let keys = reader.u16()?;
+    for (key, pressed) in registers.keys.iter_mut().enumerate() {
+        *pressed = keys & (1 << key) != 0;
+    }
+
+    registers.pitch = reader.byte()?;
+    registers
+        .audio_pattern
+        .copy_from_slice(reader.bytes(AUDIO_PATTERN_SIZE)?);
+    registers.set_rng_state(reader.u32()?);
+    machine.cpu.cycles = reader.u32()?;
+
+    let registers = &mut machine.cpu.registers;
+    let stack_idx = reader.u16()? as usize;
+    if stack_idx > registers.stack.len() {
+        return Err(SnapshotError::Invalid);
+    }
+    registers.stack[..stack_idx].copy_from_slice(reader.bytes(stack_idx)?);
+    registers.stack_idx = stack_idx;
+
+    let size = reader.u32()? as usize;
+    if size > memory_size {
+        return Err(SnapshotError::MemoryTooLarge);
+    }
+    for (address, byte) in reader.bytes(size)?.iter().enumerate() {
+        machine.memory.set(address, *byte);
+    }
+
+    let frame = reader.bytes(SCREEN_SIZE)?;
+    unsafe {
+        (*machine.memory.frame_buffer).copy_from_slice(frame);
+    }
+
+    Ok(machine)
+}
@@ -0,0 +1,229 @@
+//! Run the community CHIP-8 test ROMs (corax+, flags, quirks and keypad) from tests/roms and check
+//! the pass and fail glyphs each ROM draws for its own checks. The glyphs are read from a
+//! <rom>.glyphs file vendored next to each ROM, see tests/roms/README.md, and are searched for
+//! anywhere on the lo-res screen rather than at fixed positions.
+//!
+//! A ROM that has not been vendored is reported and skipped, the assembled suites in
+//! conformance.rs cover the same behaviour without them.
+use chip8_core::cpu::Platform;
+use chip8_core::machine::Machine;
+use chip8_core::memory::{PROGRAM_ADDRESS, SCREEN_SIZE, SCREEN_WIDTH};
+use chip8_core::quirks::Quirks;
+use std::path::PathBuf;
+
+/// The bytes of machine memory chip-8-rust runs ROMs with
+const MACHINE_MEMORY_SIZE: usize = 0x1000;
+
+/// The default CPU speed of 600 instructions per second
+const INSTRUCTIONS_PER_FRAME: usize = 10;
+
+/// The suites never exit, they are run this long and then their screen is read
+const FRAMES: usize = 600;
+
+/// The suites show a menu unless this byte selects a test or platform before they start
+const SELECTION_ADDRESS: usize = 0x1FF;
+
+/// The lo-res screen the suites draw on
+const LORES_WIDTH: usize = 64;
+const LORES_HEIGHT: usize = 32;
+
+/// A community ROM and how to run it
+struct CommunityRom {
+    file: &'static str,
+    platform: Platform,
+    quirks: Quirks,
+    /// Written to SELECTION_ADDRESS before the ROM starts
+    selection: Option<u8>,
+    /// Keys to hold from a frame on
+    input: &'static [(usize, u16)],
+}
+
+/// A glyph as rows of pixels, the leftmost pixel in the high bit
+type Glyph = Vec<u8>;
+
+fn roms_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/roms")
+}
+
+/// Parse a glyphs file, a pass line and a fail line each listing the glyph's rows as hex bytes
+fn parse_glyphs(text: &str) -> (Glyph, Glyph) {
+    let mut pass = None;
+    let mut fail = None;
+    for line in text.lines().filter(|line| !line.trim().is_empty()) {
+        let mut words = line.split_whitespace();
+        let name = words.next().unwrap();
+        let rows = words
+            .map(|word| u8::from_str_radix(word, 16).unwrap())
+            .collect();
+        match name {
+            "pass" => pass = Some(rows),
+            "fail" => fail = Some(rows),
+            _ => panic!("unknown glyph {name}"),
+        }
+    }
+    (pass.expect("no pass glyph"), fail.expect("no fail glyph"))
+}
+
+/// Whether the lo-res pixel at x, y is lit. Lo-res pixels are 2x2 blocks in the framebuffer.
+fn lit(frame_buffer: &[u8; SCREEN_SIZE], x: usize, y: usize) -> bool {
+    let index = (y * 2) * SCREEN_WIDTH + x * 2;
+    frame_buffer[index >> 3] & (1 << (index & 0b111)) != 0
+}
+
+/// The width of glyph in pixels, up to its rightmost lit column
+fn width(glyph: &[u8]) -> usize {
+    let bits = glyph.iter().fold(0, |bits, row| bits | row);
+    8 - bits.trailing_zeros().min(8) as usize
+}
+
+/// Count the places glyph is drawn on the lo-res screen. Every pixel in the glyph's box has to
+/// match, so a glyph is not found inside a larger shape that covers it.
+fn count(frame_buffer: &[u8; SCREEN_SIZE], glyph: &[u8]) -> usize {
+    let width = width(glyph);
+    let mut found = 0;
+    for y in 0..=LORES_HEIGHT - glyph.len() {
+        for x in 0..=LORES_WIDTH - width {
+            let matches = glyph.iter().enumerate().all(|(row, bits)| {
+                (0..width).all(|column| {
+                    lit(frame_buffer, x + column, y + row) == (bits & (0x80 >> column) != 0)
+                })
+            });
+            found += matches as usize;
+        }
+    }
+    found
+}
+
+/// Run rom, returning its screen. None if the ROM or its glyphs have not been vendored.
+fn run(rom: &CommunityRom) -> Option<(Box<[u8; SCREEN_SIZE]>, Glyph, Glyph)> {
+    let path = roms_dir().join(rom.file);
+    let glyphs_path = roms_dir().join(format!("{}.glyphs", rom.file));
+    let (Ok(image), Ok(glyphs)) = (std::fs::read(&path), std::fs::read_to_string(&glyphs_path))
+    else {
+        eprintln!("{} has not been vendored, skipping", rom.file);
+        return None;
+    };
+    let (pass, fail) = parse_glyphs(&glyphs);
+
+    let memory = Box::leak(vec![0; MACHINE_MEMORY_SIZE].into_boxed_slice());
+    let mut frame_buffer = Box::new([0; SCREEN_SIZE]);
+    // Returning the framebuffer moves the box, not the buffer the machine points at
+    let mut machine = unsafe { Machine::new(memory, &mut *frame_buffer, rom.platform, rom.quirks) };
+    for (offset, byte) in image.iter().enumerate() {
+        machine.memory.set(PROGRAM_ADDRESS + offset, *byte);
+    }
+    if let Some(selection) = rom.selection {
+        machine.memory.set(SELECTION_ADDRESS, selection);
+    }
+
+    let mut input = rom.input.iter().peekable();
+    for frame in 0..FRAMES {
+        if let Some((_, keys)) = input.next_if(|(at, _)| *at == frame) {
+            machine.set_keys(*keys);
+        }
+        if let Err(fault) = machine.run_frame(INSTRUCTIONS_PER_FRAME) {
+            panic!("{} faulted in frame {frame}: {fault:?}", rom.file);
+        }
+    }
+
+    Some((frame_buffer, pass, fail))
+}
+
+/// Run rom and fail if it drew a fail glyph, or drew no pass glyphs
+fn check(rom: CommunityRom) {
+    let Some((frame_buffer, pass, fail)) = run(&rom) else {
+        return;
+    };
+    let passed = count(&frame_buffer, &pass);
+    let failed = count(&frame_buffer, &fail);
+    println!("{}: {passed} passed, {failed} failed", rom.file);
+    assert!(passed > 0, "{} drew no pass glyphs", rom.file);
+    assert_eq!(failed, 0, "{} has failing checks", rom.file);
+}
+
+#[test]
+fn corax_plus() {
+    check(CommunityRom {
+        file: "3-corax+.ch8",
+        platform: Platform::Chip8,
+        quirks: Quirks::COSMAC_VIP,
+        selection: None,
+        input: &[],
+    });
+}
+
+#[test]
+fn flags() {
+    check(CommunityRom {
+        file: "4-flags.ch8",
+        platform: Platform::Chip8,
+        quirks: Quirks::COSMAC_VIP,
+        selection: None,
+        input: &[],
+    });
+}
+
+#[test]
+fn quirks() {
+    for (selection, platform, quirks) in [
+        (1, Platform::Chip8, Quirks::COSMAC_VIP),
+        (2, Platform::SuperChip, Quirks::SUPER_CHIP),
+        (3, Platform::XoChip, Quirks::XO_CHIP),
+    ] {
+        check(CommunityRom {
+            file: "5-quirks.ch8",
+            platform,
+            quirks,
+            selection: Some(selection),
+            input: &[],
+        });
+    }
+}
+
+#[test]
+fn keypad() {
+    // The FX0A test, which waits for a key to be pressed and released
+    check(CommunityRom {
+        file: "6-keypad.ch8",
+        platform: Platform::Chip8,
+        quirks: Quirks::COSMAC_VIP,
+        selection: Some(3),
+        input: &[(60, 1 << 0x5), (70, 0)],
+    });
+}
+
+#[test]
+fn glyphs_are_found_where_drawn() {
+    // The scanner finds glyphs drawn by a program, and only those
+    let pass = [0x10, 0x10, 0x20, 0xA0, 0x40];
+    let fail = [0x90, 0x60, 0x60, 0x90, 0x00];
+    let program = [
+        0xA2, 0x10, // LD I, pass
+        0x60, 0x03, 0x61, 0x04, 0xD0, 0x15, // DRW at 3, 4
+        0x60, 0x20, 0xD0, 0x15, // DRW at 32, 4
+        0x12, 0x0C, // JP self
+        0x00, 0x00, //
+    ];
+    let memory = Box::leak(vec![0; MACHINE_MEMORY_SIZE].into_boxed_slice());
+    let mut frame_buffer = Box::new([0; SCREEN_SIZE]);
+    // The framebuffer is dropped after the machine
+    let mut machine = unsafe {
+        Machine::new(
+            memory,
+            &mut *frame_buffer,
+            Platform::Chip8,
+            Quirks::COSMAC_VIP,
+        )
+    };
+    for (offset, byte) in program.iter().chain(&pass).enumerate() {
+        machine.memory.set(PROGRAM_ADDRESS + offset, *byte);
+    }
+    machine.run_frame(INSTRUCTIONS_PER_FRAME).unwrap();
+
+    assert_eq!(count(&frame_buffer, &pass), 2);
+    assert_eq!(count(&frame_buffer, &fail), 0);
+    assert_eq!(
+        parse_glyphs("pass 10 10 20 A0 40\nfail 90 60 60 90 00\n"),
+        (pass.to_vec(), fail.to_vec())
+    );
+}
@@ -0,0 +1,816 @@
+//! Conformance checks in the style of the community CHIP-8 test suites (corax+, flags, quirks and
+//! keypad). Each suite is a CHIP-8 program assembled here that runs one check per opcode
+//! behaviour and draws a tick or a cross for the result into a grid on the screen, as the
+//! community ROMs do. The results are decoded from the glyphs in the framebuffer rather than read
+//! from machine state, so every check goes through the same fetch, decode and draw path as a ROM.
+//!
+//! Each check runs a short sequence of instructions and then compares one register against an
+//! expected value with 3XNN, so the glyph drawn records whether the instructions left the machine
+//! in the expected state.
+//!
+//! The community ROMs themselves are run by community.rs when they have been vendored into
+//! tests/roms.
+use chip8_core::cpu::Platform;
+use chip8_core::font::Font;
+use chip8_core::machine::Machine;
+use chip8_core::memory::{PROGRAM_ADDRESS, SCREEN_SIZE, SCREEN_WIDTH};
+use chip8_core::quirks::Quirks;
+
+/// The bytes of machine memory chip-8-rust runs ROMs with
+const MACHINE_MEMORY_SIZE: usize = 0x1000;
+
+/// The default CPU speed of 600 instructions per second
+const INSTRUCTIONS_PER_FRAME: usize = 10;
+
+/// A suite that has not exited after this many frames is reported as hung
+const MAX_FRAMES: usize = 600;
+
+/// Checks that store to memory use the bytes from here, above the assembled checks
+const DATA_ADDRESS: u16 = 0xD00;
+
+/// Where the pass and fail glyphs are placed, well clear of the assembled checks
+const PASS_ADDRESS: u16 = 0xE00;
+const FAIL_ADDRESS: u16 = 0xE05;
+
+/// 4x5 glyphs in the top nibble of each row, a tick for pass and a cross for fail
+const PASS_GLYPH: [u8; 5] = [0x10, 0x10, 0x20, 0xA0, 0x40];
+const FAIL_GLYPH: [u8; 5] = [0x90, 0x60, 0x60, 0x90, 0x00];
+
+/// Glyphs are drawn into a lo-res grid of cells, CELL_WIDTH by CELL_HEIGHT pixels
+const CELL_WIDTH: usize = 8;
+const CELL_HEIGHT: usize = 6;
+const GRID_COLUMNS: usize = 8;
+const GRID_ROWS: usize = 5;
+
+/// The grid leaves a scratch area in the bottom right corner for checks that draw
+const SCRATCH_X: u8 = 60;
+const SCRATCH_Y: u8 = 26;
+
+/// Registers reserved for the position of the glyph being drawn
+const VX_COLUMN: u8 = 0xC;
+const VY_ROW: u8 = 0xD;
+
+/// Registers used by the checks
+const V0: u8 = 0x0;
+const V1: u8 = 0x1;
+const V2: u8 = 0x2;
+const V5: u8 = 0x5;
+const VF: u8 = 0xF;
+
+fn cls() -> u16 {
+    0x00E0
+}
+
+fn ret() -> u16 {
+    0x00EE
+}
+
+fn jp(nnn: u16) -> u16 {
+    0x1000 | nnn
+}
+
+fn call(nnn: u16) -> u16 {
+    0x2000 | nnn
+}
+
+fn se(x: u8, nn: u8) -> u16 {
+    0x3000 | (x as u16) << 8 | nn as u16
+}
+
+fn sne(x: u8, nn: u8) -> u16 {
+    0x4000 | (x as u16) << 8 | nn as u16
+}
+
+fn se_reg(x: u8, y: u8) -> u16 {
+    0x5000 | (x as u16) << 8 | (y as u16) << 4
+}
+
+fn ld(x: u8, nn: u8) -> u16 {
+    0x6000 | (x as u16) << 8 | nn as u16
+}
+
+fn add(x: u8, nn: u8) -> u16 {
+    0x7000 | (x as u16) << 8 | nn as u16
+}
+
+/// An 8XYN register operation
+fn alu(x: u8, y: u8, n: u8) -> u16 {
+    0x8000 | (x as u16) << 8 | (y as u16) << 4 | n as u16
+}
+
+fn sne_reg(x: u8, y: u8) -> u16 {
+    0x9000 | (x as u16) << 8 | (y as u16) << 4
+}
+
+fn ld_i(nnn: u16) -> u16 {
+    0xA000 | nnn
+}
+
+fn jp_v0(nnn: u16) -> u16 {
+    0xB000 | nnn
+}
+
+fn rnd(x: u8, nn: u8) -> u16 {
+    0xC000 | (x as u16) << 8 | nn as u16
+}
+
+fn drw(x: u8, y: u8, n: u8) -> u16 {
+    0xD000 | (x as u16) << 8 | (y as u16) << 4 | n as u16
+}
+
+fn skp(x: u8) -> u16 {
+    0xE09E | (x as u16) << 8
+}
+
+fn sknp(x: u8) -> u16 {
+    0xE0A1 | (x as u16) << 8
+}
+
+/// An FXNN operation
+fn misc(x: u8, nn: u8) -> u16 {
+    0xF000 | (x as u16) << 8 | nn as u16
+}
+
+const LD_VX_DT: u8 = 0x07;
+const LD_VX_K: u8 = 0x0A;
+const LD_DT_VX: u8 = 0x15;
+const ADD_I_VX: u8 = 0x1E;
+const LD_F_VX: u8 = 0x29;
+const LD_HF_VX: u8 = 0x30;
+const LD_B_VX: u8 = 0x33;
+const LD_I_VX: u8 = 0x55;
+const LD_VX_I: u8 = 0x65;
+
+const OR: u8 = 0x1;
+const AND: u8 = 0x2;
+const XOR: u8 = 0x3;
+const ADD: u8 = 0x4;
+const SUB: u8 = 0x5;
+const SHR: u8 = 0x6;
+const SUBN: u8 = 0x7;
+const SHL: u8 = 0xE;
+
+/// A CHIP-8 program built from checks, along with the name of each check in grid order
+struct Suite {
+    code: Vec<u16>,
+    names: Vec<String>,
+}
+
+impl Suite {
+    fn new() -> Self {
+        Self {
+            code: Vec::new(),
+            names: Vec::new(),
+        }
+    }
+
+    /// The address the next instruction is assembled at
+    fn address(&self) -> u16 {
+        PROGRAM_ADDRESS as u16 + self.code.len() as u16 * 2
+    }
+
+    /// Add a check that runs code and then expects register to hold expected
+    fn check(&mut self, name: &str, register: u8, expected: u8, code: &[u16]) {
+        self.check_at(name, register, expected, |_| code.to_vec());
+    }
+
+    /// As check, for code that needs to know the address it is assembled at
+    fn check_at(
+        &mut self,
+        name: &str,
+        register: u8,
+        expected: u8,
+        code: impl FnOnce(u16) -> Vec<u16>,
+    ) {
+        let index = self.names.len();
+        assert!(index < GRID_COLUMNS * GRID_ROWS, "too many checks in suite");
+        self.names.push(name.to_string());
+
+        let column = (index % GRID_COLUMNS * CELL_WIDTH) as u8;
+        let row = (index / GRID_COLUMNS * CELL_HEIGHT) as u8;
+        self.code.extend([ld(VX_COLUMN, column), ld(VY_ROW, row)]);
+        let code = code(self.address());
+        self.code.extend(code);
+
+        let fail = self.address() + 8;
+        let draw = fail + 2;
+        self.code.extend([
+            se(register, expected),
+            jp(fail),
+            ld_i(PASS_ADDRESS),
+            jp(draw),
+            ld_i(FAIL_ADDRESS),
+            drw(VX_COLUMN, VY_ROW, 5),
+        ]);
+    }
+
+    /// Assemble the suite into a ROM image that loops forever after the last check, returning
+    /// the address of the loop along with the image. 00FD is not available on every platform.
+    fn assemble(mut self) -> (Vec<u8>, u16, Vec<String>) {
+        let end = self.address();
+        self.code.push(jp(end));
+        let mut rom: Vec<u8> = self.code.iter().flat_map(|op| op.to_be_bytes()).collect();
+        assert!(
+            rom.len() <= DATA_ADDRESS as usize - PROGRAM_ADDRESS,
+            "suite too large"
+        );
+        rom.resize(PASS_ADDRESS as usize - PROGRAM_ADDRESS, 0);
+        rom.extend(PASS_GLYPH);
+        rom.extend(FAIL_GLYPH);
+        (rom, end, self.names)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Pass,
+    Fail,
+    /// The cell does not hold either glyph, the check never drew or drew over something
+    Missing,
+}
+
+/// Run a suite to completion and decode the result of every check from the screen
+fn run(suite: Suite, platform: Platform, quirks: Quirks, input: &[(usize, u16)]) -> Report {
+    let (rom, end, names) = suite.assemble();
+    let memory = Box::leak(vec![0; MACHINE_MEMORY_SIZE].into_boxed_slice());
+    let mut frame_buffer = Box::new([0; SCREEN_SIZE]);
+    // The framebuffer is dropped after the machine
+    let mut machine = unsafe { Machine::new(memory, &mut *frame_buffer, platform, quirks) };
+    for (offset, byte) in rom.iter().enumerate() {
+        machine.memory.set(PROGRAM_ADDRESS + offset, *byte);
+    }
+
+    let mut input = input.iter().peekable();
+    let mut finished = false;
+    for frame in 0..MAX_FRAMES {
+        if let Some((_, keys)) = input.next_if(|(at, _)| *at == frame) {
+            machine.set_keys(*keys);
+        }
+
+        if let Err(fault) = machine.run_frame(INSTRUCTIONS_PER_FRAME) {
+            panic!("faulted in frame {frame}: {fault:?}");
+        }
+
+        if machine.cpu.registers.pc == end {
+            finished = true;
+            break;
+        }
+    }
+
+    let results = names
+        .into_iter()
+        .enumerate()
+        .map(|(index, name)| (name, decode(&frame_buffer, index)))
+        .collect();
+    Report { finished, results }
+}
+
+/// Whether the lo-res pixel at x, y is lit. Lo-res pixels are 2x2 blocks in the framebuffer.
+fn lit(frame_buffer: &[u8; SCREEN_SIZE], x: usize, y: usize) -> bool {
+    let index = (y * 2) * SCREEN_WIDTH + x * 2;
+    frame_buffer[index >> 3] & (1 << (index & 0b111)) != 0
+}
+
+/// Read the glyph drawn in grid cell index
+fn decode(frame_buffer: &[u8; SCREEN_SIZE], index: usize) -> Outcome {
+    let x = index % GRID_COLUMNS * CELL_WIDTH;
+    let y = index / GRID_COLUMNS * CELL_HEIGHT;
+    let glyph: Vec<u8> = (0..5)
+        .map(|row| {
+            (0..CELL_WIDTH).fold(0, |byte, column| {
+                byte | (lit(frame_buffer, x + column, y + row) as u8) << (7 - column)
+            })
+        })
+        .collect();
+
+    if glyph == PASS_GLYPH {
+        Outcome::Pass
+    } else if glyph == FAIL_GLYPH {
+        Outcome::Fail
+    } else {
+        Outcome::Missing
+    }
+}
+
+struct Report {
+    finished: bool,
+    results: Vec<(String, Outcome)>,
+}
+
+impl Report {
+    /// Print the result of every check and fail the test if any check did not pass
+    fn check(&self, suite: &str) {
+        let mut text = String::new();
+        for (name, outcome) in &self.results {
+            text.push_str(&format!("{suite}: {name:<24} {outcome:?}\n"));
+        }
+        print!("{text}");
+
+        assert!(self.finished, "{suite} did not finish\n{text}");
+        assert!(
+            self.results
+                .iter()
+                .all(|(_, outcome)| *outcome == Outcome::Pass),
+            "{suite} has failing checks\n{text}"
+        );
+    }
+}
+
+/// One check per opcode, in the spirit of the corax+ opcode test
+fn opcodes() -> Suite {
+    let mut suite = Suite::new();
+    let scratch = [
+        ld(V0, SCRATCH_X),
+        ld(V1, SCRATCH_Y),
+        ld(V2, 0),
+        misc(V2, LD_F_VX),
+    ];
+
+    // Nothing has been drawn yet, so a sprite drawn after the clear must not collide
+    let mut clear = scratch.to_vec();
+    clear.extend([
+        drw(V0, V1, 5),
+        cls(),
+        drw(V0, V1, 5),
+        alu(V2, VF, 0),
+        drw(V0, V1, 5),
+    ]);
+    suite.check("00E0 CLS", V2, 0, &clear);
+
+    suite.check_at("1NNN JP", V0, 1, |base| {
+        vec![ld(V0, 1), jp(base + 6), ld(V0, 0)]
+    });
+    suite.check_at("2NNN/00EE CALL/RET", V0, 0x33, |base| {
+        vec![
+            ld(V0, 0),
+            call(base + 6),
+            jp(base + 10),
+            ld(V0, 0x33),
+            ret(),
+        ]
+    });
+    suite.check("3XNN SE", V0, 5, &[ld(V0, 5), se(V0, 5), ld(V0, 0)]);
+    suite.check("4XNN SNE", V0, 5, &[ld(V0, 5), sne(V0, 6), ld(V0, 0)]);
+    suite.check(
+        "5XY0 SE",
+        V0,
+        5,
+        &[ld(V0, 5), ld(V1, 5), se_reg(V0, V1), ld(V0, 0)],
+    );
+    suite.check("6XNN LD", V0, 0x42, &[ld(V0, 0x42)]);
+    suite.check("7XNN ADD", V0, 0x01, &[ld(V0, 0xFF), add(V0, 2)]);
+    suite.check(
+        "7XNN keeps VF",
+        VF,
+        7,
+        &[ld(VF, 7), ld(V0, 0xFF), add(V0, 2)],
+    );
+    suite.check("8XY0 LD", V0, 0x42, &[ld(V1, 0x42), alu(V0, V1, 0)]);
+    suite.check(
+        "8XY1 OR",
+        V0,
+        0xFF,
+        &[ld(V0, 0x0F), ld(V1, 0xF0), alu(V0, V1, OR)],
+    );
+    suite.check(
+        "8XY2 AND",
+        V0,
+        0x0C,
+        &[ld(V0, 0x3C), ld(V1, 0x0F), alu(V0, V1, AND)],
+    );
+    suite.check(
+        "8XY3 XOR",
+        V0,
+        0xF0,
+        &[ld(V0, 0xFF), ld(V1, 0x0F), alu(V0, V1, XOR)],
+    );
+    suite.check(
+        "8XY4 ADD",
+        V0,
+        0x30,
+        &[ld(V0, 0x10), ld(V1, 0x20), alu(V0, V1, ADD)],
+    );
+    suite.check(
+        "8XY5 SUB",
+        V0,
+        0x20,
+        &[ld(V0, 0x30), ld(V1, 0x10), alu(V0, V1, SUB)],
+    );
+    suite.check(
+        "8XY6 SHR",
+        V0,
+        0x42,
+        &[ld(V0, 0x84), ld(V1, 0x84), alu(V0, V1, SHR)],
+    );
+    suite.check(
+        "8XY7 SUBN",
+        V0,
+        0x20,
+        &[ld(V0, 0x10), ld(V1, 0x30), alu(V0, V1, SUBN)],
+    );
+    suite.check(
+        "8XYE SHL",
+        V0,
+        0x82,
+        &[ld(V0, 0x41), ld(V1, 0x41), alu(V0, V1, SHL)],
+    );
+    suite.check(
+        "9XY0 SNE",
+        V0,
+        5,
+        &[ld(V0, 5), ld(V1, 6), sne_reg(V0, V1), ld(V0, 0)],
+    );
+
+    // Both V0 and VX hold the offset, so this passes whichever register the jump uses
+    suite.check_at("BNNN JP V0", V5, 1, |base| {
+        let target = base + 8;
+        let x = (target >> 8) as u8;
+        vec![
+            ld(V0, 4),
+            ld(x, 4),
+            ld(V5, 1),
+            jp_v0(target),
+            ld(V5, 0),
+            ld(V5, 0),
+        ]
+    });
+    suite.check(
+        "CXNN RND",
+        V0,
+        0,
+        &[rnd(V0, 0x0F), ld(V1, 0xF0), alu(V0, V1, AND)],
+    );
+
+    let mut collide = scratch.to_vec();
+    collide.extend([drw(V0, V1, 5), alu(V2, VF, 0), drw(V0, V1, 5)]);
+    suite.check("DXYN no collision", V2, 0, &collide);
+    suite.check("DXYN collision", VF, 1, &collide);
+
+    suite.check_at("FX07/FX15 DT", V1, 0, |base| {
+        vec![
+            ld(V0, 10),
+            misc(V0, LD_DT_VX),
+            misc(V1, LD_VX_DT),
+            se(V1, 0),
+            jp(base + 4),
+        ]
+    });
+    suite.check(
+        "FX1E/FX55/FX65",
+        V0,
+        0x5A,
+        &[
+            ld_i(DATA_ADDRESS),
+            ld(V0, 0x10),
+            misc(V0, ADD_I_VX),
+            ld(V0, 0x5A),
+            misc(V0, LD_I_VX),
+            ld_i(DATA_ADDRESS + 0x10),
+            ld(V0, 0),
+            misc(V0, LD_VX_I),
+        ],
+    );
+    suite.check(
+        "FX29 LD F",
+        V0,
+        0xF0,
+        &[ld(V0, 0xA), misc(V0, LD_F_VX), misc(V0, LD_VX_I)],
+    );
+    let bcd = [
+        ld(V0, 137),
+        ld_i(DATA_ADDRESS + 0x20),
+        misc(V0, LD_B_VX),
+        ld_i(DATA_ADDRESS + 0x20),
+        misc(V2, LD_VX_I),
+    ];
+    suite.check("FX33 hundreds", V0, 1, &bcd);
+    suite.check("FX33 tens", V1, 3, &bcd);
+    suite.check("FX33 ones", V2, 7, &bcd);
+
+    // Addresses wrap at the end of the 4K address space, so the last digit lands at 0x001
+    suite.check(
+        "FX33 wraps",
+        V0,
+        7,
+        &[
+            ld(V0, 137),
+            ld_i(0xFFF),
+            misc(V0, LD_B_VX),
+            ld_i(0x001),
+            misc(V0, LD_VX_I),
+        ],
+    );
+    suite.check(
+        "FX55/FX65 wrap",
+        V0,
+        0x22,
+        &[
+            ld(V0, 0x11),
+            ld(V1, 0x22),
+            ld_i(0xFFF),
+            misc(V1, LD_I_VX),
+            ld_i(0x000),
+            misc(V0, LD_VX_I),
+        ],
+    );
+    suite
+}
+
+/// The result and VF of every arithmetic operation, including with VF as an operand, in the
+/// spirit of the flags test
+fn flags() -> Suite {
+    let mut suite = Suite::new();
+
+    // name, VX, VY, operation, result, flag
+    let cases = [
+        ("8XY4", 0x10, 0x20, ADD, 0x30, 0),
+        ("8XY4 carry", 0xFF, 0x02, ADD, 0x01, 1),
+        ("8XY5", 0x30, 0x10, SUB, 0x20, 1),
+        ("8XY5 equal", 0x05, 0x05, SUB, 0x00, 1),
+        ("8XY5 borrow", 0x10, 0x30, SUB, 0xE0, 0),
+        ("8XY7", 0x10, 0x30, SUBN, 0x20, 1),
+        ("8XY7 borrow", 0x30, 0x10, SUBN, 0xE0, 0),
+        ("8XY6", 0x84, 0x84, SHR, 0x42, 0),
+        ("8XY6 carry", 0x85, 0x85, SHR, 0x42, 1),
+        ("8XYE", 0x41, 0x41, SHL, 0x82, 0),
+        ("8XYE carry", 0x81, 0x81, SHL, 0x02, 1),
+    ];
+
+    for (name, x, y, op, result, flag) in cases {
+        let code = [ld(V0, x), ld(V1, y), alu(V0, V1, op)];
+        suite.check(name, V0, result, &code);
+        suite.check(&format!("{name} VF"), VF, flag, &code);
+
+        // The flag is written after the result, so it wins when VF is the destination
+        let code = [ld(VF, x), ld(V1, y), alu(VF, V1, op)];
+        suite.check(&format!("{name} VF dest"), VF, flag, &code);
+    }
+
+    // VF as the source operand is read before the flag is written
+    suite.check(
+        "8XF4 VF source",
+        V0,
+        0x05,
+        &[ld(V0, 0x10), ld(VF, 0xF5), alu(V0, VF, ADD)],
+    );
+    suite
+}
+
+/// The behaviours that interpreters disagree on. The expected result of each check depends on
+/// the platform and quirks the suite is run with.
+fn quirks(platform: Platform, quirks: Quirks) -> Suite {
+    let mut suite = Suite::new();
+
+    let flag = if quirks.logic_resets_vf { 0 } else { 5 };
+    for (name, op) in [("8XY1 VF", OR), ("8XY2 VF", AND), ("8XY3 VF", XOR)] {
+        suite.check(
+            name,
+            VF,
+            flag,
+            &[ld(VF, 5), ld(V0, 0x0F), ld(V1, 0xF0), alu(V0, V1, op)],
+        );
+    }
+
+    let (right, left) = if quirks.shift_uses_vy {
+        (0x20, 0x80)
+    } else {
+        (0x08, 0x20)
+    };
+    suite.check(
+        "8XY6 source",
+        V0,
+        right,
+        &[ld(V0, 0x10), ld(V1, 0x40), alu(V0, V1, SHR)],
+    );
+    suite.check(
+        "8XYE source",
+        V0,
+        left,
+        &[ld(V0, 0x10), ld(V1, 0x40), alu(V0, V1, SHL)],
+    );
+
+    // DATA_ADDRESS + 2 holds a marker, so reading back from I after a store shows whether I moved
+    let store = if quirks.load_store_increments_i {
+        0x33
+    } else {
+        0x11
+    };
+    suite.check(
+        "FX55 I",
+        V0,
+        store,
+        &[
+            ld_i(DATA_ADDRESS + 2),
+            ld(V0, 0x33),
+            misc(V0, LD_I_VX),
+            ld_i(DATA_ADDRESS),
+            ld(V0, 0x11),
+            ld(V1, 0x22),
+            misc(V1, LD_I_VX),
+            misc(V0, LD_VX_I),
+        ],
+    );
+    let load = if quirks.load_store_increments_i {
+        0x33
+    } else {
+        0x11
+    };
+    suite.check(
+        "FX65 I",
+        V0,
+        load,
+        &[
+            ld_i(DATA_ADDRESS + 2),
+            ld(V0, 0x33),
+            misc(V0, LD_I_VX),
+            ld_i(DATA_ADDRESS),
+            ld(V0, 0x11),
+            misc(V0, LD_I_VX),
+            ld_i(DATA_ADDRESS),
+            misc(V1, LD_VX_I),
+            misc(V0, LD_VX_I),
+        ],
+    );
+
+    // V0 and VX hold different offsets, each landing on a different value for V5
+    let jump = if quirks.jump_uses_vx { 2 } else { 1 };
+    suite.check_at("BXNN", V5, jump, |base| {
+        let target = base + 6;
+        let x = (target >> 8) as u8;
+        vec![
+            ld(V0, 4),
+            ld(x, 8),
+            jp_v0(target),
+            ld(V5, 0),
+            ld(V5, 0),
+            ld(V5, 1),
+            jp(target + 10),
+            ld(V5, 2),
+        ]
+    });
+
+    // A sprite drawn over the right edge collides with one at the left edge only if it wraps
+    let wrap = if quirks.clip_sprites { 0 } else { 1 };
+    suite.check(
+        "DXYN wrap",
+        V2,
+        wrap,
+        &[
+            ld(V2, 0),
+            misc(V2, LD_F_VX),
+            ld(V0, 0),
+            ld(V1, SCRATCH_Y),
+            drw(V0, V1, 5),
+            ld(V0, 62),
+            drw(V0, V1, 5),
+            alu(V2, VF, 0),
+            drw(V0, V1, 5),
+            ld(V0, 0),
+            drw(V0, V1, 5),
+        ],
+    );
+
+    // A row of 8 pixels drawn just right of a lo-res DXY0 sprite collides with it only if the
+    // sprite is 16 wide. Both go on the bottom row, below the grid, and are erased afterwards.
+    let wide = match platform {
+        Platform::Chip8 => 0,
+        _ if quirks.lores_dxy0_draws_8x16 => 0,
+        _ => 1,
+    };
+    suite.check(
+        "DXY0 lo-res width",
+        V5,
+        wide,
+        &[
+            ld(V0, 0xFF),
+            ld(V1, 0xFF),
+            ld_i(DATA_ADDRESS),
+            misc(V1, LD_I_VX),
+            ld_i(DATA_ADDRESS),
+            ld(V0, 40),
+            ld(V1, 31),
+            ld(V2, 48),
+            drw(V0, V1, 0),
+            drw(V2, V1, 1),
+            alu(V5, VF, 0),
+            drw(V2, V1, 1),
+            drw(V0, V1, 0),
+        ],
+    );
+    suite
+}
+
+/// FX29 finds the selected small font, told apart by the first row of B, and FX30 the big font
+fn fonts(font: Font) -> Suite {
+    let mut suite = Suite::new();
+    let b = match font {
+        Font::SuperChip => 0xE0,
+        Font::Vip => 0xF0,
+        Font::Dream6800 => 0xC0,
+        Font::Eti660 => 0x80,
+    };
+    suite.check(
+        "FX29 B",
+        V0,
+        b,
+        &[ld(V0, 0xB), misc(V0, LD_F_VX), misc(V0, LD_VX_I)],
+    );
+    suite.check(
+        "FX30 8",
+        V1,
+        0x7E,
+        &[ld(V0, 0x8), misc(V0, LD_HF_VX), misc(V1, LD_VX_I)],
+    );
+    suite.check(
+        "FX30 F",
+        V0,
+        0xFF,
+        &[ld(V0, 0xF), misc(V0, LD_HF_VX), misc(V0, LD_VX_I)],
+    );
+    suite
+}
+
+/// FX0A waits for key 5, pressed by the harness, and EX9E / EXA1 check it is held
+fn keypad() -> Suite {
+    let mut suite = Suite::new();
+    suite.check("FX0A", V0, 5, &[ld(V0, 0), misc(V0, LD_VX_K)]);
+    suite.check(
+        "EX9E pressed",
+        V0,
+        1,
+        &[ld(V1, 5), ld(V0, 1), skp(V1), ld(V0, 0)],
+    );
+    suite.check(
+        "EX9E released",
+        V0,
+        0,
+        &[ld(V1, 6), ld(V0, 1), skp(V1), ld(V0, 0)],
+    );
+    suite.check(
+        "EXA1 pressed",
+        V0,
+        1,
+        &[ld(V1, 5), ld(V0, 0), sknp(V1), ld(V0, 1)],
+    );
+    suite.check(
+        "EXA1 released",
+        V0,
+        0,
+        &[ld(V1, 6), ld(V0, 0), sknp(V1), ld(V0, 1)],
+    );
+    suite
+}
+
+#[test]
+fn corax() {
+    run(opcodes(), Platform::Chip8, Quirks::COSMAC_VIP, &[]).check("corax");
+}
+
+#[test]
+fn flags_chip8() {
+    run(flags(), Platform::Chip8, Quirks::COSMAC_VIP, &[]).check("flags");
+}
+
+#[test]
+fn flags_superchip() {
+    run(flags(), Platform::SuperChip, Quirks::SUPER_CHIP, &[]).check("flags");
+}
+
+#[test]
+fn quirks_presets() {
+    for (name, platform, preset) in [
+        ("quirks cosmac-vip", Platform::Chip8, Quirks::COSMAC_VIP),
+        ("quirks chip-48", Platform::Chip8, Quirks::CHIP_48),
+        ("quirks super-chip", Platform::SuperChip, Quirks::SUPER_CHIP),
+        ("quirks xo-chip", Platform::XoChip, Quirks::XO_CHIP),
+    ] {
+        run(quirks(platform, preset), platform, preset, &[]).check(name);
+    }
+}
+
+#[test]
+fn keypad_input() {
+    run(
+        keypad(),
+        Platform::Chip8,
+        Quirks::COSMAC_VIP,
+        &[(10, 1 << 5)],
+    )
+    .check("keypad");
+}
+
+#[test]
+fn font_sets() {
+    for (name, font) in [
+        ("fonts super-chip", Font::SuperChip),
+        ("fonts vip", Font::Vip),
+        ("fonts dream-6800", Font::Dream6800),
+        ("fonts eti-660", Font::Eti660),
+    ] {
+        let quirks = Quirks {
+            font,
+            ..Quirks::SUPER_CHIP
+        };
+        run(fonts(font), Platform::SuperChip, quirks, &[]).check(name);
+    }
+}
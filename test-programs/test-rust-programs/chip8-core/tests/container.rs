@@ -0,0 +1,79 @@
+//! Check that ROM containers round trip through header and parse, and that headers no ROM can
+//! have are rejected.
+use chip8_core::container::{
+    Container, ContainerError, FLAG_VIP_TIMING, HEADER_SIZE, MAX_PROGRAM_SIZE,
+};
+use chip8_core::cpu::Platform;
+use chip8_core::quirks::Quirks;
+
+const PROGRAM: [u8; 4] = [0x60, 0x05, 0x12, 0x02];
+
+fn container(program: &[u8]) -> Container<'_> {
+    Container {
+        title: "TEST",
+        platform: Platform::XoChip.to_u8(),
+        quirks: Quirks::XO_CHIP.to_bits(),
+        flags: FLAG_VIP_TIMING,
+        instructions_per_frame: 20,
+        keymap: *b"x123qweasdzc4rfv",
+        program,
+    }
+}
+
+fn image(container: &Container) -> Vec<u8> {
+    let mut image = container.header().to_vec();
+    image.extend_from_slice(container.program);
+    image
+}
+
+#[test]
+fn round_trip() {
+    let image = image(&container(&PROGRAM));
+    let parsed = Container::parse(&image).unwrap();
+    assert_eq!(parsed.title, "TEST");
+    assert_eq!(parsed.platform, Platform::XoChip.to_u8());
+    assert_eq!(parsed.quirks, Quirks::XO_CHIP.to_bits());
+    assert_eq!(parsed.flags, FLAG_VIP_TIMING);
+    assert_eq!(parsed.instructions_per_frame, 20);
+    assert_eq!(&parsed.keymap, b"x123qweasdzc4rfv");
+    assert_eq!(parsed.program, PROGRAM);
+}
+
+#[test]
+fn bad_headers_are_rejected() {
+    let good = image(&container(&PROGRAM));
+
+    let mut unknown_flag = good.clone();
+    unknown_flag[7] |= 0x80;
+    assert_eq!(
+        Container::parse(&unknown_flag).unwrap_err(),
+        ContainerError::Invalid
+    );
+
+    let no_instructions = image(&Container {
+        instructions_per_frame: 0,
+        ..container(&PROGRAM)
+    });
+    assert_eq!(
+        Container::parse(&no_instructions).unwrap_err(),
+        ContainerError::Invalid
+    );
+
+    let mut corrupt = good.clone();
+    corrupt[HEADER_SIZE] ^= 1;
+    assert_eq!(
+        Container::parse(&corrupt).unwrap_err(),
+        ContainerError::BadChecksum
+    );
+
+    assert_eq!(
+        Container::parse(&good[..good.len() - 1]).unwrap_err(),
+        ContainerError::Truncated
+    );
+
+    let large = vec![1; MAX_PROGRAM_SIZE + 1];
+    assert_eq!(
+        Container::parse(&image(&container(&large))).unwrap_err(),
+        ContainerError::TooLarge
+    );
+}
@@ -0,0 +1,109 @@
+//! Check that memory write watchpoints and conditional register breakpoints stop the machine
+//! after the instruction that triggers them.
+use chip8_core::breakpoint::{Comparison, Condition, Register, Trigger};
+use chip8_core::cpu::{Platform, StepOutcome};
+use chip8_core::machine::Machine;
+use chip8_core::memory::{PROGRAM_ADDRESS, SCREEN_SIZE};
+use chip8_core::quirks::Quirks;
+
+/// The bytes of machine memory chip-8-rust runs ROMs with
+const MACHINE_MEMORY_SIZE: usize = 0x1000;
+
+/// Count V0 up, storing it at 0x300 each time round the loop
+const PROGRAM: [u16; 4] = [
+    0xA300, // LD I, 0x300
+    0x7001, // ADD V0, 1
+    0xF055, // LD [I], V0
+    0x1202, // JP 0x202
+];
+
+/// Create a machine running PROGRAM
+fn load() -> Machine {
+    let memory = Box::leak(vec![0; MACHINE_MEMORY_SIZE].into_boxed_slice());
+    for (index, instruction) in PROGRAM.iter().enumerate() {
+        memory[PROGRAM_ADDRESS + 2 * index..][..2].copy_from_slice(&instruction.to_be_bytes());
+    }
+    // The framebuffer is leaked so it outlives the machine
+    let frame_buffer = Box::leak(Box::new([0; SCREEN_SIZE]));
+    unsafe { Machine::new(memory, frame_buffer, Platform::Chip8, Quirks::SUPER_CHIP) }
+}
+
+/// Step until the machine stops at a debug table entry, or give up after max_steps
+fn run_until_stopped(machine: &mut Machine, max_steps: usize) -> Option<Trigger> {
+    (0..max_steps).find_map(|_| match machine.step().unwrap() {
+        StepOutcome::Breakpoint(trigger) => Some(trigger),
+        _ => None,
+    })
+}
+
+#[test]
+fn watchpoint_stops_on_write() {
+    let mut machine = load();
+
+    // A write outside the range does not stop the machine
+    assert!(machine.add_watchpoint(0x301, 4));
+    assert_eq!(run_until_stopped(&mut machine, 100), None);
+    machine.remove_watchpoint(0x301);
+
+    assert!(machine.add_watchpoint(0x2FE, 4));
+    let trigger = run_until_stopped(&mut machine, 100);
+    assert_eq!(
+        trigger,
+        Some(Trigger::Watchpoint {
+            pc: 0x204,
+            address: 0x300,
+            value: machine.cpu.registers.v[0],
+        })
+    );
+
+    // The write has happened and the machine has moved past it
+    assert_eq!(machine.memory.get(0x300), machine.cpu.registers.v[0]);
+    assert_eq!(machine.cpu.registers.pc, 0x206);
+
+    // It stops again on the next write
+    let value = machine.cpu.registers.v[0];
+    machine.resume();
+    let trigger = run_until_stopped(&mut machine, 100);
+    assert_eq!(
+        trigger,
+        Some(Trigger::Watchpoint {
+            pc: 0x204,
+            address: 0x300,
+            value: value + 1,
+        })
+    );
+}
+
+#[test]
+fn condition_stops_when_it_becomes_true() {
+    let mut machine = load();
+    let condition = Condition::new(Register::V(0), Comparison::Equal, 0x10);
+    assert!(machine.add_condition(condition));
+
+    let trigger = run_until_stopped(&mut machine, 1000);
+    assert_eq!(
+        trigger,
+        Some(Trigger::Condition {
+            pc: 0x202,
+            condition
+        })
+    );
+    assert_eq!(machine.cpu.registers.v[0], 0x10);
+    assert_eq!(machine.cpu.registers.pc, 0x204);
+
+    // It does not stop again while the condition holds, only once V0 wraps round to 0x10 again
+    machine.resume();
+    let trigger = run_until_stopped(&mut machine, 1000);
+    assert_eq!(
+        trigger,
+        Some(Trigger::Condition {
+            pc: 0x202,
+            condition
+        })
+    );
+    assert_eq!(machine.cpu.registers.v[0], 0x10);
+
+    machine.remove_condition(condition);
+    machine.resume();
+    assert_eq!(run_until_stopped(&mut machine, 1000), None);
+}
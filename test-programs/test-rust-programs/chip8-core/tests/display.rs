@@ -0,0 +1,109 @@
+//! Check that the framebuffer downsamples to the 64x32 lo-res layout of the board's hardware
+//! framebuffer, and that a frame reports every change to the framebuffer so the board can present
+//! them as they are drawn.
+use chip8_core::cpu::Platform;
+use chip8_core::machine::Machine;
+use chip8_core::memory::{
+    LORES_HEIGHT, LORES_SIZE, LORES_WIDTH, PROGRAM_ADDRESS, SCREEN_SIZE, SCREEN_WIDTH, downsample,
+};
+use chip8_core::quirks::Quirks;
+
+fn pixel(buffer: &[u8], width: usize, x: usize, y: usize) -> bool {
+    let index = y * width + x;
+    buffer[index / 8] & (1 << (index % 8)) != 0
+}
+
+fn set_pixel(buffer: &mut [u8], width: usize, x: usize, y: usize) {
+    let index = y * width + x;
+    buffer[index / 8] |= 1 << (index % 8);
+}
+
+#[test]
+fn lores_image_survives_downsampling() {
+    // Draw a scattered lo-res pattern as the machine does, each pixel as a 2x2 block
+    let lit = |x: usize, y: usize| (x * 7 + y * 3).is_multiple_of(5);
+    let mut frame_buffer = [0; SCREEN_SIZE];
+    for y in 0..LORES_HEIGHT {
+        for x in 0..LORES_WIDTH {
+            if lit(x, y) {
+                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    set_pixel(&mut frame_buffer, SCREEN_WIDTH, 2 * x + dx, 2 * y + dy);
+                }
+            }
+        }
+    }
+
+    let mut lores = [0; LORES_SIZE];
+    downsample(&frame_buffer, &mut lores);
+    for y in 0..LORES_HEIGHT {
+        for x in 0..LORES_WIDTH {
+            assert_eq!(
+                pixel(&lores, LORES_WIDTH, x, y),
+                lit(x, y),
+                "pixel ({x}, {y})"
+            );
+        }
+    }
+}
+
+#[test]
+fn any_hires_pixel_lights_its_block() {
+    for (x, y) in [
+        (0, 0),
+        (1, 0),
+        (0, 1),
+        (1, 1),
+        (127, 63),
+        (66, 33),
+        (15, 40),
+    ] {
+        let mut frame_buffer = [0; SCREEN_SIZE];
+        set_pixel(&mut frame_buffer, SCREEN_WIDTH, x, y);
+        let mut lores = [0xFF; LORES_SIZE];
+        downsample(&frame_buffer, &mut lores);
+
+        let lit: Vec<_> = (0..LORES_WIDTH * LORES_HEIGHT)
+            .filter(|index| {
+                pixel(
+                    &lores,
+                    LORES_WIDTH,
+                    index % LORES_WIDTH,
+                    index / LORES_WIDTH,
+                )
+            })
+            .collect();
+        assert_eq!(
+            lit,
+            [(y / 2) * LORES_WIDTH + x / 2],
+            "hi-res pixel ({x}, {y})"
+        );
+    }
+}
+
+#[test]
+fn every_draw_is_reported() {
+    // CLS; LD V0, 0; LD F, V0; DRW V0, V0, 5; DRW V0, V0, 5; JP 0x20A
+    let program = [
+        0x00, 0xE0, 0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0xD0, 0x05, 0x12, 0x0A,
+    ];
+    let memory = Box::leak(vec![0; 0x1000].into_boxed_slice());
+    memory[PROGRAM_ADDRESS..PROGRAM_ADDRESS + program.len()].copy_from_slice(&program);
+    let frame_buffer: *mut [u8; SCREEN_SIZE] = Box::leak(Box::new([0xFF; SCREEN_SIZE]));
+    // The framebuffer is leaked so it outlives the machine
+    let mut machine =
+        unsafe { Machine::new(memory, frame_buffer, Platform::Chip8, Quirks::SUPER_CHIP) };
+
+    // The framebuffer after each reported change: cleared, the glyph drawn, the glyph erased
+    let mut drawn = Vec::new();
+    machine
+        .run_frame_drawing(20, || {
+            drawn.push(unsafe { (*frame_buffer).iter().any(|byte| *byte != 0) })
+        })
+        .unwrap();
+    assert_eq!(drawn, [false, true, false]);
+
+    // The loop draws nothing more
+    drawn.clear();
+    machine.run_frame_drawing(20, || drawn.push(true)).unwrap();
+    assert!(drawn.is_empty());
+}
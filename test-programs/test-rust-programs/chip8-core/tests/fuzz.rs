@@ -0,0 +1,186 @@
+//! Run random ROMs with random keypad input through Cpu::step, checking that a ROM cannot make
+//! the interpreter panic, reach outside of its memory or grow the stack without bound. On the
+//! board memory and the framebuffer are raw pointers into RAM shared with everything else, so any
+//! of these would let a bad ROM corrupt the rest of the system.
+//!
+//! Memory is bounds-checked: it is a slice, so an access past the end of backing memory panics
+//! rather than reading or writing past it. Faults are expected, the fuzzer moves the PC and keeps
+//! going.
+//!
+//! Each ROM is generated from its own seed. Set CHIP8_FUZZ_SEED to change the base seed and
+//! CHIP8_FUZZ_ITERATIONS to run more ROMs, a failing ROM reports the seed that reproduces it.
+use chip8_core::cpu::{Cpu, Platform};
+use chip8_core::memory::{MEMORY_SIZE, Memory, PROGRAM_ADDRESS, SCREEN_SIZE};
+use chip8_core::quirks::Quirks;
+use chip8_core::rand::Rand;
+
+/// The bytes of machine memory chip-8-rust runs ROMs with
+const MACHINE_MEMORY_SIZE: usize = 0x1000;
+
+const DEFAULT_SEED: u32 = 0x5eed_c8c8;
+const DEFAULT_ITERATIONS: usize = 128;
+
+/// Instructions executed per ROM
+const STEPS: usize = 4096;
+
+/// The largest ROM generated, enough for a few hundred instructions
+const MAX_ROM_SIZE: usize = 512;
+
+fn env_or<T: core::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+/// A random ROM and the machine configuration to run it under
+#[derive(Debug)]
+struct Case {
+    platform: Platform,
+    quirks: Quirks,
+    memory_size: usize,
+    rom: Vec<u8>,
+}
+
+impl Case {
+    fn generate(rng: &mut Rand) -> Self {
+        let platform = match rng.next_u32() % 3 {
+            0 => Platform::Chip8,
+            1 => Platform::SuperChip,
+            _ => Platform::XoChip,
+        };
+        let quirks = Quirks::from_bits(rng.next_u32() as u8);
+
+        // Mostly the size used on the board, sometimes the full address space so that XO-CHIP
+        // addresses wrap at 16 bits instead of 12
+        let memory_size = if rng.next_u32().is_multiple_of(4) {
+            MEMORY_SIZE
+        } else {
+            MACHINE_MEMORY_SIZE
+        };
+
+        let len = 2 + (rng.next_u32() as usize % (MAX_ROM_SIZE / 2)) * 2;
+        let mut rom = Vec::with_capacity(len);
+        while rom.len() < len {
+            rom.extend(Self::opcode(rng, len).to_be_bytes());
+        }
+
+        Self {
+            platform,
+            quirks,
+            memory_size,
+            rom,
+        }
+    }
+
+    /// Random words are mostly invalid, so most opcodes are built from a random first nibble with
+    /// jump and call targets and I pointing back into the ROM to keep it running
+    fn opcode(rng: &mut Rand, len: usize) -> u16 {
+        let word = rng.next_u32();
+        match word % 8 {
+            0 => (word >> 16) as u16,
+            1 => {
+                let nibble = [0x1000, 0x2000, 0xA000, 0xB000][(word >> 8) as usize % 4];
+                let target = PROGRAM_ADDRESS + (word >> 16) as usize % len;
+                nibble | target as u16 & 0x0FFF
+            }
+            2 => [0x00E0, 0x00EE, 0x00FB, 0x00FC, 0x00FD, 0x00FE, 0x00FF][(word >> 8) as usize % 7],
+            _ => {
+                let nibble = (word >> 12) as u16 & 0xF000;
+                nibble | (word >> 20) as u16 & 0x0FFF
+            }
+        }
+    }
+
+    /// Run the case on backing, which must be memory_size bytes, and give it back afterwards
+    fn run(&self, backing: &'static mut [u8], rng: &mut Rand) -> &'static mut [u8] {
+        backing.fill(0);
+        let mut frame_buffer = Box::new([0; SCREEN_SIZE]);
+        // The framebuffer is dropped after memory
+        let mut memory =
+            unsafe { Memory::new(backing, self.platform, self.quirks.font, &mut *frame_buffer) };
+        for (offset, byte) in self.rom.iter().enumerate() {
+            memory.set(PROGRAM_ADDRESS + offset, *byte);
+        }
+
+        let mut cpu = Cpu::new(self.platform, self.quirks);
+        for _ in 0..STEPS {
+            // Press or release a random key every so often, resolving FX0A as Machine::set_key
+            if rng.next_u32().is_multiple_of(16) {
+                let key = rng.next_u32() as usize % cpu.registers.keys.len();
+                let pressed = !cpu.registers.keys[key];
+                if pressed && let Some(register) = cpu.registers.wait_for_key.take() {
+                    cpu.registers.v[register] = key as u8;
+                }
+                cpu.registers.keys[key] = pressed;
+            }
+
+            if cpu.registers.wait_for_key.is_some() {
+                continue;
+            }
+
+            // Resume at a random instruction in the ROM after a fault or 00FD
+            if cpu.step(&mut memory).is_err() || cpu.registers.halted {
+                let offset = (rng.next_u32() as usize % self.rom.len()) & !1;
+                cpu.registers.pc = (PROGRAM_ADDRESS + offset) as u16;
+                cpu.registers.halted = false;
+            }
+
+            assert!(
+                cpu.registers.stack_idx <= cpu.registers.stack.len(),
+                "stack grew to {} bytes",
+                cpu.registers.stack_idx
+            );
+        }
+
+        memory.into_memory()
+    }
+}
+
+/// Prints how to reproduce a case if it panics
+struct Report<'a> {
+    iteration: usize,
+    seed: u32,
+    case: &'a Case,
+}
+
+impl Drop for Report<'_> {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            eprintln!(
+                "ROM {} from seed {:#010x} failed: {:?}",
+                self.iteration, self.seed, self.case
+            );
+        }
+    }
+}
+
+#[test]
+fn random_roms() {
+    let base_seed: u32 = env_or("CHIP8_FUZZ_SEED", DEFAULT_SEED);
+    let iterations: usize = env_or("CHIP8_FUZZ_ITERATIONS", DEFAULT_ITERATIONS);
+
+    // Memory lives for the rest of the program, so the two sizes are allocated once and reused
+    let mut small = Some(Box::leak(vec![0; MACHINE_MEMORY_SIZE].into_boxed_slice()));
+    let mut large = Some(Box::leak(vec![0; MEMORY_SIZE].into_boxed_slice()));
+
+    for iteration in 0..iterations {
+        // xorshift never leaves a zero state, so keep the low bit set
+        let seed = base_seed.wrapping_add((iteration as u32).wrapping_mul(0x9e37_79b9)) | 1;
+        let mut rng = Rand::from_state(seed);
+        let case = Case::generate(&mut rng);
+
+        let slot = if case.memory_size == MEMORY_SIZE {
+            &mut large
+        } else {
+            &mut small
+        };
+
+        let _report = Report {
+            iteration,
+            seed,
+            case: &case,
+        };
+        *slot = Some(case.run(slot.take().unwrap(), &mut rng));
+    }
+}
@@ -0,0 +1,72 @@
+//! Check what happens past the end of backing memory. chip-8-rust backs XO-CHIP with the same 4K
+//! of memory as the other platforms, so XO-CHIP accesses past it fault rather than wrapping onto
+//! the program.
+use chip8_core::cpu::{Platform, StepOutcome};
+use chip8_core::fault::{Fault, FaultKind};
+use chip8_core::machine::Machine;
+use chip8_core::memory::{MEMORY_SIZE, PROGRAM_ADDRESS, SCREEN_SIZE};
+use chip8_core::quirks::Quirks;
+
+/// The bytes of machine memory chip-8-rust runs ROMs with
+const MACHINE_MEMORY_SIZE: usize = 0x1000;
+
+/// Store 0x42 at 0x1234 and 0x1235 with XO-CHIP's 16-bit LD I
+const PROGRAM: [u16; 5] = [
+    0x6042, // LD V0, 0x42
+    0x6142, // LD V1, 0x42
+    0xF000, 0x1234, // LD I, 0x1234
+    0xF155, // LD [I], V1
+];
+
+/// Run PROGRAM as XO-CHIP on memory_size bytes of memory, returning the machine and the outcome
+/// of the store
+fn run(memory_size: usize) -> (Machine, Result<StepOutcome, Fault>) {
+    let memory = Box::leak(vec![0; memory_size].into_boxed_slice());
+    for (index, word) in PROGRAM.iter().enumerate() {
+        memory[PROGRAM_ADDRESS + 2 * index..][..2].copy_from_slice(&word.to_be_bytes());
+    }
+    // The framebuffer is leaked so it outlives the machine
+    let frame_buffer = Box::leak(Box::new([0; SCREEN_SIZE]));
+    let mut machine =
+        unsafe { Machine::new(memory, frame_buffer, Platform::XoChip, Quirks::XO_CHIP) };
+    for _ in 0..3 {
+        assert_eq!(machine.step().unwrap(), StepOutcome::Executed);
+    }
+    let store = machine.step();
+    (machine, store)
+}
+
+#[test]
+fn xo_chip_faults_past_backing_memory() {
+    let (machine, store) = run(MACHINE_MEMORY_SIZE);
+    assert_eq!(
+        store.map_err(|fault| fault.kind),
+        Err(FaultKind::MemoryOutOfRange { address: 0x1234 })
+    );
+    assert_eq!(machine.memory.get(0x234), 0);
+    assert_eq!(machine.memory.get(0x235), 0);
+    assert_eq!(machine.memory.check_range(0xFFF, 1), Ok(()));
+    assert!(machine.memory.check_range(0xFFF, 2).is_err());
+    assert!(machine.memory.check_range(0xFFFF, 2).is_err());
+
+    // With the full address space behind it the store lands above 0x1000 and leaves 0x234 alone
+    let (machine, store) = run(MEMORY_SIZE);
+    assert_eq!(store.unwrap(), StepOutcome::Executed);
+    assert_eq!(machine.memory.get(0x1234), 0x42);
+    assert_eq!(machine.memory.get(0x1235), 0x42);
+    assert_eq!(machine.memory.get(0x234), 0);
+}
+
+#[test]
+fn chip8_wraps_at_4k() {
+    let memory = Box::leak(vec![0; MACHINE_MEMORY_SIZE].into_boxed_slice());
+    // The framebuffer is leaked so it outlives the machine
+    let frame_buffer = Box::leak(Box::new([0; SCREEN_SIZE]));
+    let mut machine =
+        unsafe { Machine::new(memory, frame_buffer, Platform::Chip8, Quirks::SUPER_CHIP) };
+
+    // The whole address space is backed, so every range is in range and wraps
+    assert_eq!(machine.memory.check_range(0xFFF, 2), Ok(()));
+    machine.memory.set(0x1234, 0x42);
+    assert_eq!(machine.memory.get(0x234), 0x42);
+}
@@ -0,0 +1,27 @@
+//! Check that disassembly shows the operands the CPU actually uses under each set of quirks.
+use chip8_core::opcode::decode;
+use chip8_core::quirks::Quirks;
+
+#[test]
+fn jump_offset_shows_the_register_used() {
+    let opcode = decode(0xB234);
+    assert_eq!(opcode.to_string(), "JP V0, 0x234");
+    assert_eq!(
+        opcode.disassemble(Quirks::COSMAC_VIP).to_string(),
+        "JP V0, 0x234"
+    );
+    assert_eq!(
+        opcode.disassemble(Quirks::SUPER_CHIP).to_string(),
+        "JP V2, 0x234"
+    );
+}
+
+#[test]
+fn other_opcodes_do_not_depend_on_quirks() {
+    for word in [0x00E0, 0x6A42, 0x8126, 0xD125, 0xF355] {
+        let opcode = decode(word);
+        for quirks in [Quirks::COSMAC_VIP, Quirks::SUPER_CHIP, Quirks::XO_CHIP] {
+            assert_eq!(opcode.disassemble(quirks).to_string(), opcode.to_string());
+        }
+    }
+}
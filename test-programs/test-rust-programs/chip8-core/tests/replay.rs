@@ -0,0 +1,126 @@
+//! Record a session of a bundled ROM and replay it, checking that the replay draws the same
+//! framebuffer as the recorded session on every frame.
+use chip8_core::cpu::Platform;
+use chip8_core::machine::Machine;
+use chip8_core::memory::{PROGRAM_ADDRESS, SCREEN_SIZE};
+use chip8_core::quirks::Quirks;
+use chip8_core::replay::{self, Recorder, Recording, RecordingError, Replay};
+
+/// The bytes of machine memory chip-8-rust runs ROMs with
+const MACHINE_MEMORY_SIZE: usize = 0x1000;
+
+/// The default CPU speed of 600 instructions per second
+const INSTRUCTIONS_PER_FRAME: usize = 10;
+
+const ROM: &[u8] = include_bytes!("../../chip-8-rust/roms/pong.ch8");
+const FRAMES: u32 = 900;
+
+/// Create a machine running ROM with its own framebuffer, started from seed. The trailing zero
+/// padding of the ROM file is dropped as chip-8-rust does.
+fn machine(seed: u32) -> (Machine, Box<[u8; SCREEN_SIZE]>) {
+    let memory = Box::leak(vec![0; MACHINE_MEMORY_SIZE].into_boxed_slice());
+    let len = ROM
+        .iter()
+        .rposition(|byte| *byte != 0)
+        .map_or(0, |last| last + 1);
+    memory[PROGRAM_ADDRESS..PROGRAM_ADDRESS + len].copy_from_slice(&ROM[..len]);
+    let mut frame_buffer = Box::new([0; SCREEN_SIZE]);
+    // The framebuffer is returned alongside the machine
+    let mut machine = unsafe {
+        Machine::new(
+            memory,
+            &mut *frame_buffer,
+            Platform::Chip8,
+            Quirks::SUPER_CHIP,
+        )
+    };
+    machine.seed(seed);
+    (machine, frame_buffer)
+}
+
+/// Run a session from seed with input changing the keys once in a while, returning the recording
+/// and the framebuffer after every frame
+fn record(seed: u32) -> (Vec<u8>, Vec<[u8; SCREEN_SIZE]>) {
+    let (mut machine, frame_buffer) = machine(seed);
+    let mut recorder = Recorder::new();
+    let mut recording = replay::header(seed).to_vec();
+    let mut frames = Vec::new();
+
+    for frame in 0..FRAMES {
+        // Move the paddles up and down and let go, changing keys on three frames in a row every
+        // so often
+        if frame % 37 < 3 {
+            let keys = [1 << 0x1, 1 << 0x4 | 1 << 0xC, 1 << 0xD, 0]
+                [(frame / 37 + frame % 37) as usize % 4];
+            machine.set_keys(keys);
+        }
+
+        recorder.record(&machine, |event| {
+            recording.extend(event.to_bytes());
+        });
+        machine.run_frame(INSTRUCTIONS_PER_FRAME).unwrap();
+        frames.push(*frame_buffer);
+    }
+
+    (recording, frames)
+}
+
+#[test]
+fn replay_matches_recording() {
+    let (image, recorded) = record(0x1234_5678);
+    let recording = Recording::parse(&image).unwrap();
+    assert_eq!(recording.seed, 0x1234_5678);
+    assert!(recording.events().count() > 10);
+
+    let (mut machine, frame_buffer) = machine(recording.seed);
+    let mut replay = Replay::new();
+    for (frame, expected) in recorded.iter().enumerate() {
+        replay.apply(&recording, &mut machine);
+        machine.run_frame(INSTRUCTIONS_PER_FRAME).unwrap();
+        assert!(
+            *frame_buffer == *expected,
+            "replay drew a different framebuffer in frame {frame}"
+        );
+    }
+    assert!(replay.finished(&recording));
+}
+
+#[test]
+fn seed_changes_session() {
+    let (_, first) = record(1);
+    let (_, second) = record(2);
+    assert_ne!(first, second);
+
+    // xorshift would stall at zero, so a zero seed runs as the default seed
+    let (_, zero) = record(0);
+    let (_, default) = record(chip8_core::rand::DEFAULT_SEED);
+    assert_eq!(zero, default);
+}
+
+#[test]
+fn bad_recordings() {
+    let mut image = replay::header(1).to_vec();
+    image.extend([0, 0, 0, 0, 0x10, 1]);
+    assert_eq!(
+        Recording::parse(&image).unwrap_err(),
+        RecordingError::Invalid
+    );
+
+    image.truncate(replay::HEADER_SIZE + 3);
+    assert_eq!(
+        Recording::parse(&image).unwrap_err(),
+        RecordingError::Truncated
+    );
+
+    image[4] = 2;
+    assert_eq!(
+        Recording::parse(&image).unwrap_err(),
+        RecordingError::UnsupportedVersion(2)
+    );
+
+    image[0] = b'X';
+    assert_eq!(
+        Recording::parse(&image).unwrap_err(),
+        RecordingError::BadMagic
+    );
+}
@@ -0,0 +1,153 @@
+//! Run a bundled ROM with rewind recording it, then step back and check that the registers,
+//! memory and framebuffer are exactly those the machine had on the frame rewind stepped back to.
+use chip8_core::cpu::Platform;
+use chip8_core::machine::Machine;
+use chip8_core::memory::{PROGRAM_ADDRESS, SCREEN_SIZE};
+use chip8_core::quirks::Quirks;
+use chip8_core::rewind::Rewind;
+
+/// The bytes of machine memory, rewind budget and record interval chip-8-rust runs with
+const MACHINE_MEMORY_SIZE: usize = 0x1000;
+const REWIND_BUDGET: usize = 0x3800;
+const REWIND_INTERVAL_FRAMES: u32 = 10;
+
+/// The default CPU speed of 600 instructions per second
+const INSTRUCTIONS_PER_FRAME: usize = 10;
+
+const ROM: &[u8] = include_bytes!("../../chip-8-rust/roms/pong.ch8");
+const FRAMES: u32 = 600;
+
+/// Everything rewind restores
+#[derive(Debug, PartialEq, Eq)]
+struct State {
+    v: [u8; 16],
+    pc: u16,
+    i: u16,
+    delay: u8,
+    sound: u8,
+    stack: Vec<u8>,
+    rng: u32,
+    memory: Vec<u8>,
+    frame_buffer: Vec<u8>,
+}
+
+impl State {
+    fn of(machine: &Machine, frame_buffer: &[u8; SCREEN_SIZE]) -> Self {
+        let registers = &machine.cpu.registers;
+        Self {
+            v: registers.v,
+            pc: registers.pc,
+            i: registers.i,
+            delay: registers.delay,
+            sound: registers.sound,
+            stack: registers.stack[..registers.stack_idx].to_vec(),
+            rng: registers.rng_state(),
+            memory: (0..machine.memory.size())
+                .map(|address| machine.memory.get(address))
+                .collect(),
+            frame_buffer: frame_buffer.to_vec(),
+        }
+    }
+}
+
+/// Create a machine running ROM with a leaked framebuffer, so that the framebuffer can be read
+/// while the machine is borrowed mutably. The trailing zero padding of the ROM file is dropped as
+/// chip-8-rust does.
+fn machine() -> (Machine, &'static [u8; SCREEN_SIZE]) {
+    let memory = Box::leak(vec![0; MACHINE_MEMORY_SIZE].into_boxed_slice());
+    let len = ROM
+        .iter()
+        .rposition(|byte| *byte != 0)
+        .map_or(0, |last| last + 1);
+    memory[PROGRAM_ADDRESS..PROGRAM_ADDRESS + len].copy_from_slice(&ROM[..len]);
+    let frame_buffer: &'static mut [u8; SCREEN_SIZE] = Box::leak(Box::new([0; SCREEN_SIZE]));
+    let frame_buffer = frame_buffer as *mut [u8; SCREEN_SIZE];
+    // The framebuffer is leaked so it outlives the machine
+    let machine =
+        unsafe { Machine::new(memory, frame_buffer, Platform::Chip8, Quirks::SUPER_CHIP) };
+    (machine, unsafe { &*frame_buffer })
+}
+
+/// The keys held on a frame, moving the paddles up and down every so often
+fn keys(frame: u32) -> u16 {
+    [0, 1 << 0x1, 1 << 0x4, 1 << 0xC | 1 << 0xD][(frame / 23) as usize % 4]
+}
+
+/// Run frame, the frame numbered as rewind numbers them
+fn run(machine: &mut Machine, rewind: &mut Rewind, frame: u32) {
+    machine.set_keys(keys(frame));
+    machine.run_frame(INSTRUCTIONS_PER_FRAME).unwrap();
+    rewind.frame(machine);
+}
+
+#[test]
+fn step_back_restores_recorded_frames() {
+    let (mut machine, frame_buffer) = machine();
+    let storage = Box::leak(vec![0; REWIND_BUDGET].into_boxed_slice());
+    let mut rewind = Rewind::new(storage, &mut machine, REWIND_INTERVAL_FRAMES).unwrap();
+
+    // states[n] is the machine at the end of frame n
+    let mut states = vec![State::of(&machine, frame_buffer)];
+    for frame in 1..=FRAMES {
+        run(&mut machine, &mut rewind, frame);
+        states.push(State::of(&machine, frame_buffer));
+    }
+    assert_eq!(rewind.current_frame(), FRAMES);
+
+    for frames in [1, 25, 10, 3] {
+        let start = rewind.current_frame();
+        let stepped = rewind.step_back_frames(&mut machine, frames);
+        assert!(
+            (frames..frames + REWIND_INTERVAL_FRAMES).contains(&stepped),
+            "asked for {frames} frames, stepped back {stepped}"
+        );
+        assert_eq!(rewind.current_frame(), start - stepped);
+        assert!(
+            State::of(&machine, frame_buffer) == states[rewind.current_frame() as usize],
+            "state differs after stepping back to frame {}",
+            rewind.current_frame()
+        );
+    }
+
+    // Running forward again with the same input reaches the same states
+    let landed = rewind.current_frame();
+    for frame in landed + 1..=landed + 50 {
+        run(&mut machine, &mut rewind, frame);
+        assert!(
+            State::of(&machine, frame_buffer) == states[frame as usize],
+            "state differs running forward on frame {frame}"
+        );
+    }
+
+    // Stepping back past the start of the history stops at the oldest record
+    let start = rewind.current_frame();
+    let stepped = rewind.step_back_frames(&mut machine, u32::MAX);
+    assert!(stepped > 0 && stepped <= start);
+    assert!(State::of(&machine, frame_buffer) == states[rewind.current_frame() as usize]);
+    assert_eq!(rewind.step_back_frames(&mut machine, 1), 0);
+}
+
+#[test]
+fn step_back_clears_a_fault() {
+    let (mut machine, frame_buffer) = machine();
+    let storage = Box::leak(vec![0; REWIND_BUDGET].into_boxed_slice());
+    let mut rewind = Rewind::new(storage, &mut machine, REWIND_INTERVAL_FRAMES).unwrap();
+
+    for frame in 1..=100 {
+        run(&mut machine, &mut rewind, frame);
+    }
+    let recorded = State::of(&machine, frame_buffer);
+
+    // Return with the stack empty, from code written over the ROM's data
+    machine.memory.set(0xE00, 0x00);
+    machine.memory.set(0xE01, 0xEE);
+    machine.cpu.registers.pc = 0xE00;
+    machine.cpu.registers.stack_idx = 0;
+    assert!(machine.run_frame(INSTRUCTIONS_PER_FRAME).is_err());
+    assert!(machine.fault.is_some());
+
+    assert_eq!(rewind.step_back_frames(&mut machine, 0), 0);
+    assert!(rewind.step_back(&mut machine));
+    assert!(machine.fault.is_none());
+    assert!(State::of(&machine, frame_buffer) == recorded);
+}
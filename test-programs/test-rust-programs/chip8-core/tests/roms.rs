@@ -0,0 +1,146 @@
+//! Run the ROMs bundled into chip-8-rust for a fixed number of frames with scripted input and
+//! compare a hash of the framebuffer against a golden value. The interpreter and the random number
+//! generator are deterministic, so a changed hash means the machine now draws something different.
+//! If the change is intended, check the rendered screen in the failure message and update the hash.
+use chip8_core::cpu::Platform;
+use chip8_core::machine::Machine;
+use chip8_core::memory::{PROGRAM_ADDRESS, SCREEN_HEIGHT, SCREEN_SIZE, SCREEN_WIDTH};
+use chip8_core::quirks::Quirks;
+
+/// The bytes of machine memory chip-8-rust runs ROMs with
+const MACHINE_MEMORY_SIZE: usize = 0x1000;
+
+/// The default CPU speed of 600 instructions per second
+const INSTRUCTIONS_PER_FRAME: usize = 10;
+
+/// A machine along with the host framebuffer backing it
+struct Harness {
+    machine: Machine,
+    frame_buffer: Box<[u8; SCREEN_SIZE]>,
+}
+
+impl Harness {
+    /// Load a raw ROM as chip-8-rust does, dropping the trailing zero padding of the ROM files
+    fn new(rom: &[u8], platform: Platform) -> Self {
+        let memory = Box::leak(vec![0; MACHINE_MEMORY_SIZE].into_boxed_slice());
+        let mut frame_buffer = Box::new([0; SCREEN_SIZE]);
+        // The harness keeps the framebuffer alongside the machine
+        let mut machine =
+            unsafe { Machine::new(memory, &mut *frame_buffer, platform, Quirks::SUPER_CHIP) };
+
+        let len = rom
+            .iter()
+            .rposition(|byte| *byte != 0)
+            .map_or(0, |last| last + 1);
+        for (offset, byte) in rom[..len].iter().enumerate() {
+            machine.memory.set(PROGRAM_ADDRESS + offset, *byte);
+        }
+
+        Self {
+            machine,
+            frame_buffer,
+        }
+    }
+
+    /// Run frames frames. input holds the keypad state to switch to at the start of a frame, as
+    /// the frame number and a bitmask of held keys, in frame order.
+    fn run(&mut self, frames: usize, input: &[(usize, u16)]) {
+        let mut input = input.iter().peekable();
+        for frame in 0..frames {
+            if let Some((_, keys)) = input.next_if(|(at, _)| *at == frame) {
+                self.machine.set_keys(*keys);
+            }
+
+            if let Err(fault) = self.machine.run_frame(INSTRUCTIONS_PER_FRAME) {
+                panic!("faulted in frame {frame}: {fault:?}");
+            }
+        }
+    }
+
+    /// The FNV-1a hash of the framebuffer
+    fn hash(&self) -> u32 {
+        self.frame_buffer
+            .iter()
+            .fold(0x811c_9dc5, |hash: u32, byte| {
+                (hash ^ *byte as u32).wrapping_mul(0x0100_0193)
+            })
+    }
+
+    /// The framebuffer as text, one line per row
+    fn render(&self) -> String {
+        let mut screen = String::new();
+        for y in 0..SCREEN_HEIGHT {
+            for x in 0..SCREEN_WIDTH {
+                let index = y * SCREEN_WIDTH + x;
+                let lit = self.frame_buffer[index >> 3] & (1 << (index & 0b111)) != 0;
+                screen.push(if lit { '#' } else { '.' });
+            }
+            screen.push('\n');
+        }
+        screen
+    }
+
+    fn check(&self, golden: u32) {
+        let hash = self.hash();
+        assert!(
+            hash == golden,
+            "framebuffer hash {hash:#010x} does not match golden {golden:#010x}\n{}",
+            self.render()
+        );
+    }
+}
+
+const fn key(key: u8) -> u16 {
+    1 << key
+}
+
+#[test]
+fn pong() {
+    let mut harness = Harness::new(
+        include_bytes!("../../chip-8-rust/roms/pong.ch8"),
+        Platform::Chip8,
+    );
+    // Move the left paddle up, then the right paddle down
+    harness.run(600, &[(60, key(0x1)), (120, 0), (180, key(0xD)), (240, 0)]);
+    harness.check(0x234f_2e65);
+}
+
+#[test]
+fn cave() {
+    let mut harness = Harness::new(
+        include_bytes!("../../chip-8-rust/roms/cave.ch8"),
+        Platform::Chip8,
+    );
+    // Start the game, then walk right and down
+    harness.run(
+        600,
+        &[
+            (60, key(0xF)),
+            (70, 0),
+            (120, key(0x6)),
+            (240, key(0x8)),
+            (360, 0),
+        ],
+    );
+    harness.check(0xe3dd_da05);
+}
+
+#[test]
+fn space_invaders() {
+    let mut harness = Harness::new(
+        include_bytes!("../../chip-8-rust/roms/space_invaders.ch8"),
+        Platform::SuperChip,
+    );
+    // Start the game, then move left firing and move right
+    harness.run(
+        900,
+        &[
+            (60, key(0x5)),
+            (70, 0),
+            (300, key(0x4) | key(0x5)),
+            (420, key(0x6)),
+            (540, 0),
+        ],
+    );
+    harness.check(0xac1d_ebcd);
+}
@@ -0,0 +1,179 @@
+//! Save a machine part way through a bundled ROM, restore it into fresh memory and check that the
+//! restored machine matches and runs on exactly as the original does.
+use chip8_core::cpu::Platform;
+use chip8_core::machine::Machine;
+use chip8_core::memory::{PROGRAM_ADDRESS, SCREEN_SIZE};
+use chip8_core::quirks::Quirks;
+use chip8_core::snapshot::{self, SNAPSHOT_MAGIC, SNAPSHOT_VERSION, SnapshotError};
+
+/// The bytes of machine memory chip-8-rust runs ROMs with
+const MACHINE_MEMORY_SIZE: usize = 0x1000;
+
+/// The default CPU speed of 600 instructions per second
+const INSTRUCTIONS_PER_FRAME: usize = 10;
+
+const ROM: &[u8] = include_bytes!("../../chip-8-rust/roms/pong.ch8");
+
+/// The offset of the version byte and the size of the fields before the stack, see the layout in
+/// chip8_core::snapshot
+const VERSION_OFFSET: usize = 4;
+const FIXED_SIZE: usize = 61;
+
+/// Leak a framebuffer so that it can be read while the machine using it is borrowed mutably
+fn frame_buffer() -> &'static mut [u8; SCREEN_SIZE] {
+    Box::leak(Box::new([0; SCREEN_SIZE]))
+}
+
+fn memory(size: usize) -> &'static mut [u8] {
+    Box::leak(vec![0; size].into_boxed_slice())
+}
+
+/// Create a machine running ROM and run it for frames frames with the paddles moving. The
+/// trailing zero padding of the ROM file is dropped as chip-8-rust does.
+fn run(frames: u32) -> (Machine, &'static [u8; SCREEN_SIZE]) {
+    let memory = memory(MACHINE_MEMORY_SIZE);
+    let len = ROM
+        .iter()
+        .rposition(|byte| *byte != 0)
+        .map_or(0, |last| last + 1);
+    memory[PROGRAM_ADDRESS..PROGRAM_ADDRESS + len].copy_from_slice(&ROM[..len]);
+    let frame_buffer: *mut [u8; SCREEN_SIZE] = frame_buffer();
+    // The framebuffer is leaked so it outlives the machine
+    let mut machine =
+        unsafe { Machine::new(memory, frame_buffer, Platform::Chip8, Quirks::SUPER_CHIP) };
+    machine.seed(0x5EED);
+
+    for frame in 0..frames {
+        machine.set_keys([0, 1 << 0x1, 1 << 0x4 | 1 << 0xD][(frame / 19) as usize % 3]);
+        machine.run_frame(INSTRUCTIONS_PER_FRAME).unwrap();
+    }
+
+    (machine, unsafe { &*frame_buffer })
+}
+
+fn save(machine: &Machine) -> Vec<u8> {
+    let mut image = Vec::new();
+    snapshot::save(machine, |bytes| image.extend_from_slice(bytes));
+    image
+}
+
+fn restore(image: &[u8], memory_size: usize) -> Result<Machine, SnapshotError> {
+    // The framebuffer is leaked so it outlives the machine
+    unsafe { snapshot::restore(image, memory(memory_size), frame_buffer()) }
+}
+
+/// The registers and memory of a machine as text, so that mismatches are readable
+fn state(machine: &Machine) -> String {
+    let memory: Vec<u8> = (0..machine.memory.size())
+        .map(|address| machine.memory.get(address))
+        .collect();
+    format!(
+        "{:?} vip_timing {} cycles {} rng {:x} hires {} planes {} memory {:?}",
+        machine.cpu.registers,
+        machine.cpu.vip_timing,
+        machine.cpu.cycles,
+        machine.cpu.registers.rng_state(),
+        machine.memory.hires(),
+        machine.memory.planes(),
+        memory
+    )
+}
+
+#[test]
+fn round_trip() {
+    let (mut machine, frame_buffer) = run(300);
+    machine.cpu.vip_timing = true;
+    let image = save(&machine);
+
+    let mut restored = restore(&image, MACHINE_MEMORY_SIZE).unwrap();
+    let restored_frame_buffer = unsafe { &*restored.memory.frame_buffer };
+    assert_eq!(state(&restored), state(&machine));
+    assert_eq!(restored_frame_buffer, frame_buffer);
+    assert_eq!(save(&restored), image);
+
+    for frame in 0..300 {
+        machine.run_frame(INSTRUCTIONS_PER_FRAME).unwrap();
+        restored.run_frame(INSTRUCTIONS_PER_FRAME).unwrap();
+        assert!(
+            restored_frame_buffer == frame_buffer,
+            "framebuffers differ {frame} frames after restoring"
+        );
+    }
+    assert_eq!(state(&restored), state(&machine));
+}
+
+#[test]
+fn layout_matches_version() {
+    // The fixed fields are where the documented layout puts them. A change to the layout has to
+    // bump SNAPSHOT_VERSION, and update this test.
+    let (machine, _) = run(60);
+    let image = save(&machine);
+    let registers = &machine.cpu.registers;
+    let stack_idx = registers.stack_idx;
+
+    assert_eq!(&image[..4], &SNAPSHOT_MAGIC);
+    assert_eq!(image[VERSION_OFFSET], SNAPSHOT_VERSION);
+    assert_eq!(SNAPSHOT_VERSION, 2);
+    assert_eq!(image[5], Platform::Chip8.to_u8());
+    assert_eq!(image[6], Quirks::SUPER_CHIP.to_bits());
+    assert_eq!(&image[10..26], &registers.v);
+    assert_eq!(&image[26..28], &registers.pc.to_le_bytes());
+    assert_eq!(&image[28..30], &registers.i.to_le_bytes());
+    assert_eq!(
+        &image[FIXED_SIZE - 2..FIXED_SIZE],
+        &(stack_idx as u16).to_le_bytes()
+    );
+    let memory = FIXED_SIZE + stack_idx;
+    assert_eq!(
+        &image[memory..memory + 4],
+        &(MACHINE_MEMORY_SIZE as u32).to_le_bytes()
+    );
+    assert_eq!(image.len(), memory + 4 + MACHINE_MEMORY_SIZE + SCREEN_SIZE);
+}
+
+#[test]
+fn other_versions_are_rejected() {
+    let (machine, _) = run(60);
+    let image = save(&machine);
+
+    // Version 1 images have the font at 0x000, restoring one would leave the font in the wrong
+    // place, and a newer image may have fields this version does not know about
+    for version in [1, SNAPSHOT_VERSION + 1] {
+        let mut other = image.clone();
+        other[VERSION_OFFSET] = version;
+        assert_eq!(
+            restore(&other, MACHINE_MEMORY_SIZE).err(),
+            Some(SnapshotError::UnsupportedVersion(version))
+        );
+    }
+}
+
+#[test]
+fn bad_images_are_rejected() {
+    let (machine, _) = run(60);
+    let image = save(&machine);
+
+    let mut bad_magic = image.clone();
+    bad_magic[0] = b'X';
+    assert_eq!(
+        restore(&bad_magic, MACHINE_MEMORY_SIZE).err(),
+        Some(SnapshotError::BadMagic)
+    );
+
+    assert_eq!(
+        restore(&image[..image.len() - 1], MACHINE_MEMORY_SIZE).err(),
+        Some(SnapshotError::Truncated)
+    );
+
+    assert_eq!(
+        restore(&image, MACHINE_MEMORY_SIZE / 2).err(),
+        Some(SnapshotError::MemoryTooLarge)
+    );
+
+    let mut bad_platform = image.clone();
+    bad_platform[5] = 3;
+    assert_eq!(
+        restore(&bad_platform, MACHINE_MEMORY_SIZE).err(),
+        Some(SnapshotError::Invalid)
+    );
+}
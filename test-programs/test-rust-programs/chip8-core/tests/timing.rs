@@ -0,0 +1,101 @@
+//! Check the COSMAC VIP timing mode: each instruction is charged its VIP machine cycle cost and a
+//! frame ends when the machine draws, as the VIP interpreter waited for the display interrupt.
+use chip8_core::cpu::{Platform, StepOutcome};
+use chip8_core::cycles::VIP_INTERPRETER_CYCLES_PER_FRAME;
+use chip8_core::machine::Machine;
+use chip8_core::memory::{PROGRAM_ADDRESS, SCREEN_SIZE};
+use chip8_core::quirks::Quirks;
+
+/// The bytes of machine memory chip-8-rust runs ROMs with
+const MACHINE_MEMORY_SIZE: usize = 0x1000;
+
+/// Set up a sprite, take a skip, draw and then loop forever
+const PROGRAM: [u16; 7] = [
+    0x6005, // LD V0, 5
+    0xA20C, // LD I, 0x20C
+    0x3005, // SE V0, 5
+    0x1206, // JP 0x206, skipped
+    0xD001, // DRW V0, V0, 1
+    0x120A, // JP 0x20A
+    0x8000, // the sprite
+];
+
+/// The cycles charged to each instruction of PROGRAM up to the loop, with the PC after it
+const CHARGED: [(u32, u16); 4] = [
+    // Every instruction costs 40 cycles to fetch and dispatch on top of executing
+    (40 + 6, 0x202),
+    (40 + 12, 0x204),
+    // A taken skip costs 4 more
+    (40 + 10 + 4, 0x208),
+    // A draw costs 22 and 46 per row
+    (40 + 22 + 46, 0x20A),
+];
+
+const LOOP_CYCLES: u32 = 40 + 12;
+
+/// Create a machine running PROGRAM, with VIP timing if vip_timing is set
+fn load(vip_timing: bool) -> Machine {
+    let memory = Box::leak(vec![0; MACHINE_MEMORY_SIZE].into_boxed_slice());
+    for (index, instruction) in PROGRAM.iter().enumerate() {
+        memory[PROGRAM_ADDRESS + 2 * index..][..2].copy_from_slice(&instruction.to_be_bytes());
+    }
+    // The framebuffer is leaked so it outlives the machine
+    let frame_buffer = Box::leak(Box::new([0; SCREEN_SIZE]));
+    let mut machine =
+        unsafe { Machine::new(memory, frame_buffer, Platform::Chip8, Quirks::COSMAC_VIP) };
+    machine.cpu.vip_timing = vip_timing;
+    machine
+}
+
+#[test]
+fn instructions_are_charged_vip_cycles() {
+    let mut machine = load(true);
+    for (cycles, pc) in CHARGED {
+        let before = machine.cpu.cycles;
+        let outcome = machine.step().unwrap();
+        assert_eq!(machine.cpu.registers.pc, pc);
+        assert_eq!(
+            machine.cpu.cycles - before,
+            cycles,
+            "instruction before {pc:#X}"
+        );
+
+        let expected = if pc == 0x20A {
+            StepOutcome::WaitingForDisplay
+        } else {
+            StepOutcome::Executed
+        };
+        assert_eq!(outcome, expected);
+    }
+
+    let before = machine.cpu.cycles;
+    machine.step().unwrap();
+    assert_eq!(machine.cpu.cycles - before, LOOP_CYCLES);
+}
+
+#[test]
+fn draw_ends_the_frame() {
+    let budget = VIP_INTERPRETER_CYCLES_PER_FRAME as usize;
+
+    let mut machine = load(true);
+    let outcome = machine.run_frame(budget).unwrap();
+    assert_eq!(outcome, StepOutcome::WaitingForDisplay);
+    assert_eq!(machine.cpu.registers.pc, 0x20A);
+    let drawn: u32 = CHARGED.iter().map(|(cycles, _)| cycles).sum();
+    assert_eq!(machine.cpu.cycles, drawn);
+
+    // The next frame spins in the loop until its cycles run out
+    let outcome = machine.run_frame(budget).unwrap();
+    assert_eq!(outcome, StepOutcome::Executed);
+    assert_eq!(
+        machine.cpu.cycles - drawn,
+        (budget as u32).div_ceil(LOOP_CYCLES) * LOOP_CYCLES
+    );
+
+    // Without VIP timing a draw does not end the frame and cycles are not counted
+    let mut untimed = load(false);
+    let outcome = untimed.run_frame(10).unwrap();
+    assert_eq!(outcome, StepOutcome::Executed);
+    assert_eq!(untimed.cpu.registers.pc, 0x20A);
+    assert_eq!(untimed.cpu.cycles, 0);
+}
@@ -0,0 +1,293 @@
+(* Host side of the CHIP-8 emulator remote debug protocol (see
+   test-programs/test-rust-programs/chip-8-rust/src/debugger.rs). Requests are
+   written into a mailbox in device RAM with a DMA packet, replies come back as
+   DMA packets starting with "DBG". *)
+open! Core
+
+let mailbox_address = 0x8800
+let mailbox_size = 64
+let mailbox_data_size = 52
+let sequence_offset = 60
+let reply_magic = "DBG"
+let register_i = 16
+let register_pc = 17
+let register_delay = 18
+let register_sound = 19
+
+module Display_mode = struct
+  type t =
+    | Direct
+    | Buffered
+    | Persist
+  [@@deriving sexp_of, enumerate]
+
+  let to_int = function
+    | Direct -> 0
+    | Buffered -> 1
+    | Persist -> 2
+  ;;
+
+  let to_string t = sexp_of_t t |> Sexp.to_string |> String.lowercase
+end
+
+module Comparison = struct
+  type t =
+    | Equal
+    | Not_equal
+    | Less
+    | Greater
+  [@@deriving sexp_of, enumerate]
+
+  let to_int = function
+    | Equal -> 0
+    | Not_equal -> 1
+    | Less -> 2
+    | Greater -> 3
+  ;;
+
+  let to_string = function
+    | Equal -> "=="
+    | Not_equal -> "!="
+    | Less -> "<"
+    | Greater -> ">"
+  ;;
+end
+
+(* A conditional register breakpoint. [register] is 0 - 15 for V0 - VF or one
+   of the register_ constants. *)
+module Condition = struct
+  type t =
+    { register : int
+    ; comparison : Comparison.t
+    ; value : int
+    }
+  [@@deriving sexp_of]
+
+  (* The register, and the value and comparison packed as the device expects *)
+  let encode t =
+    t.register, (Comparison.to_int t.comparison lsl 16) lor (t.value land 0xFFFF)
+  ;;
+end
+
+module Request = struct
+  type t =
+    | Set_breakpoint of int
+    | Clear_breakpoint of int
+    | Step
+    | Continue
+    | Halt
+    | Read_registers
+    | Write_register of
+        { register : int
+        ; value : int
+        }
+    | Read_memory of
+        { address : int
+        ; length : int
+        }
+    | Write_memory of
+        { address : int
+        ; data : string
+        }
+    | Set_watchpoint of
+        { address : int
+        ; length : int
+        }
+    | Clear_watchpoint of int
+    | Set_condition of Condition.t
+    | Clear_condition of Condition.t
+    | Set_display_mode of Display_mode.t
+    (* The seed to load ROMs with from the next load, 0 to seed from the CSRs *)
+    | Set_seed of int
+    (* Run translated code for hot blocks, for emulators built with the JIT *)
+    | Set_jit of bool
+    (* Step back at least this many frames through the rewind history *)
+    | Step_back of int
+  [@@deriving sexp_of]
+
+  let to_mailbox t ~sequence =
+    let mailbox = Bytes.make mailbox_size '\000' in
+    let set_u8 offset value =
+      Bytes.set mailbox offset (Char.of_int_exn (value land 0xFF))
+    in
+    let set_u16 offset value =
+      set_u8 offset value;
+      set_u8 (offset + 1) (value lsr 8)
+    in
+    let set_u32 offset value =
+      set_u16 offset value;
+      set_u16 (offset + 2) (value lsr 16)
+    in
+    let command, index, address, value, data =
+      match t with
+      | Set_breakpoint address -> 'B', 0, address, 0, ""
+      | Clear_breakpoint address -> 'b', 0, address, 0, ""
+      | Step -> 'S', 0, 0, 0, ""
+      | Continue -> 'C', 0, 0, 0, ""
+      | Halt -> 'H', 0, 0, 0, ""
+      | Read_registers -> 'R', 0, 0, 0, ""
+      | Write_register { register; value } -> 'W', register, 0, value, ""
+      | Read_memory { address; length } -> 'M', length, address, 0, ""
+      | Write_memory { address; data } ->
+        if String.length data > mailbox_data_size
+        then
+          raise_s
+            [%message
+              "Memory writes are limited to the mailbox data size"
+                (mailbox_data_size : int)];
+        'w', String.length data, address, 0, data
+      | Set_watchpoint { address; length } -> 'A', 0, address, length, ""
+      | Clear_watchpoint address -> 'a', 0, address, 0, ""
+      | Set_condition condition ->
+        let register, value = Condition.encode condition in
+        'X', register, 0, value, ""
+      | Clear_condition condition ->
+        let register, value = Condition.encode condition in
+        'x', register, 0, value, ""
+      | Set_display_mode mode -> 'D', 0, 0, Display_mode.to_int mode, ""
+      | Set_seed seed -> 'r', 0, 0, seed, ""
+      | Set_jit enabled -> 'J', 0, 0, Bool.to_int enabled, ""
+      | Step_back frames -> 'U', 0, 0, frames, ""
+    in
+    set_u8 0 (Char.to_int command);
+    set_u8 1 index;
+    set_u16 2 address;
+    set_u32 4 value;
+    Bytes.From_string.blit
+      ~src:data
+      ~src_pos:0
+      ~dst:mailbox
+      ~dst_pos:8
+      ~len:(String.length data);
+    set_u32 sequence_offset sequence;
+    Bytes.to_string mailbox
+  ;;
+
+  (** The bytes to send over the UART to write this request into the mailbox. *)
+  let to_dma_packet t ~sequence =
+    Opcode_helper.dma_packet ~address:mailbox_address (to_mailbox t ~sequence)
+  ;;
+end
+
+module Stop_reason = struct
+  type t =
+    | Halted
+    | Step
+    | Breakpoint
+    | Fault
+    | Exited
+    | Watchpoint
+    | Condition
+    | Unknown of int
+  [@@deriving sexp_of, equal]
+
+  let of_int = function
+    | 0 -> Halted
+    | 1 -> Step
+    | 2 -> Breakpoint
+    | 3 -> Fault
+    | 4 -> Exited
+    | 5 -> Watchpoint
+    | 6 -> Condition
+    | other -> Unknown other
+  ;;
+end
+
+module Reply = struct
+  type t =
+    | Ok of
+        { command : char
+        ; sequence : int
+        }
+    | Registers of
+        { sequence : int
+        ; v : int list
+        ; i : int
+        ; pc : int
+        }
+    | Memory of
+        { sequence : int
+        ; data : string
+        }
+    | Stopped of
+        { sequence : int
+        ; reason : Stop_reason.t
+        ; pc : int
+        }
+    (* The machine is paused [frames] frames back, which can be more than asked
+       for as it lands on a rewind record *)
+    | Stepped_back of
+        { sequence : int
+        ; frames : int
+        ; pc : int
+        }
+    | Error of
+        { sequence : int
+        ; command : char
+        }
+  [@@deriving sexp_of]
+
+  let sequence = function
+    | Ok { sequence; _ }
+    | Registers { sequence; _ }
+    | Memory { sequence; _ }
+    | Stopped { sequence; _ }
+    | Stepped_back { sequence; _ }
+    | Error { sequence; _ } -> sequence
+  ;;
+
+  (** Parse the payload of a DMA packet sent by the device. Packets that are
+      not debugger replies (log messages) return [None]. *)
+  let of_packet payload =
+    let u8 offset = Char.to_int payload.[offset] in
+    let u16 offset = u8 offset lor (u8 (offset + 1) lsl 8) in
+    let u32 offset = u16 offset lor (u16 (offset + 2) lsl 16) in
+    if String.length payload < 8 || not (String.is_prefix payload ~prefix:reply_magic)
+    then None
+    else (
+      let command = payload.[3] in
+      let sequence = u32 4 in
+      let body = String.drop_prefix payload 8 in
+      match command with
+      | 'R' when String.length body >= 20 ->
+        Some
+          (Registers
+             { sequence
+             ; v = List.init 16 ~f:(fun index -> u8 (8 + index))
+             ; i = u16 24
+             ; pc = u16 26
+             })
+      | 'M' -> Some (Memory { sequence; data = body })
+      | 'T' when String.length body >= 3 ->
+        Some (Stopped { sequence; reason = Stop_reason.of_int (u8 8); pc = u16 9 })
+      | 'U' when String.length body >= 6 ->
+        Some (Stepped_back { sequence; frames = u32 8; pc = u16 12 })
+      | 'E' when String.length body >= 1 -> Some (Error { sequence; command = body.[0] })
+      | command -> Some (Ok { command; sequence }))
+  ;;
+end
+
+(** Split bytes received from the device into DMA packet payloads. Each packet
+    is a 'D' header followed by a 16-bit big-endian length and the payload.
+    Returns the complete payloads and any trailing bytes of an incomplete
+    packet. *)
+let split_packets stream =
+  let rec loop position packets =
+    match String.index_from stream position 'D' with
+    | None -> List.rev packets, ""
+    | Some header ->
+      if header + 3 > String.length stream
+      then List.rev packets, String.drop_prefix stream header
+      else (
+        let length =
+          (Char.to_int stream.[header + 1] lsl 8) lor Char.to_int stream.[header + 2]
+        in
+        if header + 3 + length > String.length stream
+        then List.rev packets, String.drop_prefix stream header
+        else
+          loop
+            (header + 3 + length)
+            (String.sub stream ~pos:(header + 3) ~len:length :: packets))
+  in
+  loop 0 []
+;;
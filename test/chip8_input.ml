@@ -0,0 +1,93 @@
+(* Host side of chip-8-rust input recording and replay (see
+   test-programs/test-rust-programs/chip8-core/src/replay.rs for the recording
+   format and chip-8-rust/src/recording.rs for how it is sent). The emulator
+   streams the recording of the running game in DMA packets starting with
+   "INP", and replays a recording written into the replay mailbox. *)
+open! Core
+
+let magic = "INP"
+let recording_magic = "C8IN"
+let version = 1
+let header_size = 12
+let event_size = 6
+let mailbox_address = 0x8880
+let mailbox_size = 0x780
+
+(* DMA packets are sent in chunks of at most this many bytes *)
+let chunk_size = 1024
+
+let u32 data ~pos =
+  List.init 4 ~f:(fun index -> Char.to_int data.[pos + index] lsl (8 * index))
+  |> List.fold ~init:0 ~f:( lor )
+;;
+
+let u32_to_string value =
+  String.init 4 ~f:(fun index -> Char.of_int_exn ((value lsr (8 * index)) land 0xFF))
+;;
+
+module Event = struct
+  type t =
+    { frame : int
+    ; key : int
+    ; pressed : bool
+    }
+  [@@deriving sexp_of]
+end
+
+module Recording = struct
+  (* The recording image, as sent by the device and written to the mailbox *)
+  type t = string
+
+  (** Add the piece of a recording carried by a DMA packet sent by the device.
+      A header starts a new recording, as the device sends one each time it
+      loads a ROM. Packets that are not part of a recording are ignored. *)
+  let add t packet =
+    match String.chop_prefix packet ~prefix:magic with
+    | None -> t
+    | Some piece ->
+      if String.is_prefix piece ~prefix:recording_magic
+         && String.length piece = header_size
+      then piece
+      else t ^ piece
+  ;;
+
+  let seed t = u32 t ~pos:8
+
+  let events t =
+    String.drop_prefix t header_size
+    |> String.to_list
+    |> List.chunks_of ~length:event_size
+    |> List.filter ~f:(fun event -> List.length event = event_size)
+    |> List.map ~f:(fun event ->
+      let event = String.of_char_list event in
+      { Event.frame = u32 event ~pos:0
+      ; key = Char.to_int event.[4]
+      ; pressed = Char.equal event.[5] '\001'
+      })
+  ;;
+end
+
+(** The DMA writes, as addresses and data, that replay a recording: the
+    recording and its length first, then the generation that tells the
+    emulator it has arrived. Each replay needs a generation different from the
+    last. *)
+let replay_messages ~generation recording =
+  if String.length recording > mailbox_size - 8
+  then raise_s [%message "Recording too large for the mailbox"];
+  let recording_messages =
+    String.to_list recording
+    |> List.chunks_of ~length:chunk_size
+    |> List.mapi ~f:(fun index chunk ->
+      mailbox_address + 8 + (index * chunk_size), String.of_char_list chunk)
+  in
+  recording_messages
+  @ [ mailbox_address + 4, u32_to_string (String.length recording)
+    ; mailbox_address, u32_to_string generation
+    ]
+;;
+
+(** [replay_messages] as DMA packets ready to send over the UART *)
+let replay_packets ~generation recording =
+  replay_messages ~generation recording
+  |> List.map ~f:(fun (address, data) -> Opcode_helper.dma_packet ~address data)
+;;
@@ -0,0 +1,60 @@
+(* Host side of the chip-8-rust keypad (see
+   test-programs/test-rust-programs/chip-8-rust/src/keypad.rs). The host tracks
+   which of the 16 hex keys are held and writes them into device RAM as a
+   bitmask after every key up or down event. The device tells the host which
+   host keys each ROM it loads expects. *)
+open! Core
+
+let address = 0x8840
+let num_keys = 16
+
+module Keymap = struct
+  (* The host keyboard key for each keypad key 0 - F *)
+  type t = string [@@deriving sexp_of]
+
+  (* The usual layout, with the 4x4 keypad on the left of a QWERTY keyboard:
+
+     1 2 3 C      1 2 3 4
+     4 5 6 D  <-  q w e r
+     7 8 9 E      a s d f
+     A 0 B F      z x c v *)
+  let default = Chip8_rom.default_keymap
+
+  let of_string keymap =
+    let keymap = String.lowercase keymap in
+    if String.length keymap <> num_keys
+    then Or_error.error_s [%message "Keymaps must have a key for each keypad key" keymap]
+    else if List.contains_dup (String.to_list keymap) ~compare:Char.compare
+    then Or_error.error_s [%message "Keymaps cannot use a host key twice" keymap]
+    else Ok keymap
+  ;;
+
+  (** The keypad key a host key is mapped to, if any *)
+  let keypad_key t host_key = String.index t (Char.lowercase host_key)
+
+  (* The device sends the keymap of each ROM it loads as a DMA packet of this
+     followed by the keymap, see send_keymap in src/keypad.rs *)
+  let magic = "KEY"
+
+  (** The keymap carried by a DMA packet sent by the device, if it is one *)
+  let of_packet packet =
+    Option.bind (String.chop_prefix packet ~prefix:magic) ~f:(fun keymap ->
+      Or_error.ok (of_string keymap))
+  ;;
+end
+
+module State = struct
+  (* Bit n is set while key n is held *)
+  type t = int [@@deriving sexp_of, equal]
+
+  let empty = 0
+  let of_keys keys = List.fold keys ~init:empty ~f:(fun t key -> t lor (1 lsl key))
+
+  (** The bytes to write at [address] *)
+  let to_message t =
+    String.init 4 ~f:(fun index -> Char.of_int_exn ((t lsr (8 * index)) land 0xFF))
+  ;;
+
+  (** [to_message] as a DMA packet ready to send over the UART *)
+  let to_dma_packet t = Opcode_helper.dma_packet ~address (to_message t)
+end
@@ -0,0 +1,170 @@
+(* Host side of the chip-8-rust ROM container format (see
+   test-programs/test-rust-programs/chip8-core/src/container.rs) and of the
+   mailbox used to load a ROM at runtime (see chip-8-rust/src/loader.rs). *)
+open! Core
+
+let magic = "C8RM"
+let version = 1
+let header_size = 64
+let title_size = 32
+
+(* Every platform, XO-CHIP included, runs in 4K of machine memory with the
+   program loaded at 0x200 *)
+let max_program_size = 0x1000 - 0x200
+let default_keymap = "x123qweasdzc4rfv"
+let mailbox_address = 0x7000
+let mailbox_size = 0x1000
+
+(* DMA packets are sent in chunks of at most this many bytes *)
+let chunk_size = 1024
+
+module Platform = struct
+  type t =
+    | Chip8
+    | Super_chip
+    | Xo_chip
+  [@@deriving sexp_of]
+
+  let to_int = function
+    | Chip8 -> 0
+    | Super_chip -> 1
+    | Xo_chip -> 2
+  ;;
+end
+
+module Font = struct
+  type t =
+    | Super_chip
+    | Vip
+    | Dream_6800
+    | Eti_660
+  [@@deriving sexp_of]
+
+  let to_int = function
+    | Super_chip -> 0
+    | Vip -> 1
+    | Dream_6800 -> 2
+    | Eti_660 -> 3
+  ;;
+end
+
+module Quirks = struct
+  type t =
+    { shift_uses_vy : bool
+    ; load_store_increments_i : bool
+    ; jump_uses_vx : bool
+    ; clip_sprites : bool
+    ; logic_resets_vf : bool
+    ; lores_dxy0_draws_8x16 : bool
+    ; font : Font.t
+    }
+  [@@deriving sexp_of]
+
+  let super_chip =
+    { shift_uses_vy = false
+    ; load_store_increments_i = false
+    ; jump_uses_vx = true
+    ; clip_sprites = true
+    ; logic_resets_vf = false
+    ; lores_dxy0_draws_8x16 = true
+    ; font = Font.Super_chip
+    }
+  ;;
+
+  (* A bit per flag, in declaration order, then the font in the two bits above
+     and lores_dxy0_draws_8x16 in the top bit *)
+  let to_int t =
+    List.foldi
+      [ t.shift_uses_vy
+      ; t.load_store_increments_i
+      ; t.jump_uses_vx
+      ; t.clip_sprites
+      ; t.logic_resets_vf
+      ]
+      ~init:((Font.to_int t.font lsl 5) lor (Bool.to_int t.lores_dxy0_draws_8x16 lsl 7))
+      ~f:(fun bit acc set -> if set then acc lor (1 lsl bit) else acc)
+  ;;
+end
+
+(** The FNV-1a hash of data *)
+let checksum data =
+  String.fold data ~init:0x811c9dc5 ~f:(fun hash byte ->
+    ((hash lxor Char.to_int byte) * 0x01000193) land 0xFFFFFFFF)
+;;
+
+(* Bits of the container flags byte *)
+let flag_vip_timing = 1
+
+(** Wrap a raw CHIP-8 program in a ROM container. [vip_timing] runs the ROM at
+    the speed of the COSMAC VIP interpreter instead of [instructions_per_frame]. *)
+let container
+      ?(platform = Platform.Super_chip)
+      ?(quirks = Quirks.super_chip)
+      ?(instructions_per_frame = 10)
+      ?(vip_timing = false)
+      ?(keymap = default_keymap)
+      ~title
+      program
+  =
+  if String.length title > title_size
+  then raise_s [%message "Title too long" (title : string) (title_size : int)];
+  if String.length keymap <> 16 then raise_s [%message "Keymap must have 16 keys"];
+  if instructions_per_frame < 1
+  then raise_s [%message "ROMs must run at least one instruction per frame"];
+  if String.length program > max_program_size
+  then
+    raise_s
+      [%message
+        "Program too large" ~size:(String.length program : int) (max_program_size : int)];
+  let header = Bytes.make header_size '\000' in
+  let set offset value = Bytes.set header offset (Char.of_int_exn (value land 0xFF)) in
+  let set_u16 offset value =
+    set offset value;
+    set (offset + 1) (value lsr 8)
+  in
+  let set_u32 offset value =
+    set_u16 offset value;
+    set_u16 (offset + 2) (value lsr 16)
+  in
+  Bytes.From_string.blit ~src:magic ~src_pos:0 ~dst:header ~dst_pos:0 ~len:4;
+  set 4 version;
+  set 5 (Platform.to_int platform);
+  set 6 (Quirks.to_int quirks);
+  set 7 (if vip_timing then flag_vip_timing else 0);
+  set_u16 8 instructions_per_frame;
+  set_u16 10 (String.length program);
+  set_u32 12 (checksum program);
+  Bytes.From_string.blit ~src:keymap ~src_pos:0 ~dst:header ~dst_pos:16 ~len:16;
+  Bytes.From_string.blit
+    ~src:title
+    ~src_pos:0
+    ~dst:header
+    ~dst_pos:32
+    ~len:(String.length title);
+  Bytes.to_string header ^ program
+;;
+
+(** The DMA writes, as addresses and data, that load a container into the
+    mailbox: the container first, then the generation that tells the emulator
+    it has arrived. Each load needs a generation different from the last. *)
+let load_messages ~generation container =
+  if String.length container > mailbox_size - 4
+  then raise_s [%message "ROM container too large for the mailbox"];
+  let generation =
+    String.init 4 ~f:(fun index ->
+      Char.of_int_exn ((generation lsr (8 * index)) land 0xFF))
+  in
+  let container_messages =
+    String.to_list container
+    |> List.chunks_of ~length:chunk_size
+    |> List.mapi ~f:(fun index chunk ->
+      mailbox_address + 4 + (index * chunk_size), String.of_char_list chunk)
+  in
+  container_messages @ [ mailbox_address, generation ]
+;;
+
+(** [load_messages] as DMA packets ready to send over the UART *)
+let load_packets ~generation container =
+  load_messages ~generation container
+  |> List.map ~f:(fun (address, data) -> Opcode_helper.dma_packet ~address data)
+;;
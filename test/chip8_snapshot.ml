@@ -0,0 +1,90 @@
+(* Host side of chip-8-rust snapshots (see
+   test-programs/test-rust-programs/chip8-core/src/snapshot.rs for the image
+   format and chip-8-rust/src/snapshot.rs for how it is sent). When a machine
+   faults the emulator sends a "SNAPSHOT" packet followed by the image in raw
+   DMA packets, and it restores an image written to [address] when it is
+   reset. *)
+open! Core
+
+let marker = "SNAPSHOT"
+let magic = "C8SS"
+let version = 2
+let address = 0xA000
+let max_size = 0x2000
+
+(* The fields before the stack, ending with the number of stack bytes *)
+let fixed_size = 61
+let screen_size = 128 * 64 / 8
+
+(* DMA packets are sent in chunks of at most this many bytes *)
+let chunk_size = 1024
+let u16 data ~pos = Char.to_int data.[pos] lor (Char.to_int data.[pos + 1] lsl 8)
+let u32 data ~pos = u16 data ~pos lor (u16 data ~pos:(pos + 2) lsl 16)
+
+(** The size of the image that [prefix] starts, once enough of it has arrived
+    to tell. *)
+let size prefix =
+  if String.length prefix < fixed_size
+  then None
+  else (
+    let memory = fixed_size + u16 prefix ~pos:(fixed_size - 2) in
+    if String.length prefix < memory + 4
+    then None
+    else Some (memory + 4 + u32 prefix ~pos:memory + screen_size))
+;;
+
+module Capture = struct
+  type t =
+    | Waiting
+    | Receiving of string
+    | Complete of string
+  [@@deriving sexp_of]
+
+  (** Add a DMA packet sent by the device. A marker starts a new image, and
+      the packets after it are the image until it is complete. Packets outside
+      of an image are ignored. *)
+  let add t packet =
+    if String.equal packet marker
+    then Receiving ""
+    else (
+      match t with
+      | Waiting | Complete _ -> t
+      | Receiving image ->
+        let image = image ^ packet in
+        (match size image with
+         | Some size when String.length image >= size ->
+           Complete (String.prefix image size)
+         | _ -> Receiving image))
+  ;;
+end
+
+(** The DMA writes, as addresses and data, that put [image] where the emulator
+    restores it from. The emulator only looks for it when it starts, so the
+    design has to be reset after the writes. *)
+let restore_messages image =
+  if not (String.is_prefix image ~prefix:magic)
+  then raise_s [%message "Not a snapshot image"];
+  if Char.to_int image.[String.length magic] <> version
+  then
+    raise_s
+      [%message
+        "Unsupported snapshot version"
+          ~image_version:(Char.to_int image.[String.length magic] : int)
+          (version : int)];
+  if String.length image > max_size
+  then
+    raise_s
+      [%message "Snapshot too large" ~size:(String.length image : int) (max_size : int)];
+  String.to_list image
+  |> List.chunks_of ~length:chunk_size
+  |> List.mapi ~f:(fun index chunk ->
+    address + (index * chunk_size), String.of_char_list chunk)
+;;
+
+(** [restore_messages] as DMA packets ready to send over the UART, followed by
+    the clear signal that resets the design *)
+let restore_packets image =
+  List.map (restore_messages image) ~f:(fun (address, data) ->
+    Opcode_helper.dma_packet ~address data)
+  @ [ Opcode_helper.clear_packet ]
+;;
@@ -0,0 +1,37 @@
+(* Host side of the chip-8-rust sound events (see
+   test-programs/test-rust-programs/chip-8-rust/src/sound.rs). The emulator
+   sends an event over DMA each time the buzzer starts or stops. *)
+open! Core
+
+let magic = "SND"
+let default_pitch = 64
+
+module Event = struct
+  type t =
+    { on : bool
+    ; time_ns : int
+    ; pitch : int
+    }
+  [@@deriving sexp_of]
+
+  (** Parse the payload of a DMA packet sent by the device. Packets that are
+      not sound events return [None]. *)
+  let of_packet payload =
+    if String.length payload <> 13 || not (String.is_prefix payload ~prefix:magic)
+    then None
+    else (
+      let time_ns =
+        List.init 8 ~f:(fun index -> Char.to_int payload.[4 + index] lsl (8 * index))
+        |> List.fold ~init:0 ~f:( lor )
+      in
+      Some
+        { on = Char.equal payload.[3] '\001'
+        ; time_ns
+        ; pitch = Char.to_int payload.[12]
+        })
+  ;;
+end
+
+(** The XO-CHIP audio pattern playback rate for a pitch register value, in
+    samples per second. The default pitch plays at 4000Hz. *)
+let playback_rate pitch = 4000. *. (2. ** (Float.of_int (pitch - default_pitch) /. 48.))